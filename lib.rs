@@ -1,16 +1,210 @@
 #![cfg_attr(not(feature = "std"), no_std, no_main)]
 
+use ink::prelude::{string::String, vec::Vec};
+use ink::primitives::AccountId;
+use crate::transmitter::{Content, Error, Message, MessageType, Username};
+
+type Balance = <ink::env::DefaultEnvironment as ink::env::Environment>::Balance;
+
+/// Token identifier of the PSP34 standard. Names are identified by their bytes ('Id::Bytes').
+#[derive(Clone,Debug,PartialEq,Eq, scale::Decode, scale::Encode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub enum Id {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    Bytes(Vec<u8>),
+}
+
+/// Errors of the PSP34 standard.
+#[derive(Debug,PartialEq,Eq, scale::Decode, scale::Encode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub enum PSP34Error {
+    Custom(String),
+    SelfApprove,
+    NotApproved,
+    TokenExists,
+    TokenNotExists,
+    SafeTransferCheckFailed(String),
+}
+
+/// Errors of the PSP22 (fungible token) standard, returned by the token accepted for payments (see 'co_set_payment_token').
+#[derive(Debug,PartialEq,Eq, scale::Decode, scale::Encode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub enum PSP22Error {
+    Custom(String),
+    InsufficientBalance,
+    InsufficientAllowance,
+    ZeroRecipientAddress,
+    ZeroSenderAddress,
+    SafeTransferCheckFailed(String),
+}
+
+/// The PSP34 (non-fungible token) standard, so names can be held and traded with generic NFT wallets and marketplaces.
+#[ink::trait_definition]
+pub trait PSP34 {
+
+    /// Identifier of the collection.
+    #[ink(message)]
+    fn collection_id(&self) -> Id;
+
+    /// Amount of tokens held by an account.
+    #[ink(message)]
+    fn balance_of(&self, owner: AccountId) -> u32;
+
+    /// Account holding a token, if it exists.
+    #[ink(message)]
+    fn owner_of(&self, id: Id) -> Option<AccountId>;
+
+    /// Tells you whether an operator may transfer a token of the owner, or all of them if no token is specified.
+    #[ink(message)]
+    fn allowance(&self, owner: AccountId, operator: AccountId, id: Option<Id>) -> bool;
+
+    /// Allows or forbids an operator to transfer one of your tokens, or all of them if no token is specified.
+    #[ink(message)]
+    fn approve(&mut self, operator: AccountId, id: Option<Id>, approved: bool) -> Result<(), PSP34Error>;
+
+    /// Transfers a token to another account.
+    #[ink(message)]
+    fn transfer(&mut self, to: AccountId, id: Id, data: Vec<u8>) -> Result<(), PSP34Error>;
+
+    /// Amount of tokens in existence.
+    #[ink(message)]
+    fn total_supply(&self) -> u128;
+
+}
+
+/// The main messages of the transmitter, so other contracts can call it through a typed interface.
+/// The selectors are the ones these messages had before they were gathered here, so existing callers keep working.
+///
+/// A contract holding a name can send messages from it like this:
+///
+/// ```ignore
+/// use transmitter::{transmitter::MessageType, TransmitterApi};
+///
+/// let mut transmitter: ink::contract_ref!(TransmitterApi) = transmitter_account.into();
+///
+/// let hash = transmitter.send_message_from_contract("my_bot".into(), "Bob".into(), MessageType::Text, b"hi".to_vec())?;
+/// ```
+#[ink::trait_definition]
+pub trait TransmitterApi {
+
+    /// Tells you the fee for registering a username.
+    #[ink(message, selector = 0x54bceafd)]
+    fn check_fee(&self) -> Balance;
+
+    /// Attempts to register a new name connected to your account id.
+    /// The correct registration fee must be paid (use 'get_registration_fee').
+    /// If the payment does not equal the fee, the remainder is stored in your account's balance.
+    /// Names whose grace period has passed without renewal can be registered again.
+    /// Accounts exempted by the contract owner don't pay any fee.
+    /// Fails if the contract already holds as many names as the owner allows.
+    /// Names used by the contract as senders (like the system name) cannot be registered.
+    /// Names must be within the length limits set by the contract owner (use 'check_name_length'), be made of
+    /// letters, digits, spaces, '_', '-' and '.', and not start or end with a space.
+    #[ink(message, payable, selector = 0x87df127a)]
+    fn register_username(&mut self, name: String) -> Result<(), Error>;
+
+    /// Tells you the account holding the specified name. Names whose grace period has passed
+    /// without renewal don't resolve.
+    #[ink(message, selector = 0x9e30d65d)]
+    fn resolve(&self, username: Username) -> Result<AccountId, Error>;
+
+    /// Attempts to send a message to another user using one of your names.
+    /// The name from which you wish the message to be sent must be specified.
+    /// Json messages must contain well-formed JSON.
+    /// The message fee (use 'check_fees') and, if the recipient charges a fee for receiving messages
+    /// (use 'check_inbound_fee'), that fee must be paid; any excess is stored in your account's balance.
+    #[ink(message, payable, selector = 0xafdc403f)]
+    fn send_message(&mut self, from: Username, to: Username, mtype: MessageType, content: Content) -> Result<(), Error>;
+
+    /// Same as 'send_message', for contracts: the name must be held by the account of the calling contract.
+    /// Returns the hash of the message, so the contract can refer to it later. With 'build_call', the selector
+    /// is the one of "TransmitterApi::send_message_from_contract".
+    #[ink(message, payable)]
+    fn send_message_from_contract(&mut self, from: Username, to: Username, mtype: MessageType, content: Content) -> Result<[u8;32], Error>;
+
+    /// Attempts to make all the messages that were sent to a specific name of yours available.
+    #[ink(message, payable, selector = 0xc1eed7e9)]
+    fn get_all_messages(&self, belonging_to: Username) -> Result<Vec<Message>, Error>;
+
+    /// Attempts to find and delete the specified message. The account name and message hash must be specified.
+    #[ink(message, selector = 0x4cc20ce9)]
+    fn delete_message(&mut self, belonging_to: Username, hash: [u8;32]) -> Result<(), Error>;
+
+}
+
 #[ink::contract]
-mod transmitter {
+pub mod transmitter {
 
+    use crate::{Id, PSP22Error, PSP34Error, TransmitterApi};
     use ink::storage::{Mapping, Lazy, traits::ManualKey};
-    use ink::prelude::{string::String, vec::Vec};
+    use ink::prelude::{format, string::String, vec::Vec};
     use ink::env::hash::Sha2x256;
+    use ink::env::call::{build_call, ExecutionInput, Selector};
+    use ink::env::DefaultEnvironment;
 
     pub type Username = String;
     pub type Content = Vec<u8>;
+    pub type MessageCounts = Vec<(Username, u32)>;
+    pub type NameHistory = Vec<(AccountId, Timestamp)>;
+    pub type IdempotencyKey = (Username, Username, [u8;16]);
+    pub type SeenMessage = ([u8;32], Timestamp);
+    pub type RecentKey = (Username, [u8;16], Timestamp);
+    pub type InboxSlot = (Username, u64);
+    pub type InboxEntry = (Username, [u8;32]);
+    pub type OperatorApproval = (AccountId, AccountId);
+    pub type ChannelSeat = (String, AccountId);
+    pub type ChannelSlot = (String, u64);
+    pub type Reactions = Vec<(Username, String)>;
+    pub type SendDelegation = (Username, AccountId);
+    pub type DelegationGrant = (AccountId, Timestamp);
+    pub type BalanceAllowance = (AccountId, AccountId);
+    pub type SenderKey = (Username, u32, Username);
+    pub type SenderSlot = (Username, u32, Username, u64);
+
+    /// Name used as the sender of the notes you send to yourself (see 'note_to_self').
+    const NOTE_TO_SELF_SENDER: &str = "self";
+
+    /// Name used as the sender of messages sent by the contract itself.
+    const SYSTEM_NAME: &str = "system";
+
+    /// Maximum amount of previous holders remembered for each name.
+    const MAX_NAME_HISTORY: usize = 16;
+
+    /// How long, in milliseconds, a message sent with an idempotency key is remembered (see 'SendOptions').
+    const IDEMPOTENCY_WINDOW: Timestamp = 3_600_000;
+
+    /// Longest time, in milliseconds, a message can be scheduled ahead of when it is sent (see 'schedule_message').
+    const MAX_SCHEDULE_DELAY: Timestamp = 30 * 24 * 3_600_000;
+
+    /// Maximum amount of idempotency keys remembered for each recipient. Past it, the oldest ones are forgotten early.
+    const MAX_RECENT_KEYS: usize = 64;
+
+    /// Maximum length, in bytes, of the metadata of a name (see 'set_metadata').
+    const MAX_METADATA_LEN: usize = 256;
+
+    /// Maximum amount of senders each name can block (see 'block_sender').
+    const MAX_BLOCKED_SENDERS: usize = 64;
+
+    /// Maximum amount of senders each name can approve (see 'approve_sender').
+    const MAX_APPROVED_SENDERS: usize = 64;
+
+    /// Maximum length, in bytes, of the location of an attachment (see 'MessageType::Attachment').
+    const MAX_CONTENT_URI_LEN: usize = 256;
+
+    /// Maximum length, in bytes, of a reaction to a message (see 'react_to_message').
+    const MAX_REACTION_LEN: usize = 16;
+
+    /// Maximum amount of reactions each message can have.
+    const MAX_REACTIONS: usize = 32;
+
+    /// Time the backup account of an account must wait between starting and completing its recovery (see 'recover_account').
+    const RECOVERY_DELAY: Timestamp = 7 * 24 * 3_600_000;
 
-    #[derive(PartialEq, scale::Decode, scale::Encode)]
+    #[derive(Clone,Debug,PartialEq, scale::Decode, scale::Encode)]
     #[cfg_attr(
         feature = "std",
         derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
@@ -19,10 +213,39 @@ mod transmitter {
         Text,
         Email { subject: String },
         ReplyTo { hash: [u8;32] },
-        Custom(String),
+        Custom { tag: String, schema_version: u16 },
+        Json,
+        Encrypted { scheme: String },
+        Forwarded { original_hash: [u8;32], original_from: Username },
+        /// Content stored off-chain (on IPFS for instance), with the hash of that content so it can be checked.
+        Attachment { subject: String, content_uri: String, content_hash: [u8;32], mime: String },
+        /// A request numbered by the contract (see 'send_request').
+        Request { id: u64 },
+        /// The answer to a request (see 'send_response').
+        Response { request_id: u64 },
     }
 
-    #[derive(PartialEq, scale::Decode, scale::Encode)]
+    impl MessageType {
+
+        /// Position of the variant in the enum, used as its bit in the allowed types mask.
+        pub fn index(&self) -> u8 {
+            match self {
+                MessageType::Text => 0,
+                MessageType::Email { .. } => 1,
+                MessageType::ReplyTo { .. } => 2,
+                MessageType::Custom { .. } => 3,
+                MessageType::Json => 4,
+                MessageType::Encrypted { .. } => 5,
+                MessageType::Forwarded { .. } => 6,
+                MessageType::Attachment { .. } => 7,
+                MessageType::Request { .. } => 8,
+                MessageType::Response { .. } => 9,
+            }
+        }
+
+    }
+
+    #[derive(Clone,Debug,PartialEq, scale::Decode, scale::Encode)]
     #[cfg_attr(
         feature = "std",
         derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
@@ -33,9 +256,183 @@ mod transmitter {
         content: Content,
         hash: [u8;32],
         timestamp: Timestamp,
+        signature: Option<Signature>,
+        requires_ack: bool,
+        acknowledged: bool,
+        compressed: bool,
+        seq: u64,
+        read_at: Option<Timestamp>,
+        block: BlockNumber,
+        expires_at: Option<Timestamp>,
+        deliver_at: Option<Timestamp>,
+        edited_from: Option<[u8;32]>,
+    }
+
+    /// An action of the contract owner that needs the approval of the co-owners (see 'co_set_owners').
+    #[derive(Clone,Debug,PartialEq, scale::Decode, scale::Encode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum OwnerAction {
+        SetCode { code_hash: ink::primitives::Hash },
+        SetFee { new_fee: Balance },
+        SetFees { registration: Option<Balance>, message: Option<Balance>, renewal: Option<Balance>, sale_percent: Option<u8> },
+        SetSaleFee { bps: u16 },
+        SetRoyalty { bps: u16 },
+        WithdrawAmount { balance: Balance },
+        WithdrawAllBalance,
+        Drain { to: AccountId },
+        ProposeOwner { new_owner: AccountId },
+        SetOwners { co_owners: Vec<AccountId>, threshold: u32 },
+        SetBulkDiscount { threshold: u32, percent: u8 },
+        SetFeeExempt { who: AccountId, exempt: bool },
+        SetPaymentToken { token: Option<AccountId> },
+        SweepDust { accounts: Vec<AccountId>, threshold: Balance },
+    }
+
+    /// What an account may do besides using its names (see 'co_set_role').
+    #[derive(Clone,Copy,Debug,PartialEq, scale::Decode, scale::Encode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub enum Role {
+        /// Everything, including changing the fees and the code. Only the contract owner has this role.
+        Owner,
+        /// Removing messages and freezing names (see 'mod_remove_message' and 'mod_freeze_username').
+        Moderator,
+    }
+
+    /// Who can send messages to a name (see 'set_inbox_policy').
+    #[derive(Clone,Copy,Debug,PartialEq, scale::Decode, scale::Encode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub enum InboxPolicy {
+        /// Anyone who is not blocked.
+        Open,
+        /// Only the approved senders (see 'approve_sender').
+        AllowlistOnly,
+    }
+
+    /// Optional settings for sending a message (see 'send_message_with_options').
+    #[derive(Clone,Debug,Default,PartialEq,scale::Decode, scale::Encode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct SendOptions {
+        /// The recipient is asked to acknowledge the message (see 'acknowledge').
+        pub requires_ack: bool,
+        /// The content was compressed off-chain and must be decompressed by the recipient.
+        /// The contract stores it as is.
+        pub compressed: bool,
+        /// Sending again a message with the same key, from the same name to the same name, within
+        /// 'IDEMPOTENCY_WINDOW' doesn't store a second copy and gives back the hash of the first one.
+        pub idempotency_key: Option<[u8;16]>,
+        /// The message is dropped from the inbox once this time is reached (see 'prune_expired').
+        pub expires_at: Option<Timestamp>,
+        /// The message is only shown to the recipient once this time is reached (see 'schedule_message').
+        pub deliver_at: Option<Timestamp>,
+    }
+
+    /// A 64 byte signature made off-chain by the sender of a message.
+    #[derive(Clone,Copy,Debug,PartialEq,scale::Decode, scale::Encode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct Signature(pub [u8;64]);
+
+    // The storage layout is only derived for arrays of up to 32 elements.
+    #[cfg(feature = "std")]
+    impl ink::storage::traits::StorageLayout for Signature {
+        fn layout(key: &ink::primitives::Key) -> ink::metadata::layout::Layout {
+            ink::metadata::layout::Layout::Leaf(
+                ink::metadata::layout::LeafLayout::from_key::<Self>(ink::metadata::layout::LayoutKey::from(key))
+            )
+        }
+    }
+
+    /// A name offered to anyone at a fixed price (see 'list_username_for_sale').
+    #[derive(Clone,Debug,PartialEq, scale::Decode, scale::Encode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Listing {
+        username: Username,
+        seller: AccountId,
+        price: Balance,
+    }
+
+    /// A group that its members can post messages to (see 'create_channel').
+    #[derive(Clone,Debug,PartialEq, scale::Decode, scale::Encode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Channel {
+        creator: AccountId,
+        member_count: u32,
+        post_count: u64,
+    }
+
+    /// A message posted to a channel.
+    #[derive(Clone,Debug,PartialEq, scale::Decode, scale::Encode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct ChannelPost {
+        from: Username,
+        content: Content,
+        timestamp: Timestamp,
+    }
+
+    /// A request sent between two names, and the position of its response in the inbox of the requester, once sent.
+    #[derive(Clone,Debug,PartialEq, scale::Decode, scale::Encode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct RequestInfo {
+        from: Username,
+        to: Username,
+        response: Option<u64>,
+    }
+
+    /// The account that can recover an account whose key was lost, and when it started doing so (see 'recover_account').
+    #[derive(Clone,Debug,PartialEq, scale::Decode, scale::Encode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Recovery {
+        backup: AccountId,
+        started_at: Option<Timestamp>,
+    }
+
+    /// The last counter-offer made on a sale offer, by its buyer or by the seller (see 'counter_offer').
+    #[derive(Clone,Debug,PartialEq, scale::Decode, scale::Encode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Negotiation {
+        buyer: AccountId,
+        price: Balance,
+        by_buyer: bool,
+    }
+
+    /// A name auctioned to the highest bidder (see 'start_auction').
+    #[derive(Clone,Debug,PartialEq, scale::Decode, scale::Encode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Auction {
+        seller: AccountId,
+        reserve_price: Balance,
+        ends_at: Timestamp,
+        highest_bidder: Option<AccountId>,
+        highest_bid: Balance,
     }
 
-    #[derive(PartialEq, scale::Decode, scale::Encode)]
+    #[derive(Clone,Debug,PartialEq, scale::Decode, scale::Encode)]
     #[cfg_attr(
         feature = "std",
         derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
@@ -44,6 +441,8 @@ mod transmitter {
         username: Username,
         to: AccountId,
         price: Balance,
+        expires_at: Timestamp,
+        deposited: bool,
     }
 
     #[derive(Debug,PartialEq,scale::Decode, scale::Encode)]
@@ -76,6 +475,72 @@ mod transmitter {
         UsernameNotInSale,
         NoSalesForYou,
         UnexpectedInternalError,
+        SaleExpired,
+        InvalidJson,
+        InvalidPercentage,
+        MessageTypeNotAllowed,
+        NameExpired,
+        AcknowledgementNotRequired,
+        ReplyTooDeep,
+        TransferFailed {
+            to: AccountId,
+            amount: Balance,
+        },
+        PriceOverflow,
+        PriceTooHigh,
+        InvalidRange,
+        ZeroAccount,
+        RegistryFull,
+        BalanceOverflow,
+        NameReserved,
+        SaleAlreadyDeposited,
+        SaleNotDeposited,
+        MetadataTooLong,
+        TransferCooldown,
+        AuctionEnded,
+        AuctionNotEnded,
+        BidTooLow,
+        PaymentTokenNotSet,
+        TokenTransferFailed,
+        ChannelExists,
+        ChannelNonexistent,
+        NotChannelMember,
+        AlreadyChannelMember,
+        BannedFromChannel,
+        NotChannelCreator,
+        SenderBlocked,
+        BlocklistFull,
+        SenderNotApproved,
+        AllowlistFull,
+        MessageAlreadyDelivered,
+        ReactionTooLong,
+        TooManyReactions,
+        InvalidAttachment,
+        RequestNonexistent,
+        AlreadyResponded,
+        NamesRefused(Vec<(Username, Error)>),
+        NotRecoveryAccount,
+        InvalidRecoveryAccount,
+        RecoveryNotReady,
+        NotPendingOwner,
+        NotEnoughApprovals,
+        InvalidThreshold,
+        NotModerator,
+        InvalidRole,
+        UsernameFrozen(Username),
+        ContractPaused,
+        InsufficientAllowance,
+        NoCounterOffer,
+        DeliveryTooLate,
+    }
+
+    /// Outcome of an operation applied to several entries: the entries it succeeded for,
+    /// and the ones it failed for along with the reason.
+    #[derive(Debug,PartialEq,scale::Decode, scale::Encode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct BatchResult<T> {
+        pub succeeded: Vec<T>,
+        pub failed: Vec<(T, Error)>,
     }
 
     #[derive(Clone,Debug,PartialEq,scale::Decode, scale::Encode)]
@@ -95,8 +560,39 @@ mod transmitter {
     )]
     pub struct UsernameInfo {
         account_id: AccountId,
-        messages: Option<Vec<Message>>,
+        first_seq: u64,
+        message_count: u32,
+        fee_payment_time: Timestamp,
+        inbound_fee: Balance,
+        next_seq: u64,
+        metadata: Option<String>,
+        last_transfer: Option<Timestamp>,
+        blocked: Vec<Username>,
+        inbox_policy: InboxPolicy,
+        approved_senders: Vec<Username>,
+        unread_count: u32,
+        encryption_key: Option<[u8;32]>,
+        sent_count: u64,
+    }
+
+    /// Status of the inbox of a name, without the messages themselves (see 'get_inbox_summary').
+    #[derive(Debug,PartialEq,scale::Decode, scale::Encode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct InboxSummary {
+        total: u32,
+        unread: u32,
+        oldest_timestamp: Option<Timestamp>,
+        newest_timestamp: Option<Timestamp>,
+    }
+
+    /// Public information about a name (see 'username_profile').
+    #[derive(Debug,PartialEq,scale::Decode, scale::Encode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct UsernameProfile {
+        account_id: AccountId,
         fee_payment_time: Timestamp,
+        inbound_fee: Balance,
+        metadata: Option<String>,
     }
 
     #[derive(Debug,PartialEq,scale::Decode, scale::Encode)]
@@ -107,1159 +603,10665 @@ mod transmitter {
     pub struct OwnerInfo {
         account_id: AccountId,
         balance: Balance,
+        co_owners: Vec<AccountId>,
+        threshold: u32,
     }
 
-    #[ink(storage)]
-    pub struct Transmitter {
-        users: Mapping<AccountId,UserInfo, ManualKey<1>>,
-        usernames: Mapping<Username,UsernameInfo, ManualKey<2>>,
-        sale_offers: Lazy<Option<Vec<Sale>>, ManualKey<3>>,
-        owner: OwnerInfo,
-        registration_fee: Balance,
-        contract_paused: bool,
+    /// Emitted when the inbox of a username is cleared, by its holder or by the contract owner.
+    #[ink(event)]
+    pub struct InboxPurged {
+        #[ink(topic)]
+        username: Username,
+        count: u32,
     }
 
-    impl Transmitter {
+    /// Emitted when the recipient of a message acknowledges it.
+    #[ink(event)]
+    pub struct MessageAcknowledged {
+        #[ink(topic)]
+        hash: [u8;32],
+        by: Username,
+    }
 
-        /// Constructor.
-        #[ink(constructor)]
-        pub fn new() -> Transmitter {
-            Transmitter {
-                usernames: Mapping::new(),
-                users: Mapping::new(),
-                sale_offers: Lazy::new(),
-                owner: OwnerInfo { account_id: Self::env().caller(), balance: 0 },
-                registration_fee: 1,
-                contract_paused: false,
-            }
-        }
+    /// Emitted when the contract owner moves the small balance of an account to the owner's balance.
+    #[ink(event)]
+    pub struct DustSwept {
+        #[ink(topic)]
+        account: AccountId,
+        amount: Balance,
+    }
 
-        /// Tells you the fee for registering a username.
-        #[ink(message)]
-        pub fn check_fee(&self) -> Balance {
-            self.registration_fee
-        }
+    /// Emitted when the contract owner removes a page of the pending sale offers, starting at 'offset' (see 'co_purge_all_sales').
+    #[ink(event)]
+    pub struct SalesPurged {
+        offset: u32,
+        count: u32,
+    }
 
-        /// Attempts to register a new name connected to your account id.
-        /// The correct registration fee must be paid (use 'get_registration_fee').
-        /// If the payment does not equal the fee, the remainder is stored in your account's balance.
-        #[ink(message,payable)]
-        pub fn register_username(&mut self, name: String) -> Result<(),Error> {
+    /// Emitted when the buyer a name was offered to refuses the offer.
+    #[ink(event)]
+    pub struct SaleRefused {
+        #[ink(topic)]
+        username: Username,
+        by: AccountId,
+    }
 
-            let transferred = self.env().transferred_value();
-            let timestamp = self.env().block_timestamp();
+    /// Emitted when the contract owner moves all the contract's funds to another account.
+    #[ink(event)]
+    pub struct ContractDrained {
+        #[ink(topic)]
+        to: AccountId,
+        amount: Balance,
+    }
 
-            if let Some(_) = self.usernames.get(&name) {
+    /// Emitted when a name is registered to an account.
+    #[ink(event)]
+    pub struct UsernameRegistered {
+        #[ink(topic)]
+        username: Username,
+        account: AccountId,
+    }
 
-                return Err(Error::NameTaken);
+    /// Emitted when the holder of a name renews it.
+    #[ink(event)]
+    pub struct UsernameRenewed {
+        #[ink(topic)]
+        username: Username,
+        fee_payment_time: Timestamp,
+    }
 
-            }
+    /// Emitted when the holder of a name changes its metadata or its fee for receiving messages.
+    #[ink(event)]
+    pub struct UsernameUpdated {
+        #[ink(topic)]
+        username: Username,
+    }
 
-            let mut user_balance: Balance = 0;
+    /// Emitted when a message is delivered. The recipient is given as the hash of its name (see 'name_hash').
+    #[ink(event)]
+    pub struct MessageSent {
+        #[ink(topic)]
+        to_hash: [u8;32],
+        #[ink(topic)]
+        message_hash: [u8;32],
+    }
 
-            if transferred > self.registration_fee {
+    /// Emitted when the recipient of a message marks it as read.
+    #[ink(event)]
+    pub struct MessageRead {
+        #[ink(topic)]
+        hash: [u8;32],
+        by: Username,
+    }
 
-                self.owner.balance += self.registration_fee;
+    /// Emitted when the sender of a message changes its content, which gives it a new hash.
+    #[ink(event)]
+    pub struct MessageEdited {
+        #[ink(topic)]
+        hash: [u8;32],
+        #[ink(topic)]
+        edited_from: [u8;32],
+    }
 
-                user_balance += transferred - self.registration_fee;
+    /// Emitted when the sender or the recipient of a message reacts to it. An empty reaction withdraws the previous one.
+    #[ink(event)]
+    pub struct Reaction {
+        #[ink(topic)]
+        hash: [u8;32],
+        by: Username,
+        reaction: String,
+    }
 
-            } else if transferred < self.registration_fee {
+    /// Emitted when the recipient of a message deletes it.
+    #[ink(event)]
+    pub struct MessageDeleted {
+        #[ink(topic)]
+        hash: [u8;32],
+        by: Username,
+    }
 
-                user_balance += transferred;
+    /// Emitted when the holder of a name offers it for sale.
+    #[ink(event)]
+    pub struct SaleOffered {
+        #[ink(topic)]
+        username: Username,
+        to: AccountId,
+        price: Balance,
+        expires_at: Timestamp,
+    }
 
-                let new_user_info = UserInfo { usernames: None, balance: user_balance };
+    /// Emitted when the buyer or the seller of a sale offer proposes another price (see 'counter_offer').
+    #[ink(event)]
+    pub struct CounterOffered {
+        #[ink(topic)]
+        username: Username,
+        by: AccountId,
+        price: Balance,
+    }
 
-                self.users.insert(&self.env().caller(), &new_user_info);
+    /// Emitted when the seller of a name turns down the counter-offer of the buyer.
+    #[ink(event)]
+    pub struct CounterOfferRejected {
+        #[ink(topic)]
+        username: Username,
+    }
 
-                return Err(Error::PaymentFailed {
-                    received: transferred,
-                    required: self.registration_fee,
-                    missing:  self.registration_fee - transferred
-                });
+    /// Emitted when the holder of a name offers it to anyone.
+    #[ink(event)]
+    pub struct UsernameListed {
+        #[ink(topic)]
+        username: Username,
+        price: Balance,
+    }
 
-            } else {
+    /// Emitted when a name changes hands, as required by the PSP34 standard.
+    #[ink(event)]
+    pub struct Transfer {
+        #[ink(topic)]
+        from: Option<AccountId>,
+        #[ink(topic)]
+        to: Option<AccountId>,
+        #[ink(topic)]
+        id: Id,
+    }
 
-                self.owner.balance += transferred;
+    /// Emitted when an operator is allowed or forbidden to transfer names, as required by the PSP34 standard.
+    #[ink(event)]
+    pub struct Approval {
+        #[ink(topic)]
+        owner: AccountId,
+        #[ink(topic)]
+        operator: AccountId,
+        #[ink(topic)]
+        id: Option<Id>,
+        approved: bool,
+    }
 
-            }
+    /// Emitted when a channel is created.
+    #[ink(event)]
+    pub struct ChannelCreated {
+        #[ink(topic)]
+        channel: String,
+        creator: AccountId,
+    }
 
-            if let Some(user_info) = self.users.get(&self.env().caller()) {
+    /// Emitted when an account joins or leaves a channel, or is removed from it.
+    #[ink(event)]
+    pub struct ChannelMembershipChanged {
+        #[ink(topic)]
+        channel: String,
+        #[ink(topic)]
+        account: AccountId,
+        member: bool,
+    }
 
-                let mut usernames = Vec::new();
+    /// Emitted when a message is posted to a channel, with its position in the channel.
+    #[ink(event)]
+    pub struct ChannelPosted {
+        #[ink(topic)]
+        channel: String,
+        from: Username,
+        index: u64,
+    }
 
-                if let Some(existing) = user_info.usernames {
-                    usernames = existing;
-                }
+    /// Emitted when an account chooses the name it is known by (see 'set_primary_username').
+    #[ink(event)]
+    pub struct PrimaryUsernameSet {
+        #[ink(topic)]
+        account: AccountId,
+        username: Username,
+    }
+
+    /// Emitted when an account chooses the account that can recover it (see 'set_recovery_account').
+    #[ink(event)]
+    pub struct RecoveryAccountSet {
+        #[ink(topic)]
+        account: AccountId,
+        backup: AccountId,
+    }
 
-                usernames.push(name.clone());
+    /// Emitted when the backup account of an account starts recovering it.
+    #[ink(event)]
+    pub struct RecoveryStarted {
+        #[ink(topic)]
+        account: AccountId,
+        backup: AccountId,
+        ready_at: Timestamp,
+    }
 
-                let balance = user_info.balance + user_balance;
+    /// Emitted when an account stops a recovery of itself that was under way.
+    #[ink(event)]
+    pub struct RecoveryCancelled {
+        #[ink(topic)]
+        account: AccountId,
+    }
 
-                let new_user_info = UserInfo {
-                    usernames: Some(usernames),
-                    balance,
-                };
+    /// Emitted when the names and balance of an account are moved to its backup account.
+    #[ink(event)]
+    pub struct AccountRecovered {
+        #[ink(topic)]
+        account: AccountId,
+        #[ink(topic)]
+        backup: AccountId,
+        usernames: Vec<Username>,
+        balance: Balance,
+    }
 
-                self.users.insert(&self.env().caller(), &new_user_info);
+    /// Emitted when the holder of a name puts it up for auction.
+    #[ink(event)]
+    pub struct AuctionStarted {
+        #[ink(topic)]
+        username: Username,
+        reserve_price: Balance,
+        ends_at: Timestamp,
+    }
 
+    /// Emitted when someone becomes the highest bidder of an auction.
+    #[ink(event)]
+    pub struct BidPlaced {
+        #[ink(topic)]
+        username: Username,
+        bidder: AccountId,
+        amount: Balance,
+    }
 
-                let new_username_info = UsernameInfo {
-                    account_id: self.env().caller(),
-                    messages: None,
-                    fee_payment_time: timestamp,
-                };
+    /// Emitted when the holder of a name withdraws its sale offer or listing, or when an auction ends without a sale.
+    #[ink(event)]
+    pub struct SaleCancelled {
+        #[ink(topic)]
+        username: Username,
+    }
+
+    /// Emitted when the buyer a name was offered to deposits its price, or takes the deposit back.
+    #[ink(event)]
+    pub struct SaleDepositChanged {
+        #[ink(topic)]
+        username: Username,
+        by: AccountId,
+        deposited: bool,
+    }
+
+    /// Emitted when a name is sold.
+    #[ink(event)]
+    pub struct SaleCompleted {
+        #[ink(topic)]
+        username: Username,
+        from: AccountId,
+        to: AccountId,
+        price: Balance,
+    }
+
+    /// Emitted when the first registrant of a name is paid a part of the price it was resold at (see 'co_set_royalty').
+    #[ink(event)]
+    pub struct RoyaltyPaid {
+        #[ink(topic)]
+        username: Username,
+        to: AccountId,
+        amount: Balance,
+    }
+
+    /// Emitted when an account tops up its balance (see 'deposit').
+    #[ink(event)]
+    pub struct BalanceDeposited {
+        #[ink(topic)]
+        account: AccountId,
+        amount: Balance,
+    }
+
+    /// Emitted when an account allows another one to spend from its balance (see 'approve_spending').
+    #[ink(event)]
+    pub struct SpendingApproved {
+        #[ink(topic)]
+        owner: AccountId,
+        #[ink(topic)]
+        spender: AccountId,
+        amount: Balance,
+    }
+
+    /// Emitted when funds are moved from the balance of an account to the balance of another.
+    #[ink(event)]
+    pub struct BalanceTransferred {
+        #[ink(topic)]
+        from: AccountId,
+        #[ink(topic)]
+        to: AccountId,
+        amount: Balance,
+    }
+
+    /// Emitted when funds are sent out of the contract to an account or to the contract owner.
+    #[ink(event)]
+    pub struct BalanceWithdrawn {
+        #[ink(topic)]
+        account: AccountId,
+        amount: Balance,
+    }
+
+    /// Emitted when an account is closed.
+    #[ink(event)]
+    pub struct AccountClosed {
+        #[ink(topic)]
+        account: AccountId,
+    }
+
+    /// Emitted when the contract owner proposes to transfer the ownership (see 'co_propose_owner').
+    #[ink(event)]
+    pub struct OwnershipProposed {
+        #[ink(topic)]
+        from: AccountId,
+        #[ink(topic)]
+        to: AccountId,
+    }
+
+    /// Emitted when the contract owner or a co-owner approves an action (see 'co_approve_action').
+    #[ink(event)]
+    pub struct ActionApproved {
+        #[ink(topic)]
+        action_hash: [u8;32],
+        by: AccountId,
+    }
+
+    /// Emitted when the contract owner gives a role to an account or takes it back (see 'co_set_role').
+    #[ink(event)]
+    pub struct RoleChanged {
+        #[ink(topic)]
+        account: AccountId,
+        role: Option<Role>,
+    }
+
+    /// Emitted when a moderator removes a message from an inbox.
+    #[ink(event)]
+    pub struct MessageRemoved {
+        #[ink(topic)]
+        hash: [u8;32],
+        from_inbox: Username,
+        by: AccountId,
+    }
+
+    /// Emitted when a moderator freezes or unfreezes a name.
+    #[ink(event)]
+    pub struct UsernameFreezeChanged {
+        #[ink(topic)]
+        username: Username,
+        frozen: bool,
+    }
+
+    /// Emitted when the contract changes owner.
+    #[ink(event)]
+    pub struct OwnershipTransferred {
+        #[ink(topic)]
+        from: AccountId,
+        #[ink(topic)]
+        to: AccountId,
+    }
+
+    /// Emitted when the code of the contract is replaced.
+    #[ink(event)]
+    pub struct CodeUpgraded {
+        code_hash: ink::primitives::Hash,
+    }
+
+    /// Emitted when the contract owner changes the fees, with the fees now in effect.
+    #[ink(event)]
+    pub struct FeesChanged {
+        registration: Balance,
+        message: Balance,
+        renewal: Balance,
+        sale_fee_bps: u16,
+    }
+
+    /// Emitted when the contract owner changes any other setting, named after the message used.
+    #[ink(event)]
+    pub struct SettingChanged {
+        #[ink(topic)]
+        setting: String,
+    }
+
+    #[ink(storage)]
+    pub struct Transmitter {
+        users: Mapping<AccountId,UserInfo, ManualKey<1>>,
+        usernames: Mapping<Username,UsernameInfo, ManualKey<2>>,
+        sales: Mapping<Username,Sale, ManualKey<37>>,
+        sales_to_buyer: Mapping<AccountId,Vec<Username>, ManualKey<38>>,
+        listings: Lazy<Vec<Listing>, ManualKey<16>>,
+        auctions: Mapping<Username,Auction, ManualKey<17>>,
+        token_approvals: Mapping<Username,AccountId, ManualKey<18>>,
+        operator_approvals: Mapping<OperatorApproval,(), ManualKey<19>>,
+        channels: Mapping<String,Channel, ManualKey<20>>,
+        channel_members: Mapping<ChannelSeat,bool, ManualKey<21>>,
+        channel_posts: Mapping<ChannelSlot,ChannelPost, ManualKey<22>>,
+        message_location: Mapping<[u8;32],InboxSlot, ManualKey<23>>,
+        first_reply: Mapping<[u8;32],[u8;32], ManualKey<24>>,
+        outbox: Mapping<InboxSlot,InboxSlot, ManualKey<25>>,
+        reactions: Mapping<[u8;32],Reactions, ManualKey<26>>,
+        requests: Mapping<u64,RequestInfo, ManualKey<27>>,
+        primary_names: Mapping<AccountId,Username, ManualKey<28>>,
+        delegates: Mapping<SendDelegation,DelegationGrant, ManualKey<29>>,
+        recoveries: Mapping<AccountId,Recovery, ManualKey<30>>,
+        action_approvals: Mapping<[u8;32],Vec<AccountId>, ManualKey<31>>,
+        roles: Mapping<AccountId,Role, ManualKey<32>>,
+        frozen_names: Mapping<Username,bool, ManualKey<33>>,
+        first_registrants: Mapping<Username,AccountId, ManualKey<34>>,
+        balance_allowances: Mapping<BalanceAllowance,Balance, ManualKey<35>>,
+        negotiations: Mapping<Username,Negotiation, ManualKey<36>>,
+        sender_index: Mapping<SenderSlot,u64, ManualKey<39>>,
+        sender_counts: Mapping<SenderKey,u64, ManualKey<40>>,
+        inbox_generations: Mapping<Username,u32, ManualKey<41>>,
+        scheduled: Mapping<Username,Vec<u64>, ManualKey<42>>,
+        sale_names: Mapping<u32,Username, ManualKey<43>>,
+        sale_name_index: Mapping<Username,u32, ManualKey<44>>,
+        sale_names_len: u32,
+        recent_keys: Mapping<Username,Vec<RecentKey>, ManualKey<45>>,
+        next_request_id: u64,
+        name_history: Mapping<Username,NameHistory, ManualKey<9>>,
+        fee_exempt: Mapping<AccountId,bool, ManualKey<10>>,
+        seen_keys: Mapping<IdempotencyKey,SeenMessage, ManualKey<13>>,
+        inbox: Mapping<InboxSlot,Message, ManualKey<14>>,
+        inbox_index: Mapping<InboxEntry,u64, ManualKey<15>>,
+        registry: Mapping<u32,Username, ManualKey<11>>,
+        registry_index: Mapping<Username,u32, ManualKey<12>>,
+        registry_len: u32,
+        registry_cap: u32,
+        owner: OwnerInfo,
+        pending_owner: Option<AccountId>,
+        registration_fee: Balance,
+        renewal_fee: Balance,
+        message_fee: Balance,
+        sale_fee_bps: u16,
+        royalty_bps: u16,
+        contract_paused: bool,
+        bulk_discount_threshold: u32,
+        bulk_discount_percent: u8,
+        total_user_balance: Balance,
+        allowed_message_types: u32,
+        renewal_period: Timestamp,
+        grace_period: Timestamp,
+        expiry_enabled_at: Timestamp,
+        max_reply_depth: u32,
+        max_sale_price: Option<Balance>,
+        transfer_cooldown: Timestamp,
+        min_name_len: u32,
+        max_name_len: u32,
+        payment_token: Option<AccountId>,
+    }
+
+    impl Default for Transmitter {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl Transmitter {
+
+        /// Constructor.
+        #[ink(constructor)]
+        pub fn new() -> Transmitter {
+            Self::new_with_config(1, None)
+        }
+
+        /// Constructor setting the registration fee and, optionally, an owner other than the deployer.
+        #[ink(constructor)]
+        pub fn new_with_config(initial_fee: Balance, owner: Option<AccountId>) -> Transmitter {
+            Transmitter {
+                usernames: Mapping::new(),
+                users: Mapping::new(),
+                sales: Mapping::new(),
+                sales_to_buyer: Mapping::new(),
+                listings: Lazy::new(),
+                auctions: Mapping::new(),
+                token_approvals: Mapping::new(),
+                operator_approvals: Mapping::new(),
+                channels: Mapping::new(),
+                channel_members: Mapping::new(),
+                channel_posts: Mapping::new(),
+                message_location: Mapping::new(),
+                first_reply: Mapping::new(),
+                outbox: Mapping::new(),
+                reactions: Mapping::new(),
+                requests: Mapping::new(),
+                primary_names: Mapping::new(),
+                delegates: Mapping::new(),
+                recoveries: Mapping::new(),
+                action_approvals: Mapping::new(),
+                roles: Mapping::new(),
+                frozen_names: Mapping::new(),
+                first_registrants: Mapping::new(),
+                balance_allowances: Mapping::new(),
+                negotiations: Mapping::new(),
+                sender_index: Mapping::new(),
+                sender_counts: Mapping::new(),
+                inbox_generations: Mapping::new(),
+                scheduled: Mapping::new(),
+                sale_names: Mapping::new(),
+                sale_name_index: Mapping::new(),
+                sale_names_len: 0,
+                recent_keys: Mapping::new(),
+                next_request_id: 0,
+                name_history: Mapping::new(),
+                fee_exempt: Mapping::new(),
+                seen_keys: Mapping::new(),
+                inbox: Mapping::new(),
+                inbox_index: Mapping::new(),
+                registry: Mapping::new(),
+                registry_index: Mapping::new(),
+                registry_len: 0,
+                registry_cap: u32::MAX,
+                owner: OwnerInfo { account_id: owner.unwrap_or(Self::env().caller()), balance: 0, co_owners: Vec::new(), threshold: 1 },
+                pending_owner: None,
+                registration_fee: initial_fee,
+                renewal_fee: initial_fee,
+                message_fee: 0,
+                sale_fee_bps: 500,
+                royalty_bps: 0,
+                contract_paused: false,
+                bulk_discount_threshold: 5,
+                bulk_discount_percent: 10,
+                total_user_balance: 0,
+                allowed_message_types: u32::MAX,
+                renewal_period: 0,
+                grace_period: 0,
+                expiry_enabled_at: 0,
+                max_reply_depth: 0,
+                max_sale_price: None,
+                transfer_cooldown: 0,
+                min_name_len: 1,
+                max_name_len: 32,
+                payment_token: None,
+            }
+        }
+
+        /// Tells you every fee charged by the contract: for registering a name, for sending a message,
+        /// for renewing a name, and the percentage of the price kept from a sale, rounded down (see 'check_sale_fee').
+        #[ink(message)]
+        pub fn check_fees(&self) -> (Balance, Balance, Balance, u8) {
+            (self.registration_fee, self.message_fee, self.renewal_fee, (self.sale_fee_bps / 100) as u8)
+        }
+
+        /// Tells you the part of the price kept from a sale, in basis points (hundredths of a percent).
+        #[ink(message)]
+        pub fn check_sale_fee(&self) -> u16 {
+            self.sale_fee_bps
+        }
+
+        /// Registers a name, paying the fee with the token accepted by the contract (use 'get_payment_token')
+        /// instead of native value. The contract must be allowed to spend the fee from your token balance, which is sent
+        /// to the contract owner. Names follow the same rules as with 'register_username'.
+        #[ink(message)]
+        pub fn register_username_with_token(&mut self, name: String) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            let caller = self.env().caller();
+            let timestamp = self.env().block_timestamp();
+
+            if self.payment_token.is_none() {
+
+                return Err(Error::PaymentTokenNotSet);
+
+            }
+
+            if !self.name_valid(&name) {
+
+                return Err(Error::InvalidName);
+
+            }
+
+            if Self::name_reserved(&name) {
+
+                return Err(Error::NameReserved);
+
+            }
+
+            if self.name_taken(&name) {
+
+                return Err(Error::NameTaken);
+
+            }
+
+            if !self.registry_has_room(core::slice::from_ref(&name)) {
+
+                return Err(Error::RegistryFull);
+
+            }
+
+            let fee = if self.fee_exempt.get(caller).unwrap_or(false) { 0 } else { self.registration_fee };
+
+            self.pull_tokens(caller, self.owner.account_id, fee)?;
+
+            self.release_username(&name)?;
+
+            self.assign_username(caller, name, timestamp)?;
+
+            Ok(())
+
+        }
+
+        /// Attempts to register several names at once, all connected to your account id.
+        /// When enough names are registered together a discount is applied to the total fee
+        /// (use 'check_bulk_discount'). If any of the names cannot be registered, none are, and every refused name
+        /// is listed along with the reason ('NamesRefused').
+        /// If the payment exceeds the total fee, the remainder is stored in your account's balance.
+        #[ink(message,payable)]
+        pub fn register_usernames(&mut self, names: Vec<String>) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            let transferred = self.env().transferred_value();
+            let timestamp = self.env().block_timestamp();
+            let caller = self.env().caller();
+
+            if names.is_empty() {
+
+                return Err(Error::NoNames);
+
+            }
+
+            let mut refused = Vec::<(Username, Error)>::new();
+
+            for (pos, name) in names.iter().enumerate() {
+
+                if !self.name_valid(name) {
+
+                    refused.push((name.clone(), Error::InvalidName));
+
+                } else if Self::name_reserved(name) {
+
+                    refused.push((name.clone(), Error::NameReserved));
+
+                } else if self.name_taken(name) || names[..pos].contains(name) {
+
+                    refused.push((name.clone(), Error::NameTaken));
+
+                }
+
+            }
+
+            if !refused.is_empty() {
+
+                return Err(Error::NamesRefused(refused));
+
+            }
+
+            if !self.registry_has_room(&names) {
+
+                return Err(Error::RegistryFull);
+
+            }
+
+            let mut total = self.bulk_registration_total(names.len() as u32);
+
+            if self.fee_exempt.get(caller).unwrap_or(false) {
+
+                total = 0;
+
+            }
+
+            let transferred = self.draw_balance(caller, transferred, total)?;
+
+            if transferred < total {
+
+                self.credit_balance(caller, transferred)?;
+
+                return Err(Error::PaymentFailed {
+                    received: transferred,
+                    required: total,
+                    missing: total - transferred,
+                });
+
+            }
+
+            self.credit_owner(total)?;
+
+            self.credit_balance(caller, transferred - total)?;
+
+            for name in names.iter() {
+
+                self.release_username(name)?;
+
+                self.assign_username(caller, name.clone(), timestamp)?;
+
+            }
+
+            Ok(())
+
+        }
+
+        /// Tells you the minimum and maximum length, in characters, of a name.
+        #[ink(message)]
+        pub fn check_name_length(&self) -> (u32, u32) {
+            (self.min_name_len, self.max_name_len)
+        }
+
+        /// Tells you which message types can be sent, as a mask where bit N is set
+        /// if the type with index N (see 'MessageType::index') is allowed.
+        #[ink(message)]
+        pub fn check_allowed_types(&self) -> u32 {
+            self.allowed_message_types
+        }
+
+        /// Tells you the minimum amount of names registered together for the bulk discount to apply,
+        /// and the discount percentage.
+        #[ink(message)]
+        pub fn check_bulk_discount(&self) -> (u32, u8) {
+            (self.bulk_discount_threshold, self.bulk_discount_percent)
+        }
+
+        /// Renews one of your names, paying the renewal fee (use 'check_fees'). Once the renewal period has passed,
+        /// the name can still be renewed during the grace period, after which anyone can register it.
+        /// If the payment exceeds the fee, the remainder is stored in your account's balance.
+        #[ink(message,payable)]
+        pub fn renew_username(&mut self, username: Username) -> Result<(),Error> {
+
+            let transferred = self.env().transferred_value();
+            let caller = self.env().caller();
+
+            if let Some(mut username_info) = self.usernames.get(&username) {
+
+                if username_info.account_id != caller {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                if self.name_expired(&username_info) {
+
+                    return Err(Error::NameExpired);
+
+                }
+
+                let transferred = self.draw_balance(caller, transferred, self.renewal_fee)?;
+
+                if transferred < self.renewal_fee {
+
+                    self.credit_balance(caller, transferred)?;
+
+                    return Err(Error::PaymentFailed {
+                        received: transferred,
+                        required: self.renewal_fee,
+                        missing: self.renewal_fee - transferred,
+                    });
+
+                }
+
+                self.credit_owner(self.renewal_fee)?;
+
+                self.credit_balance(caller, transferred - self.renewal_fee)?;
+
+                username_info.fee_payment_time = self.env().block_timestamp();
+
+                self.usernames.insert(&username, &username_info);
+
+                self.env().emit_event(UsernameRenewed { username, fee_payment_time: username_info.fee_payment_time });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// States when the renewal period of a name ends, after which its grace period starts, and when
+        /// the grace period ends, after which anyone can register the name. Names never expire while the
+        /// renewal period set by the contract owner is 0, in which case there is no expiry.
+        #[ink(message)]
+        pub fn get_expiry(&self, username: Username) -> Result<Option<(Timestamp, Timestamp)>,Error> {
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                if self.renewal_period == 0 {
+
+                    return Ok(None);
+
+                }
+
+                let grace_start = self.grace_start(&username_info);
+
+                Ok(Some((grace_start, grace_start.saturating_add(self.grace_period))))
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Tells you whether the renewal period of a name has passed but it can still be renewed by its holder.
+        #[ink(message)]
+        pub fn is_in_grace(&self, username: Username) -> bool {
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                if self.renewal_period == 0 {
+
+                    return false;
+
+                }
+
+                let now = self.env().block_timestamp();
+                let grace_start = self.grace_start(&username_info);
+
+                now >= grace_start && now < grace_start.saturating_add(self.grace_period)
+
+            } else {
+
+                false
+
+            }
+
+        }
+
+        /// Tells you how long (in milliseconds) a name stays valid after being registered or renewed,
+        /// and how long its holder can still renew it afterwards. A renewal period of 0 means names never expire.
+        #[ink(message)]
+        pub fn check_expiry_periods(&self) -> (Timestamp, Timestamp) {
+            (self.renewal_period, self.grace_period)
+        }
+
+        /// Sets a display name, bio or any other text shown along with one of your names, or removes it.
+        /// It can be at most 'MAX_METADATA_LEN' bytes long.
+        #[ink(message)]
+        pub fn set_metadata(&mut self, username: Username, metadata: Option<String>) -> Result<(),Error> {
+
+            if let Some(mut username_info) = self.usernames.get(&username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                if let Some(text) = &metadata {
+
+                    if text.len() > MAX_METADATA_LEN {
+
+                        return Err(Error::MetadataTooLong);
+
+                    }
+
+                }
+
+                username_info.metadata = metadata;
+
+                self.usernames.insert(&username, &username_info);
+
+                self.env().emit_event(UsernameUpdated { username });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Tells you the public information about the specified name: the account holding it,
+        /// when its fee was last paid, the fee it charges for receiving messages and its metadata.
+        #[ink(message)]
+        pub fn username_profile(&self, username: Username) -> Result<UsernameProfile,Error> {
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                Ok(UsernameProfile {
+                    account_id: username_info.account_id,
+                    fee_payment_time: username_info.fee_payment_time,
+                    inbound_fee: username_info.inbound_fee,
+                    metadata: username_info.metadata,
+                })
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// States when the fee of the specified name was last paid, that is, when it was registered or last renewed.
+        #[ink(message)]
+        pub fn registration_time(&self, username: Username) -> Result<Timestamp,Error> {
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                Ok(username_info.fee_payment_time)
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Lists the accounts that have held the specified name, and since when, oldest first.
+        /// Only the most recent holders are remembered.
+        #[ink(message)]
+        pub fn name_history(&self, username: Username) -> Result<NameHistory,Error> {
+
+            if let Some(history) = self.name_history.get(&username) {
+
+                Ok(history)
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Lists the names registered to your account.
+        #[ink(message)]
+        pub fn get_usernames(&self) -> Result<Vec<Username>,Error> {
+
+            if let Some(user_info) = self.users.get(self.env().caller()) {
+
+                if let Some(usernames) = user_info.usernames {
+
+                    Ok(usernames)
+
+                } else {
+
+                    Err(Error::NoNames)
+
+                }
+
+            } else {
+
+                Err(Error::NoAccount)
+
+            }
+        }
+
+        /// States your account id, as seen by the contract.
+        #[ink(message)]
+        pub fn whoami(&self) -> AccountId {
+            self.env().caller()
+        }
+
+        /// Attempts to state the balance associated to your account.
+        #[ink(message)]
+        pub fn get_balance(&self) -> Result<Balance,Error> {
+
+            if self.users.get(self.env().caller()).is_none() {
+
+                return Err(Error::NoAccount);
+
+            }
+        
+            if let Some(user_info) = self.users.get(self.env().caller()) {
+
+                Ok(user_info.balance)
+
+            } else {
+
+                Ok(0)
+
+            }
+        }
+
+        /// States the balance associated to your account, or 0 if you have no account.
+        #[ink(message)]
+        pub fn balance_or_zero(&self) -> Balance {
+
+            self.users.get(self.env().caller()).map(|user_info| user_info.balance).unwrap_or(0)
+
+        }
+
+        /// States, in a single call, the names registered to your account, your balance,
+        /// and how many messages each of your names has received.
+        #[ink(message)]
+        pub fn dashboard(&self) -> Result<(Vec<Username>, Balance, MessageCounts),Error> {
+
+            if let Some(user_info) = self.users.get(self.env().caller()) {
+
+                let usernames = user_info.usernames.unwrap_or_default();
+
+                let mut message_counts = MessageCounts::new();
+
+                for username in usernames.iter() {
+
+                    let count = self.usernames.get(username)
+                        .map(|username_info| username_info.message_count)
+                        .unwrap_or(0);
+
+                    message_counts.push((username.clone(), count));
+
+                }
+
+                Ok((usernames, user_info.balance, message_counts))
+
+            } else {
+
+                Err(Error::NoAccount)
+
+            }
+
+        }
+
+        /// Same as 'send_message', with the specified options. Returns the hash of the message.
+        #[ink(message,payable)]
+        pub fn send_message_with_options(&mut self, from: Username, to: Username, mtype: MessageType, content: Content, options: SendOptions) -> Result<[u8;32],Error> {
+
+            self.deliver_message(from, to, mtype, content, None, options)
+
+        }
+
+        /// Sends the same message to several names at once. Every copy has the same hash, so replies to any of them
+        /// can be matched. The transferred value must cover the fees of every recipient (see 'send_message'), which are
+        /// paid in order; any excess is stored in your account's balance. Returns the recipients the message was
+        /// delivered to, and the ones it could not be delivered to along with the reason.
+        #[ink(message,payable)]
+        pub fn broadcast_message(&mut self, from: Username, to: Vec<Username>, mtype: MessageType, content: Content) -> Result<BatchResult<Username>,Error> {
+
+            if let MessageType::Request { .. } | MessageType::Response { .. } = mtype {
+
+                return Err(Error::MessageTypeNotAllowed);
+
+            }
+
+            self.check_sender(&from, &mtype, &content)?;
+
+            let mut budget = self.env().transferred_value();
+
+            let options = SendOptions::default();
+
+            let message = self.new_message(from, mtype, content, None, &options);
+
+            let mut hash = None;
+
+            let mut result = BatchResult { succeeded: Vec::new(), failed: Vec::new() };
+
+            for name in to {
+
+                if result.succeeded.contains(&name) {
+
+                    continue;
+
+                }
+
+                match self.deliver_to(name.clone(), message.clone(), hash, &options, budget) {
+
+                    Ok((delivered, spent)) => {
+
+                        hash = Some(delivered);
+
+                        budget -= spent;
+
+                        result.succeeded.push(name);
+
+                    }
+
+                    Err(error) => result.failed.push((name, error)),
+
+                }
+
+            }
+
+            if budget > 0 {
+
+                self.credit_balance(self.env().caller(), budget)?;
+
+            }
+
+            Ok(result)
+
+        }
+
+        /// Same as 'send_message', but also stores a signature of the message made off-chain.
+        /// The signature is not verified by the contract: recipients can verify it against the sender's published key.
+        #[ink(message,payable)]
+        pub fn send_message_signed(&mut self, from: Username, to: Username, mtype: MessageType, content: Content, signature: [u8;64]) -> Result<(),Error> {
+
+            self.deliver_message(from, to, mtype, content, Some(Signature(signature)), SendOptions::default())?;
+
+            Ok(())
+
+        }
+
+        /// Same as 'send_message', but the recipient only sees the message once the specified time is reached,
+        /// which can be at most 30 days away. Until then, it can be withdrawn (use 'cancel_scheduled'). Returns the hash of the message.
+        #[ink(message,payable)]
+        pub fn schedule_message(&mut self, from: Username, to: Username, mtype: MessageType, content: Content, deliver_at: Timestamp) -> Result<[u8;32],Error> {
+
+            let options = SendOptions { deliver_at: Some(deliver_at), ..Default::default() };
+
+            self.deliver_message(from, to, mtype, content, None, options)
+
+        }
+
+        /// Withdraws a message you scheduled that was not delivered yet. The fees paid for it are not given back.
+        #[ink(message)]
+        pub fn cancel_scheduled(&mut self, hash: [u8;32]) -> Result<(),Error> {
+
+            if let Some((recipient, seq)) = self.message_location.get(hash) {
+
+                if let Some(message) = self.inbox.get((&recipient, seq)) {
+
+                    if !self.holds(self.env().caller(), &message.from) {
+
+                        return Err(Error::WrongAccount(message.from));
+
+                    }
+
+                    if message.deliver_at.unwrap_or(0) <= self.env().block_timestamp() {
+
+                        return Err(Error::MessageAlreadyDelivered);
+
+                    }
+
+                    if let Some(mut username_info) = self.usernames.get(&recipient) {
+
+                        self.take_message(&recipient, &mut username_info, seq);
+
+                        self.usernames.insert(&recipient, &username_info);
+
+                    }
+
+                    self.env().emit_event(MessageDeleted { hash, by: message.from });
+
+                    return Ok(());
+
+                }
+
+            }
+
+            Err(Error::MessageNonexistent)
+
+        }
+
+        /// Replaces the content of a message you sent from a specific name of yours. The message gets a new hash,
+        /// which is returned, and keeps the hash it had before the change ('edited_from'). Its signature is dropped,
+        /// since it no longer matches the content. Messages sent before you got the name cannot be edited.
+        #[ink(message)]
+        pub fn edit_message(&mut self, from: Username, recipient: Username, hash: [u8;32], new_content: Content) -> Result<[u8;32],Error> {
+
+            self.ensure_not_paused()?;
+
+            if !self.holds(self.env().caller(), &from) {
+
+                return Err(Error::WrongAccount(from));
+
+            }
+
+            self.ensure_not_frozen(&from)?;
+
+            if let Some(mut message) = self.find_message(&recipient, hash) {
+
+                if message.from != from {
+
+                    return Err(Error::MessageNonexistent);
+
+                }
+
+                if self.held_since(&from).unwrap_or(0) > message.timestamp {
+
+                    return Err(Error::WrongAccount(from));
+
+                }
+
+                if message.mtype == MessageType::Json && !Self::json_well_formed(&new_content) {
+
+                    return Err(Error::InvalidJson);
+
+                }
+
+                let block = self.env().block_number();
+                let new_hash = self.message_hash(block, message.seq, &new_content);
+
+                let reactions = self.reactions.get(hash);
+
+                self.forget_message(&recipient, &message);
+
+                if let Some(reactions) = reactions {
+
+                    self.reactions.insert(new_hash, &reactions);
+
+                }
+
+                self.inbox_index.remove((&recipient, hash));
+                self.inbox_index.insert((&recipient, new_hash), &message.seq);
+                self.message_location.insert(new_hash, &(recipient.clone(), message.seq));
+
+                if let Some(reply) = self.first_reply.get(hash) {
+
+                    self.first_reply.insert(new_hash, &reply);
+
+                }
+
+                message.content = new_content;
+                message.hash = new_hash;
+                message.block = block;
+                message.edited_from = Some(hash);
+                message.signature = None;
+
+                self.inbox.insert((&recipient, message.seq), &message);
+
+                self.env().emit_event(MessageEdited { hash: new_hash, edited_from: hash });
+
+                Ok(new_hash)
+
+            } else {
+
+                Err(Error::MessageNonexistent)
+
+            }
+
+        }
+
+        /// Reacts to a message sent to or from a specific name of yours, replacing the previous reaction of that name.
+        /// An empty reaction withdraws the previous one.
+        #[ink(message)]
+        pub fn react_to_message(&mut self, my_username: Username, hash: [u8;32], reaction: String) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            self.message_party(&my_username, hash)?;
+
+            self.ensure_not_frozen(&my_username)?;
+
+            if reaction.len() > MAX_REACTION_LEN {
+
+                return Err(Error::ReactionTooLong);
+
+            }
+
+            let mut reactions = self.reactions.get(hash).unwrap_or_default();
+
+            reactions.retain(|(name, _)| name != &my_username);
+
+            if !reaction.is_empty() {
+
+                if reactions.len() >= MAX_REACTIONS {
+
+                    return Err(Error::TooManyReactions);
+
+                }
+
+                reactions.push((my_username.clone(), reaction.clone()));
+
+            }
+
+            self.reactions.insert(hash, &reactions);
+
+            self.env().emit_event(Reaction { hash, by: my_username, reaction });
+
+            Ok(())
+
+        }
+
+        /// Lists the reactions to a message sent to or from a specific name of yours.
+        #[ink(message)]
+        pub fn get_reactions(&self, username: Username, hash: [u8;32]) -> Result<Reactions,Error> {
+
+            self.message_party(&username, hash)?;
+
+            Ok(self.reactions.get(hash).unwrap_or_default())
+
+        }
+
+        /// Sends a request to a user, with an identifier chosen by the contract which is returned. The recipient
+        /// answers with 'send_response', and the answer can be found with 'get_response_for'. Fees are paid as with
+        /// 'send_message'.
+        #[ink(message,payable)]
+        pub fn send_request(&mut self, from: Username, to: Username, content: Content) -> Result<u64,Error> {
+
+            let id = self.next_request_id;
+
+            let mtype = MessageType::Request { id };
+
+            self.check_sender(&from, &mtype, &content)?;
+
+            self.requests.insert(id, &RequestInfo { from: from.clone(), to: to.clone(), response: None });
+
+            self.next_request_id += 1;
+
+            let options = SendOptions::default();
+
+            let message = self.new_message(from, mtype, content, None, &options);
+
+            self.pay_and_deliver(to, message, &options)?;
+
+            Ok(id)
+
+        }
+
+        /// Answers a request sent to a specific name of yours. Each request can be answered once.
+        /// Fees are paid as with 'send_message'. Returns the hash of the response.
+        #[ink(message,payable)]
+        pub fn send_response(&mut self, from: Username, request_id: u64, content: Content) -> Result<[u8;32],Error> {
+
+            let mtype = MessageType::Response { request_id };
+
+            self.check_sender(&from, &mtype, &content)?;
+
+            if let Some(mut request) = self.requests.get(request_id) {
+
+                if request.to != from {
+
+                    return Err(Error::WrongAccount(from));
+
+                }
+
+                if request.response.is_some() {
+
+                    return Err(Error::AlreadyResponded);
+
+                }
+
+                let options = SendOptions::default();
+
+                let message = self.new_message(from, mtype, content, None, &options);
+
+                let hash = self.pay_and_deliver(request.from.clone(), message, &options)?;
+
+                request.response = self.inbox_index.get((&request.from, hash));
+
+                self.requests.insert(request_id, &request);
+
+                Ok(hash)
+
+            } else {
+
+                Err(Error::RequestNonexistent)
+
+            }
+
+        }
+
+        /// Attempts to find the response to a request sent from a specific name of yours, if it was answered.
+        #[ink(message)]
+        pub fn get_response_for(&self, request_id: u64) -> Result<Option<Message>,Error> {
+
+            if let Some(request) = self.requests.get(request_id) {
+
+                if !self.holds(self.env().caller(), &request.from) {
+
+                    return Err(Error::WrongAccount(request.from));
+
+                }
+
+                if let Some(seq) = request.response {
+
+                    return Ok(self.inbox.get((&request.from, seq)));
+
+                }
+
+                Ok(None)
+
+            } else {
+
+                Err(Error::RequestNonexistent)
+
+            }
+
+        }
+
+        /// Forwards a message sent to a specific name of yours to another user, from that same name.
+        /// The content is preserved, and the message type ('Forwarded') notes the hash and sender of the original message.
+        /// Forwarding a forwarded message keeps pointing to the original one.
+        /// Returns the hash of the forwarded message.
+        #[ink(message,payable)]
+        pub fn forward_message(&mut self, from_inbox: Username, hash: [u8;32], to: Username) -> Result<[u8;32],Error> {
+
+            if let Some(username_info) = self.usernames.get(&from_inbox) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(from_inbox));
+
+                }
+
+                if username_info.message_count > 0 {
+
+                    if let Some(message) = self.find_message(&from_inbox, hash) {
+
+                        let mtype = if let MessageType::Forwarded { .. } = message.mtype {
+                            message.mtype
+                        } else {
+                            MessageType::Forwarded { original_hash: message.hash, original_from: message.from }
+                        };
+
+                        self.deliver_message(from_inbox, to, mtype, message.content, None, SendOptions::default())
+
+                    } else {
+
+                        Err(Error::MessageNonexistent)
+
+                    }
+
+                } else {
+
+                    Err(Error::NoMessages)
+
+                }
+
+            } else {
+
+                Err(Error::NameNonexistent(from_inbox))
+
+            }
+
+        }
+
+        /// Sets the fee senders must pay to message one of your names. The fee is credited to your balance.
+        /// Approved senders (see 'approve_sender') do not pay it.
+        #[ink(message)]
+        pub fn set_inbound_fee(&mut self, username: Username, fee: Balance) -> Result<(),Error> {
+
+            if let Some(mut username_info) = self.usernames.get(&username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                username_info.inbound_fee = fee;
+
+                self.usernames.insert(&username, &username_info);
+
+                self.env().emit_event(UsernameUpdated { username });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Refuses messages sent to a specific name of yours from the specified name.
+        #[ink(message)]
+        pub fn block_sender(&mut self, my_username: Username, blocked: Username) -> Result<(),Error> {
+
+            if let Some(mut username_info) = self.usernames.get(&my_username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(my_username));
+
+                }
+
+                if !username_info.blocked.contains(&blocked) {
+
+                    if username_info.blocked.len() >= MAX_BLOCKED_SENDERS {
+
+                        return Err(Error::BlocklistFull);
+
+                    }
+
+                    username_info.blocked.push(blocked);
+
+                    self.usernames.insert(&my_username, &username_info);
+
+                }
+
+                self.env().emit_event(UsernameUpdated { username: my_username });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(my_username))
+
+            }
+
+        }
+
+        /// Accepts again messages sent to a specific name of yours from the specified name (see 'block_sender').
+        #[ink(message)]
+        pub fn unblock_sender(&mut self, my_username: Username, blocked: Username) -> Result<(),Error> {
+
+            if let Some(mut username_info) = self.usernames.get(&my_username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(my_username));
+
+                }
+
+                username_info.blocked.retain(|name| name != &blocked);
+
+                self.usernames.insert(&my_username, &username_info);
+
+                self.env().emit_event(UsernameUpdated { username: my_username });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(my_username))
+
+            }
+
+        }
+
+        /// Sets who can send messages to a specific name of yours: anyone who is not blocked, or only approved senders.
+        #[ink(message)]
+        pub fn set_inbox_policy(&mut self, username: Username, policy: InboxPolicy) -> Result<(),Error> {
+
+            if let Some(mut username_info) = self.usernames.get(&username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                username_info.inbox_policy = policy;
+
+                self.usernames.insert(&username, &username_info);
+
+                self.env().emit_event(UsernameUpdated { username });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Approves a sender for a specific name of yours. Approved senders do not pay the inbound fee of the name
+        /// (see 'set_inbound_fee'), and are the only ones accepted when the name only accepts approved senders
+        /// (see 'set_inbox_policy').
+        #[ink(message)]
+        pub fn approve_sender(&mut self, my_username: Username, sender: Username) -> Result<(),Error> {
+
+            if let Some(mut username_info) = self.usernames.get(&my_username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(my_username));
+
+                }
+
+                if !username_info.approved_senders.contains(&sender) {
+
+                    if username_info.approved_senders.len() >= MAX_APPROVED_SENDERS {
+
+                        return Err(Error::AllowlistFull);
+
+                    }
+
+                    username_info.approved_senders.push(sender);
+
+                    self.usernames.insert(&my_username, &username_info);
+
+                }
+
+                self.env().emit_event(UsernameUpdated { username: my_username });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(my_username))
+
+            }
+
+        }
+
+        /// Withdraws the approval of a sender for a specific name of yours (see 'approve_sender').
+        #[ink(message)]
+        pub fn revoke_sender(&mut self, my_username: Username, sender: Username) -> Result<(),Error> {
+
+            if let Some(mut username_info) = self.usernames.get(&my_username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(my_username));
+
+                }
+
+                username_info.approved_senders.retain(|name| name != &sender);
+
+                self.usernames.insert(&my_username, &username_info);
+
+                self.env().emit_event(UsernameUpdated { username: my_username });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(my_username))
+
+            }
+
+        }
+
+        /// Lets another account send messages from a specific name of yours until the given timestamp, without
+        /// holding the name (useful for bots and hot wallets). The authorization ends when the name changes hands.
+        #[ink(message)]
+        pub fn authorize_sender(&mut self, username: Username, delegate: AccountId, expires_at: Timestamp) -> Result<(),Error> {
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                self.delegates.insert((username.clone(), delegate), &(username_info.account_id, expires_at));
+
+                self.env().emit_event(UsernameUpdated { username });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Withdraws the authorization of an account to send messages from a specific name of yours (see 'authorize_sender').
+        #[ink(message)]
+        pub fn revoke_delegate(&mut self, username: Username, delegate: AccountId) -> Result<(),Error> {
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                self.delegates.remove((username.clone(), delegate));
+
+                self.env().emit_event(UsernameUpdated { username });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Returns until when an account may send messages from a specific name (see 'authorize_sender'),
+        /// if it currently may.
+        #[ink(message)]
+        pub fn delegate_expiry(&self, username: Username, delegate: AccountId) -> Option<Timestamp> {
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                if self.is_delegate(&username, &username_info.account_id, delegate) {
+
+                    return self.delegates.get((username, delegate)).map(|(_, expires_at)| expires_at);
+
+                }
+
+            }
+
+            None
+
+        }
+
+        /// Publishes the public key senders should encrypt messages to a specific name of yours with
+        /// (see 'MessageType::Encrypted'). The key is forgotten when the name changes hands.
+        #[ink(message)]
+        pub fn set_encryption_key(&mut self, username: Username, pubkey: [u8;32]) -> Result<(),Error> {
+
+            if let Some(mut username_info) = self.usernames.get(&username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                username_info.encryption_key = Some(pubkey);
+
+                self.usernames.insert(&username, &username_info);
+
+                self.env().emit_event(UsernameUpdated { username });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Tells you the public key published for a name, if any (see 'set_encryption_key').
+        #[ink(message)]
+        pub fn get_encryption_key(&self, username: Username) -> Result<Option<[u8;32]>,Error> {
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                Ok(username_info.encryption_key)
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Tells you the fee for sending a message to the specified name.
+        #[ink(message)]
+        pub fn check_inbound_fee(&self, username: Username) -> Result<Balance,Error> {
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                Ok(username_info.inbound_fee)
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Copies a note to the inboxes of all the names registered to your account.
+        /// Returns the amount of names the note was delivered to.
+        #[ink(message)]
+        pub fn note_to_self(&mut self, content: Content) -> Result<u32,Error> {
+
+            self.ensure_not_paused()?;
+
+            let usernames = self.users.get(self.env().caller())
+                .and_then(|user_info| user_info.usernames)
+                .unwrap_or_default();
+
+            let block = self.env().block_number();
+            let timestamp = self.env().block_timestamp();
+
+            let mut delivered: u32 = 0;
+
+            for username in usernames.iter() {
+
+                if let Some(mut username_info) = self.usernames.get(username) {
+
+                    let seq = username_info.next_seq;
+                    let hash = self.message_hash(block, seq, &content);
+
+                    self.push_message(username, &mut username_info, Message {
+                        from: NOTE_TO_SELF_SENDER.into(),
+                        mtype: MessageType::Text,
+                        content: content.clone(),
+                        hash,
+                        timestamp,
+                        signature: None,
+                        requires_ack: false,
+                        acknowledged: false,
+                        compressed: false,
+                        seq,
+                        read_at: None,
+                        block,
+                        expires_at: None,
+                        deliver_at: None,
+                        edited_from: None,
+                    });
+
+                    self.usernames.insert(username, &username_info);
+
+                    self.env().emit_event(MessageSent { to_hash: self.name_hash(username), message_hash: hash });
+
+                    delivered += 1;
+
+                }
+
+            }
+
+            Ok(delivered)
+
+        }
+
+        /// Stores a message in the recipient's inbox and returns its hash.
+        fn deliver_message(&mut self, from: Username, to: Username, mtype: MessageType, content: Content, signature: Option<Signature>, options: SendOptions) -> Result<[u8;32],Error> {
+
+            if let MessageType::Request { .. } | MessageType::Response { .. } = mtype {
+
+                return Err(Error::MessageTypeNotAllowed);
+
+            }
+
+            self.check_sender(&from, &mtype, &content)?;
+
+            let message = self.new_message(from, mtype, content, signature, &options);
+
+            self.pay_and_deliver(to, message, &options)
+
+        }
+
+        /// Stores a message in the recipient's inbox, paying the fees with the transferred value, and returns its hash.
+        /// Any excess is stored in the caller's balance.
+        fn pay_and_deliver(&mut self, to: Username, message: Message, options: &SendOptions) -> Result<[u8;32],Error> {
+
+            let transferred = self.env().transferred_value();
+
+            let (hash, spent) = self.deliver_to(to, message, None, options, transferred)?;
+
+            if transferred > spent {
+
+                self.credit_balance(self.env().caller(), transferred - spent)?;
+
+            }
+
+            Ok(hash)
+
+        }
+
+        /// Makes sure a message can be sent from the specified name by the caller.
+        fn check_sender(&self, from: &Username, mtype: &MessageType, content: &Content) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            if let Some(username_info) = self.usernames.get(from) {
+
+                if username_info.account_id != self.env().caller() && !self.is_delegate(from, &username_info.account_id, self.env().caller()) {
+
+                    return Err(Error::WrongAccount(from.clone()));
+
+                }
+
+                self.ensure_not_frozen(from)?;
+
+                if self.allowed_message_types & (1 << mtype.index()) == 0 {
+
+                    return Err(Error::MessageTypeNotAllowed);
+
+                }
+
+                if mtype == &MessageType::Json && !Self::json_well_formed(content) {
+
+                    return Err(Error::InvalidJson);
+
+                }
+
+                if let MessageType::Attachment { content_uri, content_hash, mime, .. } = mtype {
+
+                    if content_uri.is_empty() || content_uri.len() > MAX_CONTENT_URI_LEN || mime.is_empty() || content_hash == &[0u8;32] {
+
+                        return Err(Error::InvalidAttachment);
+
+                    }
+
+                }
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(from.clone()))
+
+            }
+
+        }
+
+        /// Whether an account may currently send from a name held by 'holder' (see 'authorize_sender').
+        /// Authorizations given by a previous holder of the name do not count.
+        fn is_delegate(&self, username: &Username, holder: &AccountId, account: AccountId) -> bool {
+
+            if let Some((granted_by, expires_at)) = self.delegates.get((username.clone(), account)) {
+
+                return &granted_by == holder && self.env().block_timestamp() < expires_at;
+
+            }
+
+            false
+
+        }
+
+        /// Message sent now, before it is given its place in an inbox.
+        fn new_message(&self, from: Username, mtype: MessageType, content: Content, signature: Option<Signature>, options: &SendOptions) -> Message {
+            Message {
+                from,
+                mtype,
+                content,
+                hash: [0u8;32],
+                timestamp: self.env().block_timestamp(),
+                signature,
+                requires_ack: options.requires_ack,
+                acknowledged: false,
+                compressed: options.compressed,
+                seq: 0,
+                read_at: None,
+                block: self.env().block_number(),
+                expires_at: options.expires_at,
+                deliver_at: options.deliver_at,
+                edited_from: None,
+            }
+        }
+
+        /// Stores a message in the inbox of a name, paying the fees out of 'budget'.
+        /// Returns the hash of the message, which is 'hash' if specified, and the amount spent.
+        fn deliver_to(&mut self, to: Username, mut message: Message, hash: Option<[u8;32]>, options: &SendOptions, budget: Balance) -> Result<([u8;32], Balance),Error> {
+
+            if message.deliver_at.is_some_and(|deliver_at| deliver_at > message.timestamp.saturating_add(MAX_SCHEDULE_DELAY)) {
+
+                return Err(Error::DeliveryTooLate);
+
+            }
+
+            if let Some(mut username_info) = self.usernames.get(&to) {
+
+                if username_info.blocked.contains(&message.from) {
+
+                    return Err(Error::SenderBlocked);
+
+                }
+
+                if username_info.inbox_policy == InboxPolicy::AllowlistOnly && !username_info.approved_senders.contains(&message.from) {
+
+                    return Err(Error::SenderNotApproved);
+
+                }
+
+                let key = options.idempotency_key.map(|key| (to.clone(), message.from.clone(), key));
+
+                if let Some(key) = &key {
+
+                    if let Some((hash, seen_at)) = self.seen_keys.get(key) {
+
+                        if message.timestamp < seen_at.saturating_add(IDEMPOTENCY_WINDOW) {
+
+                            return Ok((hash, 0));
+
+                        }
+
+                    }
+
+                }
+
+                // Approved senders do not pay the fee set by the recipient.
+                let inbound_fee = if username_info.approved_senders.contains(&message.from) { 0 } else { username_info.inbound_fee };
+
+                let required = Self::checked_sum(inbound_fee, self.message_fee)?;
+
+                if budget < required {
+
+                    return Err(Error::PaymentFailed {
+                        received: budget,
+                        required,
+                        missing: required - budget,
+                    });
+
+                }
+
+                if let MessageType::ReplyTo { hash: parent } = message.mtype {
+
+                    if self.max_reply_depth > 0 && self.reply_depth(parent, &[&to, &message.from], self.max_reply_depth) > self.max_reply_depth {
+
+                        return Err(Error::ReplyTooDeep);
+
+                    }
+
+                }
+
+                let seq = username_info.next_seq;
+                let hash = hash.unwrap_or_else(|| self.message_hash(message.block, seq, &message.content));
+
+                if self.inbox_index.contains((&to, hash)) {
+
+                    return Err(Error::UnexpectedInternalError);
+
+                }
+
+                let fee_receiver = username_info.account_id;
+                let from = message.from.clone();
+                let timestamp = message.timestamp;
+                let pending = message.deliver_at.is_some_and(|deliver_at| deliver_at > timestamp);
+                let parent = if let MessageType::ReplyTo { hash: parent } = message.mtype { Some(parent) } else { None };
+
+                message.hash = hash;
+                message.seq = seq;
+
+                self.push_message(&to, &mut username_info, message);
+
+                if !self.message_location.contains(hash) {
+
+                    self.message_location.insert(hash, &(to.clone(), seq));
+
+                }
+
+                if let Some(parent) = parent {
+
+                    if !self.first_reply.contains(parent) {
+
+                        self.first_reply.insert(parent, &hash);
+
+                    }
+
+                }
+
+                if inbound_fee > 0 {
+
+                    self.credit_balance(fee_receiver, inbound_fee)?;
+
+                }
+
+                if self.message_fee > 0 {
+
+                    self.credit_owner(self.message_fee)?;
+
+                }
+
+                self.usernames.insert(&to, &username_info);
+
+                self.record_sent(&from, &to, seq);
+
+                if let Some(key) = key {
+
+                    self.remember_key(key, hash, timestamp);
+
+                }
+
+                self.announce_due(&to);
+
+                if pending {
+
+                    // 'MessageSent' is emitted once the message is delivered (see 'announce_scheduled').
+                    let mut scheduled = self.scheduled.get(&to).unwrap_or_default();
+
+                    scheduled.push(seq);
+
+                    self.scheduled.insert(&to, &scheduled);
+
+                } else {
+
+                    self.env().emit_event(MessageSent { to_hash: self.name_hash(&to), message_hash: hash });
+
+                }
+
+                Ok((hash, required))
+
+            } else {
+
+                Err(Error::NameNonexistent(to))
+
+            }
+
+        }
+
+        /// Emits 'MessageSent' for the scheduled messages sent to a name that were delivered since (see 'schedule_message'),
+        /// returning how many there were. Delivered messages are shown either way: this only lets indexers know about them.
+        /// It is also done whenever the name receives a message. Anyone can call this.
+        #[ink(message)]
+        pub fn announce_scheduled(&mut self, username: Username) -> Result<u32,Error> {
+
+            if !self.usernames.contains(&username) {
+
+                return Err(Error::NameNonexistent(username));
+
+            }
+
+            Ok(self.announce_due(&username))
+
+        }
+
+        /// Removes the expired messages from the inbox of a name (see 'SendOptions'), returning how many there were.
+        /// Expired messages are never listed, but they take storage until they are removed. Anyone can call this.
+        #[ink(message)]
+        pub fn prune_expired(&mut self, username: Username) -> Result<u32,Error> {
+
+            if let Some(mut username_info) = self.usernames.get(&username) {
+
+                let mut count: u32 = 0;
+
+                for seq in username_info.first_seq..username_info.next_seq {
+
+                    if let Some(message) = self.inbox.get((&username, seq)) {
+
+                        if self.message_expired(&message) {
+
+                            self.take_message(&username, &mut username_info, seq);
+
+                            count += 1;
+
+                        }
+
+                    }
+
+                }
+
+                if count > 0 {
+
+                    self.usernames.insert(&username, &username_info);
+
+                    self.env().emit_event(InboxPurged { username, count });
+
+                }
+
+                Ok(count)
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Follows a conversation starting at the specified message: the first reply to it, then the first reply to
+        /// that reply, and so on, in whichever inbox they are, up to 'limit' messages. You must hold the sender or
+        /// the recipient of the first message; the walk stops at the first message you hold neither of.
+        #[ink(message)]
+        pub fn get_thread(&self, root_hash: [u8;32], limit: u32) -> Result<Vec<Message>,Error> {
+
+            let caller = self.env().caller();
+
+            let mut thread = Vec::<Message>::new();
+
+            let mut current = Some(root_hash);
+
+            while let Some(hash) = current {
+
+                if thread.len() >= limit as usize {
+
+                    break;
+
+                }
+
+                if let Some((recipient, seq)) = self.message_location.get(hash) {
+
+                    if let Some(message) = self.inbox.get((&recipient, seq)) {
+
+                        if !self.holds(caller, &recipient) && !self.holds(caller, &message.from) {
+
+                            if thread.is_empty() {
+
+                                return Err(Error::WrongAccount(recipient));
+
+                            }
+
+                            break;
+
+                        }
+
+                        if self.message_visible(&message) {
+
+                            thread.push(message);
+
+                        }
+
+                        current = self.first_reply.get(hash);
+
+                        continue;
+
+                    }
+
+                }
+
+                break;
+
+            }
+
+            if thread.is_empty() {
+
+                return Err(Error::MessageNonexistent);
+
+            }
+
+            Ok(thread)
+
+        }
+
+        /// Lists up to 'limit' messages sent from a specific name of yours, oldest first, skipping the first 'offset' ones.
+        /// Each one is given with its recipient. Messages the recipient deleted are left out, but still count for 'offset'.
+        #[ink(message)]
+        pub fn get_sent_messages(&self, from: Username, offset: u64, limit: u32) -> Result<Vec<(Username, Message)>,Error> {
+
+            if let Some(username_info) = self.usernames.get(&from) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(from));
+
+                }
+
+                let mut sent = Vec::new();
+
+                let end = offset.saturating_add(limit as u64).min(username_info.sent_count);
+
+                for index in offset..end {
+
+                    if let Some((to, seq)) = self.outbox.get((&from, index)) {
+
+                        // The slot may hold another message if the recipient name was released and registered again.
+                        if let Some(message) = self.inbox.get((&to, seq)) {
+
+                            if message.from == from {
+
+                                sent.push((to, message));
+
+                            }
+
+                        }
+
+                    }
+
+                }
+
+                Ok(sent)
+
+            } else {
+
+                Err(Error::NameNonexistent(from))
+
+            }
+
+        }
+
+        /// Lists up to 'limit' messages a specific name of yours received from another name, oldest first, skipping
+        /// the first 'offset' ones. Messages you deleted are left out, but still count for 'offset'.
+        #[ink(message)]
+        pub fn get_messages_from(&self, my_username: Username, sender: Username, offset: u64, limit: u32) -> Result<Vec<Message>,Error> {
+
+            if let Some(username_info) = self.usernames.get(&my_username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(my_username));
+
+                }
+
+                let mut messages = Vec::new();
+
+                let generation = self.inbox_generations.get(&my_username).unwrap_or(0);
+
+                let received = self.sender_counts.get((&my_username, generation, &sender)).unwrap_or(0);
+
+                let end = offset.saturating_add(limit as u64).min(received);
+
+                for index in offset..end {
+
+                    if let Some(seq) = self.sender_index.get((&my_username, generation, &sender, index)) {
+
+                        if let Some(message) = self.inbox.get((&my_username, seq)) {
+
+                            if self.message_visible(&message) {
+
+                                messages.push(message);
+
+                            }
+
+                        }
+
+                    }
+
+                }
+
+                Ok(messages)
+
+            } else {
+
+                Err(Error::NameNonexistent(my_username))
+
+            }
+
+        }
+
+        /// Same as 'get_all_messages', with the messages ordered by their sequence number ('seq'),
+        /// which every name assigns to the messages it receives in increasing order.
+        #[ink(message)]
+        pub fn get_all_messages_ordered(&self, belonging_to: Username) -> Result<Vec<Message>,Error> {
+
+            let mut messages = self.get_all_messages(belonging_to)?;
+
+            messages.sort_by_key(|message| message.seq);
+
+            Ok(messages)
+
+        }
+
+        /// Attempts to make available the messages sent to a specific name of yours
+        /// that were delivered between 'from_ts' and 'to_ts' (both included). A scheduled message is delivered
+        /// at its 'deliver_at', any other one when it is sent, so clients that sync periodically
+        /// get what arrived since their last sync, and the messages sent long before it are not read.
+        #[ink(message)]
+        pub fn get_messages_range(&self, belonging_to: Username, from_ts: Timestamp, to_ts: Timestamp) -> Result<Vec<Message>,Error> {
+
+            if from_ts > to_ts {
+
+                return Err(Error::InvalidRange);
+
+            }
+
+            if let Some(username_info) = self.usernames.get(&belonging_to) {
+
+                if self.env().caller() != username_info.account_id {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                let mut messages = Vec::new();
+
+                // Timestamps never decrease along the inbox and messages are delivered at most 'MAX_SCHEDULE_DELAY'
+                // after they are sent, so the scan starts from the newest message and stops at the first one
+                // sent too early to be delivered in the range.
+                let earliest = from_ts.saturating_sub(MAX_SCHEDULE_DELAY);
+
+                for seq in (username_info.first_seq..username_info.next_seq).rev() {
+
+                    if let Some(message) = self.inbox.get((&belonging_to, seq)) {
+
+                        if message.timestamp < earliest {
+
+                            break;
+
+                        }
+
+                        let delivered_at = message.deliver_at.unwrap_or(0).max(message.timestamp);
+
+                        if delivered_at >= from_ts && delivered_at <= to_ts && self.message_visible(&message) {
+
+                            messages.push(message);
+
+                        }
+
+                    }
+
+                }
+
+                messages.reverse();
+
+                Ok(messages)
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        /// Attempts to make available the custom messages with the specified tag sent to a specific name of yours,
+        /// whatever their schema version.
+        #[ink(message)]
+        pub fn get_messages_by_tag(&self, belonging_to: Username, tag: String) -> Result<Vec<Message>,Error> {
+
+            if let Some(username_info) = self.usernames.get(&belonging_to) {
+
+                if self.env().caller() != username_info.account_id {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                let messages = self.load_inbox(&belonging_to, &username_info)
+                    .into_iter()
+                    .filter(|message| matches!(&message.mtype, MessageType::Custom { tag: message_tag, .. } if *message_tag == tag))
+                    .collect();
+
+                Ok(messages)
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        /// Attempts to make available the messages of some types sent to a specific name of yours. The types are
+        /// given as a mask where bit N selects the type with index N, as in 'co_set_allowed_types'
+        /// (for instance, 1 << 1 for emails only, or 1 << 4 for JSON messages only).
+        #[ink(message)]
+        pub fn get_messages_of_type(&self, belonging_to: Username, mtype_filter: u32) -> Result<Vec<Message>,Error> {
+
+            if let Some(username_info) = self.usernames.get(&belonging_to) {
+
+                if self.env().caller() != username_info.account_id {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                let messages = self.load_inbox(&belonging_to, &username_info)
+                    .into_iter()
+                    .filter(|message| mtype_filter & (1 << message.mtype.index()) != 0)
+                    .collect();
+
+                Ok(messages)
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        /// States, for each name that sent messages to a specific name of yours, how many messages it sent.
+        /// Senders are listed in the order their first message was received.
+        #[ink(message)]
+        pub fn sender_stats(&self, belonging_to: Username) -> Result<MessageCounts,Error> {
+
+            if let Some(username_info) = self.usernames.get(&belonging_to) {
+
+                if self.env().caller() != username_info.account_id {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                let mut stats = MessageCounts::new();
+
+                for message in self.load_inbox(&belonging_to, &username_info).into_iter() {
+
+                    if let Some(entry) = stats.iter_mut().find(|(sender, _)| sender == &message.from) {
+
+                        entry.1 += 1;
+
+                    } else {
+
+                        stats.push((message.from, 1));
+
+                    }
+
+                }
+
+                Ok(stats)
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        /// Attempts to export all the messages sent to a specific name of yours as a single SCALE encoded blob,
+        /// which decodes into a 'Vec<Message>'. Useful for backups: importing it back would require a separate,
+        /// owner-gated message.
+        #[ink(message)]
+        pub fn export_inbox(&self, belonging_to: Username) -> Result<Vec<u8>,Error> {
+
+            if let Some(username_info) = self.usernames.get(&belonging_to) {
+
+                if self.env().caller() != username_info.account_id {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                let messages = self.load_inbox(&belonging_to, &username_info);
+
+                Ok(scale::Encode::encode(&messages))
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        /// Acknowledges a message sent to a specific name of yours that asked to be acknowledged.
+        #[ink(message)]
+        pub fn acknowledge(&mut self, belonging_to: Username, hash: [u8;32]) -> Result<(),Error> {
+
+            if let Some(username_info) = self.usernames.get(&belonging_to) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                if username_info.message_count > 0 {
+
+                    if let Some(mut message) = self.find_message(&belonging_to, hash) {
+
+                        if !message.requires_ack {
+
+                            return Err(Error::AcknowledgementNotRequired);
+
+                        }
+
+                        if message.acknowledged {
+
+                            return Ok(());
+
+                        }
+
+                        message.acknowledged = true;
+
+                        self.inbox.insert((&belonging_to, message.seq), &message);
+
+                    } else {
+
+                        return Err(Error::MessageNonexistent);
+
+                    }
+
+                    self.env().emit_event(MessageAcknowledged { hash, by: belonging_to });
+
+                    Ok(())
+
+                } else {
+
+                    Err(Error::NoMessages)
+
+                }
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        /// Attempts to make available a message sent to a specific name of yours, along with its hash
+        /// computed again from the stored message, so that it can be compared with the stored hash.
+        #[ink(message)]
+        pub fn get_message_with_proof(&self, belonging_to: Username, hash: [u8;32]) -> Result<(Message, [u8;32]),Error> {
+
+            if let Some(username_info) = self.usernames.get(&belonging_to) {
+
+                if self.env().caller() != username_info.account_id {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                if username_info.message_count > 0 {
+
+                    if let Some(message) = self.find_message(&belonging_to, hash) {
+
+                        let recomputed = self.message_hash(message.block, message.seq, &message.content);
+
+                        Ok((message, recomputed))
+
+                    } else {
+
+                        Err(Error::MessageNonexistent)
+
+                    }
+
+                } else {
+
+                    Err(Error::NoMessages)
+
+                }
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        /// Tells you whether a message sent to the specified name was acknowledged.
+        /// Can be called by the recipient or by the holder of the name the message was sent from.
+        #[ink(message)]
+        pub fn is_acknowledged(&self, recipient: Username, hash: [u8;32]) -> Result<bool,Error> {
+
+            if let Some(username_info) = self.usernames.get(&recipient) {
+
+                if let Some(message) = self.find_message(&recipient, hash) {
+
+                    let caller = self.env().caller();
+
+                    let sender = self.usernames.get(&message.from).map(|info| info.account_id);
+
+                    if username_info.account_id != caller && sender != Some(caller) {
+
+                        return Err(Error::WrongAccount(recipient));
+
+                    }
+
+                    Ok(message.acknowledged)
+
+                } else {
+
+                    Err(Error::MessageNonexistent)
+
+                }
+
+            } else {
+
+                Err(Error::NameNonexistent(recipient))
+
+            }
+
+        }
+
+        /// Attempts to mark a message sent to a specific name of yours as read. The time it was first marked as read
+        /// is kept in the message ('read_at').
+        #[ink(message)]
+        pub fn mark_as_read(&mut self, belonging_to: Username, hash: [u8;32]) -> Result<(),Error> {
+
+            if let Some(mut username_info) = self.usernames.get(&belonging_to) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                if username_info.message_count > 0 {
+
+                    if let Some(mut message) = self.find_message(&belonging_to, hash) {
+
+                        if message.read_at.is_none() {
+
+                            message.read_at = Some(self.env().block_timestamp());
+
+                            self.inbox.insert((&belonging_to, message.seq), &message);
+
+                            username_info.unread_count -= 1;
+
+                            self.usernames.insert(&belonging_to, &username_info);
+
+                        }
+
+                    } else {
+
+                        return Err(Error::MessageNonexistent);
+
+                    }
+
+                    self.env().emit_event(MessageRead { hash, by: belonging_to });
+
+                    Ok(())
+
+                } else {
+
+                    Err(Error::NoMessages)
+
+                }
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        /// Marks every message shown in the inbox of a specific name of yours as read. Returns how many were not marked as
+        /// read yet.
+        #[ink(message)]
+        pub fn mark_all_read(&mut self, belonging_to: Username) -> Result<u32,Error> {
+
+            if let Some(mut username_info) = self.usernames.get(&belonging_to) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                let timestamp = self.env().block_timestamp();
+
+                let mut count: u32 = 0;
+
+                for seq in username_info.first_seq..username_info.next_seq {
+
+                    if let Some(mut message) = self.inbox.get((&belonging_to, seq)) {
+
+                        if message.read_at.is_none() && self.message_visible(&message) {
+
+                            message.read_at = Some(timestamp);
+
+                            self.inbox.insert((&belonging_to, seq), &message);
+
+                            self.env().emit_event(MessageRead { hash: message.hash, by: belonging_to.clone() });
+
+                            count += 1;
+
+                        }
+
+                    }
+
+                }
+
+                username_info.unread_count -= count;
+
+                self.usernames.insert(&belonging_to, &username_info);
+
+                Ok(count)
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        /// Tells you whether a specific name of yours has any message that was not marked as read.
+        #[ink(message)]
+        pub fn has_unread(&self, belonging_to: Username) -> Result<bool,Error> {
+
+            if let Some(username_info) = self.usernames.get(&belonging_to) {
+
+                if self.env().caller() != username_info.account_id {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                Ok(username_info.unread_count > self.pending_count(&belonging_to))
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        /// Attempts to make the messages sent to a specific name of yours that were not marked as read available.
+        #[ink(message)]
+        pub fn get_unread_messages(&self, belonging_to: Username) -> Result<Vec<Message>,Error> {
+
+            if let Some(username_info) = self.usernames.get(&belonging_to) {
+
+                if self.env().caller() != username_info.account_id {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                let mut messages = self.load_inbox(&belonging_to, &username_info);
+
+                messages.retain(|message| message.read_at.is_none());
+
+                Ok(messages)
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        /// Tells you how many messages sent to a specific name of yours were not marked as read.
+        /// Expired messages are counted until they are removed (see 'prune_expired'), but scheduled messages are only
+        /// counted once they are delivered (see 'schedule_message').
+        #[ink(message)]
+        pub fn get_unread_count(&self, belonging_to: Username) -> Result<u32,Error> {
+
+            if let Some(username_info) = self.usernames.get(&belonging_to) {
+
+                if self.env().caller() != username_info.account_id {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                Ok(username_info.unread_count.saturating_sub(self.pending_count(&belonging_to)))
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        /// Tells you how many messages were sent to a specific name of yours and are still in its inbox.
+        #[ink(message)]
+        pub fn get_message_count(&self, belonging_to: Username) -> Result<u32,Error> {
+
+            self.get_inbox_summary(belonging_to).map(|summary| summary.total)
+
+        }
+
+        /// Tells you how many messages are in the inbox of a specific name of yours, how many of them were not marked
+        /// as read, and when the oldest and the newest were sent, without loading the messages (see 'get_all_messages').
+        /// Counts are the same as in 'get_unread_count'.
+        #[ink(message)]
+        pub fn get_inbox_summary(&self, belonging_to: Username) -> Result<InboxSummary,Error> {
+
+            if let Some(username_info) = self.usernames.get(&belonging_to) {
+
+                if self.env().caller() != username_info.account_id {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                let shown = |seq: u64| self.inbox.get((&belonging_to, seq)).filter(|message| self.message_visible(message));
+
+                let oldest_timestamp = (username_info.first_seq..username_info.next_seq).find_map(shown).map(|message| message.timestamp);
+
+                let newest_timestamp = (username_info.first_seq..username_info.next_seq).rev().find_map(shown).map(|message| message.timestamp);
+
+                let pending = self.pending_count(&belonging_to);
+
+                Ok(InboxSummary {
+                    total: username_info.message_count.saturating_sub(pending),
+                    unread: username_info.unread_count.saturating_sub(pending),
+                    oldest_timestamp,
+                    newest_timestamp,
+                })
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        /// Attempts to delete several messages sent to a specific name of yours, given their hashes.
+        /// Reports which hashes were deleted and which were not, and why.
+        #[ink(message)]
+        pub fn delete_messages(&mut self, belonging_to: Username, hashes: Vec<[u8;32]>) -> Result<BatchResult<[u8;32]>,Error> {
+
+            if let Some(mut username_info) = self.usernames.get(&belonging_to) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                let mut result = BatchResult { succeeded: Vec::new(), failed: Vec::new() };
+
+                for hash in hashes.into_iter() {
+
+                    if let Some(message) = self.find_message(&belonging_to, hash) {
+
+                        self.take_message(&belonging_to, &mut username_info, message.seq);
+
+                        self.env().emit_event(MessageDeleted { hash, by: belonging_to.clone() });
+
+                        result.succeeded.push(hash);
+
+                    } else {
+
+                        result.failed.push((hash, Error::MessageNonexistent));
+
+                    }
+
+                }
+
+                self.usernames.insert(&belonging_to, &username_info);
+
+                Ok(result)
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        /// Attempts to delete the message at the specified position of the inbox.
+        /// Useful for clients that enumerate the messages returned by 'get_all_messages'.
+        #[ink(message)]
+        pub fn delete_message_at(&mut self, belonging_to: Username, index: u32) -> Result<(),Error> {
+
+            if let Some(mut username_info) = self.usernames.get(&belonging_to) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                if username_info.message_count > 0 {
+
+                    if index >= username_info.message_count {
+
+                        return Err(Error::MessageNonexistent);
+
+                    }
+
+                    if let Some(seq) = self.seq_at(&belonging_to, &username_info, index) {
+
+                        if let Some(removed) = self.take_message(&belonging_to, &mut username_info, seq) {
+
+                            self.usernames.insert(&belonging_to, &username_info);
+
+                            self.env().emit_event(MessageDeleted { hash: removed.hash, by: belonging_to });
+
+                            return Ok(());
+
+                        }
+
+                    }
+
+                    Err(Error::MessageNonexistent)
+
+                } else {
+
+                    Err(Error::NoMessages)
+
+                }
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+        }
+
+        /// Removes all messages that are in sotrage. This operation is not undoable, so proceed with caution.
+        #[ink(message)]
+        pub fn delete_all_messages(&mut self, username: Username) -> Result<(),Error> {
+
+            if let Some(mut username_info) = self.usernames.get(&username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                let count = self.clear_inbox(&username, &mut username_info);
+
+                self.usernames.insert(&username, &username_info);
+
+                self.env().emit_event(InboxPurged { username, count });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+        }
+
+        /// Adds the transferred value to the balance associated to your account. Registrations, renewals, purchases
+        /// and bids take whatever your payment is missing from that balance (see 'register_username' and 'buy_username').
+        /// Bids take just enough to beat the highest one.
+        #[ink(message, payable)]
+        pub fn deposit(&mut self) -> Result<Balance,Error> {
+
+            self.ensure_not_paused()?;
+
+            let caller = self.env().caller();
+
+            let amount = self.env().transferred_value();
+
+            if amount == 0 {
+
+                return Err(Error::NoBalance);
+
+            }
+
+            self.credit_balance(caller, amount)?;
+
+            self.env().emit_event(BalanceDeposited { account: caller, amount });
+
+            self.get_balance()
+
+        }
+
+        /// Attempts to send the balance associated to your account back to you.
+        #[ink(message)]
+        pub fn withdraw_balance(&mut self) -> Result<(),Error> {
+
+            if let Some(mut user_info) = self.users.get(self.env().caller()) {
+
+                if user_info.balance == 0 {
+
+                    return Err(Error::NoBalance);
+
+                }
+
+                if self.env().transfer(self.env().caller(), user_info.balance).is_err() {
+
+                    Err(Error::TransferFailed { to: self.env().caller(), amount: user_info.balance })
+
+                } else {
+
+                    self.env().emit_event(BalanceWithdrawn { account: self.env().caller(), amount: user_info.balance });
+
+                    user_info.balance = 0;
+
+                    self.store_user(&self.env().caller(), &user_info)?;
+
+                    Ok(())
+
+                }
+
+            } else {
+
+                Err(Error::NoBalance)
+
+            }
+        }
+
+        /// Attempts to send a specific amount of the balance associated to your account back to you, keeping the rest.
+        #[ink(message)]
+        pub fn withdraw(&mut self, amount: Balance) -> Result<(),Error> {
+
+            let caller = self.env().caller();
+
+            if let Some(mut user_info) = self.users.get(caller) {
+
+                if user_info.balance < amount {
+
+                    return Err(Error::InsufficientBalance);
+
+                }
+
+                if self.env().transfer(caller, amount).is_err() {
+
+                    Err(Error::TransferFailed { to: caller, amount })
+
+                } else {
+
+                    user_info.balance -= amount;
+
+                    self.store_user(&caller, &user_info)?;
+
+                    self.env().emit_event(BalanceWithdrawn { account: caller, amount });
+
+                    Ok(())
+
+                }
+
+            } else {
+
+                Err(Error::InsufficientBalance)
+
+            }
+
+        }
+
+        /// Allows another account, such as a marketplace or a bot, to move up to the specified amount out of the
+        /// balance associated to your account (see 'transfer_internal_from'). This replaces any previous allowance.
+        #[ink(message)]
+        pub fn approve_spending(&mut self, spender: AccountId, amount: Balance) -> Result<(),Error> {
+
+            let caller = self.env().caller();
+
+            if amount == 0 {
+
+                self.balance_allowances.remove((caller, spender));
+
+            } else {
+
+                self.balance_allowances.insert((caller, spender), &amount);
+
+            }
+
+            self.env().emit_event(SpendingApproved { owner: caller, spender, amount });
+
+            Ok(())
+
+        }
+
+        /// Tells you how much an account may still move out of the balance of another (see 'approve_spending').
+        #[ink(message)]
+        pub fn spending_allowance(&self, owner: AccountId, spender: AccountId) -> Balance {
+            self.balance_allowances.get((owner, spender)).unwrap_or(0)
+        }
+
+        /// Moves funds from the balance of an account that allowed you to spend them (see 'approve_spending')
+        /// to the balance of another account, within the allowance, which is reduced accordingly.
+        #[ink(message)]
+        pub fn transfer_internal_from(&mut self, owner: AccountId, to: AccountId, amount: Balance) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            Self::ensure_nonzero(to)?;
+
+            let caller = self.env().caller();
+
+            let allowance = self.spending_allowance(owner, caller);
+
+            if allowance < amount {
+
+                return Err(Error::InsufficientAllowance);
+
+            }
+
+            if let Some(mut user_info) = self.users.get(owner) {
+
+                if user_info.balance < amount {
+
+                    return Err(Error::InsufficientBalance);
+
+                }
+
+                user_info.balance -= amount;
+
+                self.store_user(&owner, &user_info)?;
+
+                self.credit_balance(to, amount)?;
+
+                if allowance == amount {
+
+                    self.balance_allowances.remove((owner, caller));
+
+                } else {
+
+                    self.balance_allowances.insert((owner, caller), &(allowance - amount));
+
+                }
+
+                self.env().emit_event(BalanceTransferred { from: owner, to, amount });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::InsufficientBalance)
+
+            }
+
+        }
+
+        /// Makes a sale offer to the specified user. The same registration fee is charged.
+        /// The offer can only be accepted during the specified duration (in milliseconds).
+        /// The price cannot exceed the maximum set by the contract owner (use 'check_max_sale_price').
+        #[ink(message)]
+        pub fn sell_username_to(&mut self, username: Username, to: AccountId, price: Balance, duration: Timestamp) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            Self::ensure_nonzero(to)?;
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                if let Some(max_sale_price) = self.max_sale_price {
+
+                    if price > max_sale_price {
+
+                        return Err(Error::PriceTooHigh);
+
+                    }
+
+                }
+
+                self.marketplace_fee(price)?;
+
+                if self.listing_position(&username).is_some() || self.auctions.contains(&username) {
+
+                    return Err(Error::UsernameAlreadyInSale);
+
+                }
+
+                if let Some(sale) = self.sales.get(&username) {
+
+                    if !self.sale_expired(&sale) {
+
+                        return Err(Error::UsernameAlreadyInSale);
+
+                    }
+
+                    self.remove_sale_offer(&username);
+
+                }
+
+                self.negotiations.remove(&username);
+
+                let expires_at = self.env().block_timestamp().saturating_add(duration);
+
+                self.env().emit_event(SaleOffered { username: username.clone(), to, price, expires_at });
+
+                self.store_sale(&Sale { username, to, price, expires_at, deposited: false });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Cancels the sale offer of the specified username.
+        #[ink(message)]
+        pub fn cancel_sale(&mut self, username: Username) -> Result<(),Error> {
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                if let Some(sale) = self.remove_sale_offer(&username) {
+
+                    self.refund_deposit(&sale)?;
+
+                    self.env().emit_event(SaleCancelled { username: sale.username });
+
+                    Ok(())
+
+                } else {
+
+                    Err(Error::UsernameNotInSale)
+
+                }
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Gets any sale propositions made to you. Expired offers are removed.
+        #[ink(message)]
+        pub fn get_sale_propositions(&mut self) -> Result<Vec<Sale>, Error> {
+
+            let caller = self.env().caller();
+
+            self.remove_expired_sales_to(caller);
+
+            let sales_to_user = self.sales_to(caller);
+
+            if sales_to_user.is_empty() {
+
+                Err(Error::NoSalesForYou)
+
+            } else {
+
+                Ok(sales_to_user)
+
+            }
+
+        }
+
+        /// Tells you how many sale offers have been made to you and have not expired.
+        #[ink(message)]
+        pub fn sale_proposition_count(&self) -> u32 {
+            self.sales_to(self.env().caller()).len() as u32
+        }
+
+        /// Attempts to make available the sale offer made to you at the specified position,
+        /// counting only offers that have not expired, in the order they were made.
+        #[ink(message)]
+        pub fn sale_proposition_at(&self, index: u32) -> Result<Sale,Error> {
+
+            let sales = self.sales_to(self.env().caller());
+
+            if sales.is_empty() {
+
+                return Err(Error::NoSalesForYou);
+
+            }
+
+            if let Some(sale) = sales.get(index as usize) {
+
+                Ok(sale.clone())
+
+            } else {
+
+                Err(Error::InvalidRange)
+
+            }
+
+        }
+
+        /// Tells you the maximum price a name can be offered for, if there is one.
+        #[ink(message)]
+        pub fn check_max_sale_price(&self) -> Option<Balance> {
+            self.max_sale_price
+        }
+
+        /// Proposes another price for a sale offer: the buyer counters the price asked by the seller, and the seller can
+        /// counter the buyer in turn, which changes the price of the offer. The seller can also accept or reject the
+        /// counter-offer of the buyer (see 'accept_counter_offer' and 'reject_counter_offer').
+        /// Offers the buyer already deposited funds for cannot be negotiated.
+        #[ink(message)]
+        pub fn counter_offer(&mut self, username: Username, new_price: Balance) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            let caller = self.env().caller();
+
+            let sale = self.negotiable_sale(&username)?;
+
+            if let Some(max_sale_price) = self.max_sale_price {
+
+                if new_price > max_sale_price {
+
+                    return Err(Error::PriceTooHigh);
+
+                }
+
+            }
+
+            self.marketplace_fee(new_price)?;
+
+            if caller == sale.to {
+
+                self.negotiations.insert(&username, &Negotiation { buyer: caller, price: new_price, by_buyer: true });
+
+            } else if self.holds(caller, &username) {
+
+                if self.get_negotiation(username.clone()).map(|negotiation| negotiation.by_buyer) != Some(true) {
+
+                    return Err(Error::NoCounterOffer);
+
+                }
+
+                self.set_sale_price(&username, new_price);
+
+                self.negotiations.insert(&username, &Negotiation { buyer: sale.to, price: new_price, by_buyer: false });
+
+            } else {
+
+                return Err(Error::NoSalesForYou);
+
+            }
+
+            self.env().emit_event(CounterOffered { username, by: caller, price: new_price });
+
+            Ok(())
+
+        }
+
+        /// Accepts the counter-offer the buyer made on the sale offer of one of your names: the offer now
+        /// stands at the price proposed by the buyer, who can buy the name at that price (see 'buy_username').
+        #[ink(message)]
+        pub fn accept_counter_offer(&mut self, username: Username) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            let sale = self.negotiable_sale(&username)?;
+
+            if !self.holds(self.env().caller(), &username) {
+
+                return Err(Error::WrongAccount(username));
+
+            }
+
+            if let Some(negotiation) = self.get_negotiation(username.clone()).filter(|negotiation| negotiation.by_buyer) {
+
+                self.set_sale_price(&username, negotiation.price);
+
+                self.negotiations.remove(&username);
+
+                self.env().emit_event(SaleOffered { username, to: sale.to, price: negotiation.price, expires_at: sale.expires_at });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NoCounterOffer)
+
+            }
+
+        }
+
+        /// Turns down the counter-offer the buyer made on the sale offer of one of your names.
+        /// The offer keeps its price.
+        #[ink(message)]
+        pub fn reject_counter_offer(&mut self, username: Username) -> Result<(),Error> {
+
+            if !self.holds(self.env().caller(), &username) {
+
+                return Err(Error::WrongAccount(username));
+
+            }
+
+            if self.get_negotiation(username.clone()).map(|negotiation| negotiation.by_buyer) != Some(true) {
+
+                return Err(Error::NoCounterOffer);
+
+            }
+
+            self.negotiations.remove(&username);
+
+            self.env().emit_event(CounterOfferRejected { username });
+
+            Ok(())
+
+        }
+
+        /// Tells you the last counter-offer made on the sale offer of a name, if the offer still stands.
+        #[ink(message)]
+        pub fn get_negotiation(&self, username: Username) -> Option<Negotiation> {
+
+            let negotiation = self.negotiations.get(&username)?;
+
+            if self.get_sale(username).ok()?.to != negotiation.buyer {
+
+                return None;
+
+            }
+
+            Some(negotiation)
+
+        }
+
+        /// Removes every sale offer made to the specified account whose acceptance window has passed, returning how many
+        /// there were. Expired offers can no longer be accepted, but they take storage until they are removed. Anyone can call this.
+        #[ink(message)]
+        pub fn purge_expired_offers(&mut self, buyer: AccountId) -> u32 {
+            self.remove_expired_sales_to(buyer)
+        }
+
+        /// Gets the sale offer of the specified username, whoever it is addressed to.
+        /// Expired offers are not returned.
+        #[ink(message)]
+        pub fn get_sale(&self, username: Username) -> Result<Sale,Error> {
+
+            if let Some(sale) = self.sales.get(&username) {
+
+                if !self.sale_expired(&sale) {
+
+                    return Ok(sale);
+
+                }
+
+            }
+
+            Err(Error::UsernameNotInSale)
+
+        }
+
+        /// Executes a sale proposed to you. The price must be paid in full; any excess is stored in your
+        /// account's balance. The marketplace fee (use 'check_fees') is kept from the price and the rest is
+        /// stored in the seller's balance. The name is given to you along with its inbox.
+        #[ink(message,payable)]
+        pub fn buy_username(&mut self, username: Username) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            let transferred = self.env().transferred_value();
+            let caller = self.env().caller();
+
+            let (sale, seller) = self.sale_offered_to(&username, caller)?;
+
+            let transferred = self.draw_balance(caller, transferred, sale.price)?;
+
+            if transferred < sale.price {
+
+                return Err(Error::PaymentFailed {
+                    received: transferred,
+                    required: sale.price,
+                    missing: sale.price - transferred,
+                });
+
+            }
+
+            self.marketplace_fee(sale.price)?;
+
+            self.remove_sale_offer(&username);
+
+            if transferred > sale.price {
+
+                self.credit_balance(caller, transferred - sale.price)?;
+
+            }
+
+            self.complete_sale(username, seller, caller, sale.price)
+
+        }
+
+        /// Executes a sale proposed to you, paying with the token accepted by the contract (use 'get_payment_token')
+        /// instead of native value. The contract must be allowed to spend the price from your token balance.
+        /// The marketplace fee (use 'check_fees') is sent to the contract owner and the rest to the seller.
+        /// The name is given to you along with its inbox.
+        #[ink(message)]
+        pub fn buy_username_with_token(&mut self, username: Username) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            let caller = self.env().caller();
+
+            let (sale, seller) = self.sale_offered_to(&username, caller)?;
+
+            let fee = self.marketplace_fee(sale.price)?;
+
+            self.remove_sale_offer(&username);
+
+            self.pull_tokens(caller, self.owner.account_id, fee)?;
+
+            let mut proceeds = sale.price - fee;
+
+            if let Some((registrant, royalty)) = self.royalty(&username, seller, sale.price)? {
+
+                self.pull_tokens(caller, registrant, royalty)?;
+
+                self.env().emit_event(RoyaltyPaid { username: username.clone(), to: registrant, amount: royalty });
+
+                proceeds -= royalty;
+
+            }
+
+            self.pull_tokens(caller, seller, proceeds)?;
+
+            self.move_username(&username, caller)?;
+
+            self.env().emit_event(SaleCompleted { username, from: seller, to: caller, price: sale.price });
+
+            Ok(())
+
+        }
+
+        /// A sale proposition made to you is cancelled.
+        #[ink(message)]
+        pub fn refuse_to_buy(&mut self, username: Username) -> Result<(),Error> {
+            
+            if self.usernames.get(&username).is_none() {
+                return Err(Error::NameNonexistent(username));
+            }
+
+            if let Some(sale) = self.sales.get(&username).filter(|sale| sale.to == self.env().caller()) {
+
+                self.remove_sale_offer(&username);
+
+                self.refund_deposit(&sale)?;
+
+                self.env().emit_event(SaleRefused { username, by: self.env().caller() });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NoSalesForYou)
+
+            }
+        }
+
+        /// Attempts to lock the price of a name offered to you, so the seller can complete the sale with 'seller_accept'.
+        /// The price must be paid in full; any excess is stored in your account's balance. Until the sale is completed,
+        /// the offer doesn't expire and the funds can be taken back with 'buyer_reclaim'.
+        #[ink(message,payable)]
+        pub fn buyer_deposit(&mut self, username: Username) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            let transferred = self.env().transferred_value();
+            let caller = self.env().caller();
+
+            if let Some(mut sale) = self.sales.get(&username) {
+
+                if sale.to == caller {
+
+                    if self.sale_expired(&sale) {
+
+                        return Err(Error::SaleExpired);
+
+                    }
+
+                    if sale.deposited {
+
+                        return Err(Error::SaleAlreadyDeposited);
+
+                    }
+
+                    let transferred = self.draw_balance(caller, transferred, sale.price)?;
+
+                    if transferred < sale.price {
+
+                        return Err(Error::PaymentFailed {
+                            received: transferred,
+                            required: sale.price,
+                            missing: sale.price - transferred,
+                        });
+
+                    }
+
+                    sale.deposited = true;
+
+                    let excess = transferred - sale.price;
+
+                    self.sales.insert(&username, &sale);
+
+                    if excess > 0 {
+
+                        self.credit_balance(caller, excess)?;
+
+                    }
+
+                    self.env().emit_event(SaleDepositChanged { username, by: caller, deposited: true });
+
+                    return Ok(());
+
+                }
+
+            }
+
+            Err(Error::NoSalesForYou)
+
+        }
+
+        /// Attempts to complete the sale of one of your names whose buyer has deposited the price.
+        /// The name is given to the buyer along with its inbox, and the price, minus the marketplace fee,
+        /// is stored in your account's balance.
+        #[ink(message)]
+        pub fn seller_accept(&mut self, username: Username) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                if let Some(sale) = self.sales.get(&username) {
+
+                    if !sale.deposited {
+
+                        return Err(Error::SaleNotDeposited);
+
+                    }
+
+                    self.ensure_transferable(&username_info)?;
+
+                    self.remove_sale_offer(&username);
+
+                    return self.complete_sale(username, username_info.account_id, sale.to, sale.price);
+
+                }
+
+                Err(Error::UsernameNotInSale)
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Attempts to take back the funds you deposited for a name offered to you, which are stored in your
+        /// account's balance. The offer stays open until it expires.
+        #[ink(message)]
+        pub fn buyer_reclaim(&mut self, username: Username) -> Result<(),Error> {
+
+            let caller = self.env().caller();
+
+            if let Some(mut sale) = self.sales.get(&username) {
+
+                if sale.to == caller {
+
+                    if !sale.deposited {
+
+                        return Err(Error::SaleNotDeposited);
+
+                    }
+
+                    sale.deposited = false;
+
+                    let price = sale.price;
+
+                    self.sales.insert(&username, &sale);
+
+                    self.credit_balance(caller, price)?;
+
+                    self.env().emit_event(SaleDepositChanged { username, by: caller, deposited: false });
+
+                    return Ok(());
+
+                }
+
+            }
+
+            Err(Error::NoSalesForYou)
+
+        }
+
+        /// Chooses which of your names your account is known by (see 'primary_of').
+        #[ink(message)]
+        pub fn set_primary_username(&mut self, name: Username) -> Result<(),Error> {
+
+            let caller = self.env().caller();
+
+            if let Some(username_info) = self.usernames.get(&name) {
+
+                if username_info.account_id != caller {
+
+                    return Err(Error::WrongAccount(name));
+
+                }
+
+                self.primary_names.insert(caller, &name);
+
+                self.env().emit_event(PrimaryUsernameSet { account: caller, username: name });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(name))
+
+            }
+
+        }
+
+        /// Tells you the name an account is known by, if it chose one and still holds it.
+        #[ink(message)]
+        pub fn primary_of(&self, account: AccountId) -> Option<Username> {
+            self.primary_names.get(account).filter(|name| self.holds(account, name))
+        }
+
+        /// Chooses the account that can take over your names and balance if you lose your key (see 'recover_account').
+        /// Any recovery of your account under way is stopped.
+        #[ink(message)]
+        pub fn set_recovery_account(&mut self, backup: AccountId) -> Result<(),Error> {
+
+            Self::ensure_nonzero(backup)?;
+
+            let caller = self.env().caller();
+
+            if backup == caller {
+
+                return Err(Error::InvalidRecoveryAccount);
+
+            }
+
+            self.recoveries.insert(caller, &Recovery { backup, started_at: None });
+
+            self.env().emit_event(RecoveryAccountSet { account: caller, backup });
+
+            Ok(())
+
+        }
+
+        /// Stops a recovery of your account that is under way, keeping your backup account.
+        #[ink(message)]
+        pub fn cancel_recovery(&mut self) -> Result<(),Error> {
+
+            let caller = self.env().caller();
+
+            if let Some(mut recovery) = self.recoveries.get(caller) {
+
+                if recovery.started_at.is_some() {
+
+                    recovery.started_at = None;
+
+                    self.recoveries.insert(caller, &recovery);
+
+                    self.env().emit_event(RecoveryCancelled { account: caller });
+
+                }
+
+            }
+
+            Ok(())
+
+        }
+
+        /// Tells you the backup account of an account, and when it started recovering it, if it did.
+        #[ink(message)]
+        pub fn get_recovery(&self, account: AccountId) -> Option<Recovery> {
+            self.recoveries.get(account)
+        }
+
+        /// Recovers an account you are the backup of (see 'set_recovery_account'). The first call starts the recovery;
+        /// once the recovery delay has passed, calling again moves every name and the balance of the account to yours.
+        /// Names that are for sale are left until their sale ends; call again to pick up whatever the account has since.
+        #[ink(message)]
+        pub fn recover_account(&mut self, lost: AccountId) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            let caller = self.env().caller();
+
+            if let Some(mut recovery) = self.recoveries.get(lost) {
+
+                if recovery.backup != caller {
+
+                    return Err(Error::NotRecoveryAccount);
+
+                }
+
+                let now = self.env().block_timestamp();
+
+                if let Some(started_at) = recovery.started_at {
+
+                    if now < started_at.saturating_add(RECOVERY_DELAY) {
+
+                        return Err(Error::RecoveryNotReady);
+
+                    }
+
+                } else {
+
+                    recovery.started_at = Some(now);
+
+                    self.recoveries.insert(lost, &recovery);
+
+                    self.env().emit_event(RecoveryStarted { account: lost, backup: caller, ready_at: now.saturating_add(RECOVERY_DELAY) });
+
+                    return Ok(());
+
+                }
+
+                let mut usernames = Vec::new();
+
+                let mut balance = 0;
+
+                if let Some(user_info) = self.users.get(lost) {
+
+                    for name in user_info.usernames.unwrap_or_default() {
+
+                        if !self.for_sale(&name) {
+
+                            self.move_username(&name, caller)?;
+
+                            usernames.push(name);
+
+                        }
+
+                    }
+
+                }
+
+                if let Some(mut user_info) = self.users.get(lost) {
+
+                    balance = user_info.balance;
+
+                    if user_info.usernames.is_none() {
+
+                        self.remove_user(&lost);
+
+                    } else {
+
+                        user_info.balance = 0;
+
+                        self.store_user(&lost, &user_info)?;
+
+                    }
+
+                    self.credit_balance(caller, balance)?;
+
+                }
+
+                // Anything the account gets afterwards can only be recovered after another delay.
+                recovery.started_at = None;
+
+                self.recoveries.insert(lost, &recovery);
+
+                self.env().emit_event(AccountRecovered { account: lost, backup: caller, usernames, balance });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NotRecoveryAccount)
+
+            }
+
+        }
+
+        /// Gives one of your names, along with its inbox, to another account, without selling it.
+        /// Names that are for sale cannot be given away.
+        #[ink(message)]
+        pub fn transfer_username(&mut self, username: Username, to: AccountId) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            Self::ensure_nonzero(to)?;
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                if self.for_sale(&username) {
+
+                    return Err(Error::UsernameAlreadyInSale);
+
+                }
+
+                self.ensure_transferable(&username_info)?;
+
+                self.move_username(&username, to)?;
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Attempts to offer one of your names to anyone at the specified price, or to change the price if it is already offered.
+        /// The name cannot be offered to a specific buyer at the same time (see 'sell_username_to').
+        #[ink(message)]
+        pub fn list_username_for_sale(&mut self, username: Username, price: Balance) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                if let Some(max_sale_price) = self.max_sale_price {
+
+                    if price > max_sale_price {
+
+                        return Err(Error::PriceTooHigh);
+
+                    }
+
+                }
+
+                self.marketplace_fee(price)?;
+
+                if self.sales.contains(&username) {
+
+                    return Err(Error::UsernameAlreadyInSale);
+
+                }
+
+                if self.auctions.contains(&username) {
+
+                    return Err(Error::UsernameAlreadyInSale);
+
+                }
+
+                let mut listings = self.listings.get().unwrap_or_default();
+
+                let listing = Listing { username: username.clone(), seller: username_info.account_id, price };
+
+                if let Some(pos) = self.listing_position(&username) {
+
+                    listings[pos] = listing;
+
+                } else {
+
+                    listings.push(listing);
+
+                }
+
+                self.listings.set(&listings);
+
+                self.env().emit_event(UsernameListed { username, price });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Attempts to stop offering one of your names to anyone.
+        #[ink(message)]
+        pub fn unlist_username(&mut self, username: Username) -> Result<(),Error> {
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                if self.remove_listing(&username).is_some() {
+
+                    self.env().emit_event(SaleCancelled { username });
+
+                    Ok(())
+
+                } else {
+
+                    Err(Error::UsernameNotInSale)
+
+                }
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Lists up to 'limit' names offered to anyone, skipping the first 'offset' ones.
+        #[ink(message)]
+        pub fn get_open_listings(&self, offset: u32, limit: u32) -> Vec<Listing> {
+            self.listings.get().unwrap_or_default()
+                .into_iter()
+                .filter(|listing| self.usernames.get(&listing.username).is_some_and(|username_info| username_info.account_id == listing.seller))
+                .skip(offset as usize)
+                .take(limit as usize)
+                .collect()
+        }
+
+        /// Buys a name offered to anyone. The price must be paid in full; any excess is stored in your
+        /// account's balance. The marketplace fee (use 'check_fees') is kept from the price and the rest is
+        /// stored in the seller's balance. The name is given to you along with its inbox.
+        #[ink(message,payable)]
+        pub fn buy_listed_username(&mut self, username: Username) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            let transferred = self.env().transferred_value();
+            let caller = self.env().caller();
+
+            if let Some(pos) = self.listing_position(&username) {
+
+                let listing = self.listings.get().unwrap_or_default().remove(pos);
+
+                if let Some(username_info) = self.usernames.get(&username) {
+
+                    // The listing is stale if the name changed hands since it was listed.
+                    if username_info.account_id != listing.seller {
+
+                        return Err(Error::UsernameNotInSale);
+
+                    }
+
+                    self.ensure_transferable(&username_info)?;
+
+                    let transferred = self.draw_balance(caller, transferred, listing.price)?;
+
+                    if transferred < listing.price {
+
+                        return Err(Error::PaymentFailed {
+                            received: transferred,
+                            required: listing.price,
+                            missing: listing.price - transferred,
+                        });
+
+                    }
+
+                    if transferred > listing.price {
+
+                        self.credit_balance(caller, transferred - listing.price)?;
+
+                    }
+
+                    self.complete_sale(username, listing.seller, caller, listing.price)
+
+                } else {
+
+                    Err(Error::NameNonexistent(username))
+
+                }
+
+            } else {
+
+                Err(Error::UsernameNotInSale)
+
+            }
+
+        }
+
+        /// Attempts to auction one of your names for the specified duration. Bids below the reserve price are refused.
+        /// Once the auction is over, anyone can settle it (use 'settle_auction').
+        #[ink(message)]
+        pub fn start_auction(&mut self, username: Username, reserve_price: Balance, duration: Timestamp) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                if let Some(max_sale_price) = self.max_sale_price {
+
+                    if reserve_price > max_sale_price {
+
+                        return Err(Error::PriceTooHigh);
+
+                    }
+
+                }
+
+                self.marketplace_fee(reserve_price)?;
+
+                if self.auctions.contains(&username) || self.listing_position(&username).is_some() {
+
+                    return Err(Error::UsernameAlreadyInSale);
+
+                }
+
+                if self.sales.contains(&username) {
+
+                    return Err(Error::UsernameAlreadyInSale);
+
+                }
+
+                let ends_at = self.env().block_timestamp().saturating_add(duration);
+
+                self.auctions.insert(&username, &Auction {
+                    seller: username_info.account_id,
+                    reserve_price,
+                    ends_at,
+                    highest_bidder: None,
+                    highest_bid: 0,
+                });
+
+                self.env().emit_event(AuctionStarted { username, reserve_price, ends_at });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Tells you the state of the auction of a name, if it is being auctioned.
+        #[ink(message)]
+        pub fn get_auction(&self, username: Username) -> Option<Auction> {
+            self.auctions.get(&username)
+        }
+
+        /// Bids the transferred amount on an auctioned name. The bid must reach the reserve price and beat the highest bid.
+        /// If you are outbid, your bid is stored in your account's balance.
+        #[ink(message,payable)]
+        pub fn place_bid(&mut self, username: Username) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            let transferred = self.env().transferred_value();
+            let caller = self.env().caller();
+
+            if let Some(mut auction) = self.auctions.get(&username) {
+
+                if self.env().block_timestamp() >= auction.ends_at {
+
+                    return Err(Error::AuctionEnded);
+
+                }
+
+                if caller == auction.seller {
+
+                    return Err(Error::WrongAccount(username));
+
+                }
+
+                let minimum_bid = if auction.highest_bidder.is_some() { auction.highest_bid.saturating_add(1) } else { auction.reserve_price };
+
+                let transferred = self.draw_balance(caller, transferred, minimum_bid)?;
+
+                if transferred < minimum_bid {
+
+                    return Err(Error::BidTooLow);
+
+                }
+
+                if let Some(outbid) = auction.highest_bidder {
+
+                    self.credit_balance(outbid, auction.highest_bid)?;
+
+                }
+
+                auction.highest_bidder = Some(caller);
+
+                auction.highest_bid = transferred;
+
+                self.auctions.insert(&username, &auction);
+
+                self.env().emit_event(BidPlaced { username, bidder: caller, amount: transferred });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::UsernameNotInSale)
+
+            }
+
+        }
+
+        /// Ends an auction that is over. The name is given to the highest bidder along with its inbox, and the seller is
+        /// paid the highest bid minus the marketplace fee (use 'check_fees'). If nobody bid, or the name changed hands
+        /// in the meantime, the seller keeps the name and the highest bid is stored in the bidder's balance.
+        #[ink(message)]
+        pub fn settle_auction(&mut self, username: Username) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            if let Some(auction) = self.auctions.get(&username) {
+
+                if self.env().block_timestamp() < auction.ends_at {
+
+                    return Err(Error::AuctionNotEnded);
+
+                }
+
+                if let Some(bidder) = auction.highest_bidder {
+
+                    if let Some(username_info) = self.usernames.get(&username) {
+
+                        if username_info.account_id == auction.seller {
+
+                            self.ensure_transferable(&username_info)?;
+
+                            self.auctions.remove(&username);
+
+                            return self.complete_sale(username, auction.seller, bidder, auction.highest_bid);
+
+                        }
+
+                    }
+
+                    self.credit_balance(bidder, auction.highest_bid)?;
+
+                }
+
+                self.auctions.remove(&username);
+
+                self.env().emit_event(SaleCancelled { username });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::UsernameNotInSale)
+
+            }
+
+        }
+
+        /// Creates a channel that accounts can join to post messages to every member. You are its first member and
+        /// can remove other members (use 'remove_from_channel'). Channel names follow the same rules as names.
+        #[ink(message)]
+        pub fn create_channel(&mut self, name: String) -> Result<(),Error> {
+
+            let caller = self.env().caller();
+
+            if !self.name_valid(&name) {
+
+                return Err(Error::InvalidName);
+
+            }
+
+            if self.channels.contains(&name) {
+
+                return Err(Error::ChannelExists);
+
+            }
+
+            self.channels.insert(&name, &Channel { creator: caller, member_count: 1, post_count: 0 });
+
+            self.channel_members.insert((&name, caller), &true);
+
+            self.env().emit_event(ChannelCreated { channel: name, creator: caller });
+
+            Ok(())
+
+        }
+
+        /// Joins a channel, unless its creator removed you from it.
+        #[ink(message)]
+        pub fn join_channel(&mut self, channel: String) -> Result<(),Error> {
+
+            let caller = self.env().caller();
+
+            if let Some(mut channel_info) = self.channels.get(&channel) {
+
+                match self.channel_members.get((&channel, caller)) {
+
+                    Some(true) => return Err(Error::AlreadyChannelMember),
+
+                    Some(false) => return Err(Error::BannedFromChannel),
+
+                    None => {}
+
+                }
+
+                channel_info.member_count += 1;
+
+                self.channels.insert(&channel, &channel_info);
+
+                self.channel_members.insert((&channel, caller), &true);
+
+                self.env().emit_event(ChannelMembershipChanged { channel, account: caller, member: true });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::ChannelNonexistent)
+
+            }
+
+        }
+
+        /// Leaves a channel you are a member of.
+        #[ink(message)]
+        pub fn leave_channel(&mut self, channel: String) -> Result<(),Error> {
+
+            let caller = self.env().caller();
+
+            self.drop_channel_member(&channel, caller, false)?;
+
+            Ok(())
+
+        }
+
+        /// Removes a member from a channel you created. They cannot join it again.
+        #[ink(message)]
+        pub fn remove_from_channel(&mut self, channel: String, member: AccountId) -> Result<(),Error> {
+
+            if let Some(channel_info) = self.channels.get(&channel) {
+
+                if channel_info.creator != self.env().caller() {
+
+                    return Err(Error::NotChannelCreator);
+
+                }
+
+                self.drop_channel_member(&channel, member, true)?;
+
+                Ok(())
+
+            } else {
+
+                Err(Error::ChannelNonexistent)
+
+            }
+
+        }
+
+        /// Tells you whether an account is a member of a channel.
+        #[ink(message)]
+        pub fn is_channel_member(&self, channel: String, account: AccountId) -> bool {
+            self.channel_members.get((&channel, account)).unwrap_or(false)
+        }
+
+        /// Posts a message to a channel you are a member of, from one of your names.
+        #[ink(message)]
+        pub fn post_to_channel(&mut self, channel: String, from: Username, content: Content) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            let caller = self.env().caller();
+
+            if let Some(username_info) = self.usernames.get(&from) {
+
+                if username_info.account_id != caller {
+
+                    return Err(Error::WrongAccount(from));
+
+                }
+
+                self.ensure_not_frozen(&from)?;
+
+                if let Some(mut channel_info) = self.channels.get(&channel) {
+
+                    if !self.channel_members.get((&channel, caller)).unwrap_or(false) {
+
+                        return Err(Error::NotChannelMember);
+
+                    }
+
+                    let index = channel_info.post_count;
+
+                    self.channel_posts.insert((&channel, index), &ChannelPost {
+                        from: from.clone(),
+                        content,
+                        timestamp: self.env().block_timestamp(),
+                    });
+
+                    channel_info.post_count += 1;
+
+                    self.channels.insert(&channel, &channel_info);
+
+                    self.env().emit_event(ChannelPosted { channel, from, index });
+
+                    Ok(())
+
+                } else {
+
+                    Err(Error::ChannelNonexistent)
+
+                }
+
+            } else {
+
+                Err(Error::NameNonexistent(from))
+
+            }
+
+        }
+
+        /// Lists up to 'limit' messages posted to a channel, oldest first, skipping the first 'offset' ones.
+        #[ink(message)]
+        pub fn get_channel_messages(&self, channel: String, offset: u64, limit: u32) -> Result<Vec<ChannelPost>,Error> {
+
+            if let Some(channel_info) = self.channels.get(&channel) {
+
+                let mut posts = Vec::new();
+
+                let end = offset.saturating_add(limit as u64).min(channel_info.post_count);
+
+                for index in offset..end {
+
+                    if let Some(post) = self.channel_posts.get((&channel, index)) {
+
+                        posts.push(post);
+
+                    }
+
+                }
+
+                Ok(posts)
+
+            } else {
+
+                Err(Error::ChannelNonexistent)
+
+            }
+
+        }
+
+        /// Attempts to close your account. Any remaining balance will be sent back to you.
+        #[ink(message)]
+        pub fn close_account(&mut self) -> Result<(),Error> {
+            if let Some(user_info) = self.users.get(self.env().caller()) {
+
+                if user_info.balance > 0 {
+
+                    if self.env().transfer(self.env().caller(), user_info.balance).is_err() {
+
+                        return Err(Error::TransferFailed { to: self.env().caller(), amount: user_info.balance });
+
+                    }
+
+                    self.env().emit_event(BalanceWithdrawn { account: self.env().caller(), amount: user_info.balance });
+
+                }
+            
+                if let Some(usernames) = user_info.usernames {
+
+                    for username in usernames.iter() {
+
+                        self.release_username(username)?;
+
+                    }
+
+                }
+
+                self.remove_user(&self.env().caller());
+
+                self.env().emit_event(AccountClosed { account: self.env().caller() });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NoAccount)
+
+            }
+        }
+
+        /// Proposes to transfer the contract ownership to another account, which must accept it (see 'accept_ownership').
+        /// A new proposal replaces the previous one. Can only be called by the current owner.
+        #[ink(message)]
+        pub fn co_propose_owner(&mut self, new_owner: AccountId) -> Result<(),Error> {
+
+            Self::ensure_nonzero(new_owner)?;
+
+            if self.env().caller() == self.owner.account_id {
+
+                self.approve_action(OwnerAction::ProposeOwner { new_owner })?;
+
+                self.pending_owner = Some(new_owner);
+
+                self.env().emit_event(OwnershipProposed { from: self.owner.account_id, to: new_owner });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NotContractOwner)
+
+            }
+
+        }
+
+        /// Accepts the contract ownership proposed to you by the current owner (see 'co_propose_owner').
+        #[ink(message)]
+        pub fn accept_ownership(&mut self) -> Result<(),Error> {
+
+            let caller = self.env().caller();
+
+            if self.pending_owner != Some(caller) {
+
+                return Err(Error::NotPendingOwner);
+
+            }
+
+            self.env().emit_event(OwnershipTransferred { from: self.owner.account_id, to: caller });
+
+            self.owner.account_id = caller;
+
+            self.pending_owner = None;
+
+            Ok(())
+
+        }
+
+        /// Tells you the account the contract ownership was proposed to, if it has not accepted it yet.
+        #[ink(message)]
+        pub fn get_pending_owner(&self) -> Option<AccountId> {
+            self.pending_owner
+        }
+
+        /// Chooses the co-owners of the contract, and how many of the owners, counting the contract owner, must approve
+        /// its sensitive actions (changing the code, the fees, fee exemptions, the payment token, the owners or the
+        /// ownership, and withdrawing or sweeping funds) before the contract owner can carry them out
+        /// (see 'co_approve_action'). A threshold of 1 needs no approvals.
+        /// Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_owners(&mut self, co_owners: Vec<AccountId>, threshold: u32) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            for co_owner in co_owners.iter() {
+
+                Self::ensure_nonzero(*co_owner)?;
+
+            }
+
+            if threshold == 0 || threshold as usize > co_owners.len() + 1 {
+
+                return Err(Error::InvalidThreshold);
+
+            }
+
+            self.approve_action(OwnerAction::SetOwners { co_owners: co_owners.clone(), threshold })?;
+
+            self.owner.co_owners = co_owners;
+
+            self.owner.threshold = threshold;
+
+            self.env().emit_event(SettingChanged { setting: "co_set_owners".into() });
+
+            Ok(())
+
+        }
+
+        /// Tells you the co-owners of the contract and how many owners must approve its sensitive actions (see 'co_set_owners').
+        #[ink(message)]
+        pub fn get_owners(&self) -> (Vec<AccountId>, u32) {
+            (self.owner.co_owners.clone(), self.owner.threshold)
+        }
+
+        /// Tells you the hash identifying an action of the contract owner, to approve it with (see 'co_approve_action').
+        #[ink(message)]
+        pub fn action_hash(&self, action: OwnerAction) -> [u8;32] {
+            self.env().hash_encoded::<Sha2x256, _>(&action)
+        }
+
+        /// Approves an action of the contract owner, identified by its hash (see 'action_hash'). The approvals
+        /// are used up when the action is carried out. Can only be called by the contract owner or a co-owner.
+        #[ink(message)]
+        pub fn co_approve_action(&mut self, action_hash: [u8;32]) -> Result<(),Error> {
+
+            let caller = self.env().caller();
+
+            if !self.is_owner(caller) {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            let mut approvals = self.action_approvals.get(action_hash).unwrap_or_default();
+
+            if !approvals.contains(&caller) {
+
+                approvals.push(caller);
+
+                self.action_approvals.insert(action_hash, &approvals);
+
+            }
+
+            self.env().emit_event(ActionApproved { action_hash, by: caller });
+
+            Ok(())
+
+        }
+
+        /// Gives a role to an account, or takes its role back with 'None'. Only moderators can be appointed this way;
+        /// the contract owner changes through 'co_propose_owner'. Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_role(&mut self, account: AccountId, role: Option<Role>) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            match role {
+
+                Some(Role::Moderator) => {
+
+                    Self::ensure_nonzero(account)?;
+
+                    self.roles.insert(account, &Role::Moderator);
+
+                },
+                Some(Role::Owner) => {
+
+                    return Err(Error::InvalidRole);
+
+                },
+                None => {
+
+                    self.roles.remove(account);
+
+                }
+
+            }
+
+            self.env().emit_event(RoleChanged { account, role });
+
+            Ok(())
+
+        }
+
+        /// Tells you the role of an account, if it has one.
+        #[ink(message)]
+        pub fn role_of(&self, account: AccountId) -> Option<Role> {
+
+            if account == self.owner.account_id {
+
+                return Some(Role::Owner);
+
+            }
+
+            self.roles.get(account)
+
+        }
+
+        /// Removes a message from the inbox of any name, for instance one reported as abusive.
+        /// Can only be called by a moderator or the contract owner.
+        #[ink(message)]
+        pub fn mod_remove_message(&mut self, belonging_to: Username, hash: [u8;32]) -> Result<(),Error> {
+
+            let caller = self.env().caller();
+
+            if self.role_of(caller).is_none() {
+
+                return Err(Error::NotModerator);
+
+            }
+
+            if let Some(mut username_info) = self.usernames.get(&belonging_to) {
+
+                if let Some(seq) = self.inbox_index.get((&belonging_to, hash)) {
+
+                    self.take_message(&belonging_to, &mut username_info, seq);
+
+                    self.usernames.insert(&belonging_to, &username_info);
+
+                    self.env().emit_event(MessageRemoved { hash, from_inbox: belonging_to, by: caller });
+
+                    Ok(())
+
+                } else {
+
+                    Err(Error::MessageNonexistent)
+
+                }
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        /// Freezes an abusive name, so it cannot send messages until it is unfrozen.
+        /// Can only be called by a moderator or the contract owner.
+        #[ink(message)]
+        pub fn mod_freeze_username(&mut self, username: Username, frozen: bool) -> Result<(),Error> {
+
+            if self.role_of(self.env().caller()).is_none() {
+
+                return Err(Error::NotModerator);
+
+            }
+
+            if !self.usernames.contains(&username) {
+
+                return Err(Error::NameNonexistent(username));
+
+            }
+
+            if frozen {
+
+                self.frozen_names.insert(&username, &true);
+
+            } else {
+
+                self.frozen_names.remove(&username);
+
+            }
+
+            self.env().emit_event(UsernameFreezeChanged { username, frozen });
+
+            Ok(())
+
+        }
+
+        /// Tells you whether a name is frozen (see 'mod_freeze_username').
+        #[ink(message)]
+        pub fn is_frozen(&self, username: Username) -> bool {
+            self.frozen_names.get(&username).unwrap_or(false)
+        }
+
+        /// Halts registrations, messages, sales and transfers, for instance during an incident or before an upgrade.
+        /// Users can still change their settings, delete messages, withdraw their balance and back out of sales.
+        /// Renewals are still accepted, so that names do not expire because of a pause. Can only be called by the
+        /// contract owner.
+        #[ink(message)]
+        pub fn co_pause(&mut self) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            self.contract_paused = true;
+
+            self.env().emit_event(SettingChanged { setting: "co_pause".into() });
+
+            Ok(())
+
+        }
+
+        /// Resumes the activity halted by 'co_pause'. Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_unpause(&mut self) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            self.contract_paused = false;
+
+            self.env().emit_event(SettingChanged { setting: "co_unpause".into() });
+
+            Ok(())
+
+        }
+
+        /// Tells you whether the contract is paused (see 'co_pause').
+        #[ink(message)]
+        pub fn is_paused(&self) -> bool {
+            self.contract_paused
+        }
+
+        /// Updated the contract code. Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_code(&mut self, code_hash: ink::primitives::Hash) -> Result<(),Error> {
+
+            if self.env().caller() == self.owner.account_id {
+
+                self.approve_action(OwnerAction::SetCode { code_hash })?;
+
+                match self.env().set_code_hash(&code_hash) {
+
+                    Ok(()) => {
+
+                        self.env().emit_event(CodeUpgraded { code_hash });
+
+                        Ok(())
+
+                    },
+                    Err(_) => {
+
+                        Err(Error::UpgradeFailed)
+
+                    }
+
+                }
+
+
+            } else {
+
+                Err(Error::NotContractOwner)
+
+            }
+
+        }
+
+        /// Sets a new value for the username registration fee. Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_fee(&mut self, new_fee: Balance) -> Result<(),Error> {
+
+            if self.env().caller() == self.owner.account_id {
+
+                self.approve_action(OwnerAction::SetFee { new_fee })?;
+
+                self.registration_fee = new_fee;
+
+                self.emit_fees_changed();
+
+                Ok(())
+
+            } else {
+
+                Err(Error::NotContractOwner)
+
+            }
+
+        }
+
+        #[ink(message)] 
+        pub fn co_get_balance(&self) -> Result<Balance,Error> {
+
+            if self.owner.account_id != self.env().caller() {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            Ok(self.owner.balance)
+        }
+
+        /// Attempts to withdraw a specific amount from the owner's balance. Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_withdraw_amount(&mut self, balance: Balance) -> Result<(),Error> {
+
+            if self.owner.account_id == self.env().caller() {
+
+                self.approve_action(OwnerAction::WithdrawAmount { balance })?;
+
+                if self.owner.balance < balance {
+
+                    return Err(Error::NotEnoughBalance);
+
+                }
+
+                if self.env().transfer(self.owner.account_id, balance).is_err() {
+
+                    Err(Error::TransferFailed { to: self.owner.account_id, amount: balance })
+
+                } else {
+
+                    self.owner.balance -= balance;
+
+                    self.env().emit_event(BalanceWithdrawn { account: self.owner.account_id, amount: balance });
+
+                    Ok(())
+
+                }
+
+            } else {
+
+                Err(Error::NotContractOwner)
+
+            }
+
+        }
+
+
+        /// Withdraw the balance stored. Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_withdraw_all_balance(&mut self) -> Result<(),Error> {
+
+            if self.owner.account_id == self.env().caller() {
+
+                if self.owner.balance > 0 {
+
+                    self.approve_action(OwnerAction::WithdrawAllBalance)?;
+
+                    if self.env().transfer(self.owner.account_id, self.owner.balance).is_err() {
+
+                        Err(Error::TransferFailed { to: self.owner.account_id, amount: self.owner.balance })
+
+                    } else {
+
+                        self.env().emit_event(BalanceWithdrawn { account: self.owner.account_id, amount: self.owner.balance });
+
+                        self.owner.balance = 0;
+
+                        Ok(())
+
+                    }
+
+                } else {
+
+                    Err(Error::NoBalance)
+
+                }
+
+            } else {
+
+                Err(Error::NotContractOwner)
+            }
+
+        }
+
+        /// Removes every message sent to the specified username and returns how many were removed.
+        /// Meant for storage emergencies. Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_purge_inbox(&mut self, username: Username) -> Result<u32,Error> {
+
+            if self.owner.account_id != self.env().caller() {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            if let Some(mut username_info) = self.usernames.get(&username) {
+
+                let count = self.clear_inbox(&username, &mut username_info);
+
+                self.usernames.insert(&username, &username_info);
+
+                self.env().emit_event(InboxPurged { username, count });
+
+                Ok(count)
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        /// Removes up to 'limit' pending sale offers, skipping the first 'offset' ones, and returns how many were removed.
+        /// Deposits made by buyers are given back. Call it again with the same offset until it returns 0 to remove
+        /// every offer from there. Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_purge_all_sales(&mut self, offset: u32, limit: u32) -> Result<u32,Error> {
+
+            if self.owner.account_id != self.env().caller() {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            let end = offset.saturating_add(limit).min(self.sale_names_len);
+
+            let mut count: u32 = 0;
+
+            // Going backwards, removing an offer only moves offers that were already passed.
+            for index in (offset..end).rev() {
+
+                if let Some(name) = self.sale_names.get(index) {
+
+                    if let Some(sale) = self.remove_sale_offer(&name) {
+
+                        self.refund_deposit(&sale)?;
+
+                        count += 1;
+
+                    }
+
+                }
+
+            }
+
+            self.env().emit_event(SalesPurged { offset, count });
+
+            Ok(count)
+
+        }
+
+        /// Whether the acceptance window of a sale offer has passed.
+        /// Offers the buyer has deposited funds for don't expire until accepted or reclaimed.
+        fn sale_expired(&self, sale: &Sale) -> bool {
+
+            !sale.deposited && self.env().block_timestamp() >= sale.expires_at
+
+        }
+
+        /// Sale offers made to the specified account that have not expired, in the order they were made.
+        fn sales_to(&self, account: AccountId) -> Vec<Sale> {
+
+            let mut sales_to_account = Vec::<Sale>::new();
+
+            for username in self.sales_to_buyer.get(account).unwrap_or_default() {
+
+                if let Some(sale) = self.sales.get(&username) {
+
+                    if !self.sale_expired(&sale) {
+
+                        sales_to_account.push(sale);
+
+                    }
+
+                }
+
+            }
+
+            sales_to_account
+
+        }
+
+        /// Sale offer of a name made to the specified buyer that can be executed now, along with the seller.
+        fn sale_offered_to(&self, username: &Username, buyer: AccountId) -> Result<(Sale, AccountId),Error> {
+
+            if let Some(sale) = self.sales.get(username) {
+
+                if sale.to != buyer {
+
+                    return Err(Error::NoSalesForYou);
+
+                }
+
+                if self.sale_expired(&sale) {
+
+                    return Err(Error::SaleExpired);
+
+                }
+
+                if sale.deposited {
+
+                    return Err(Error::SaleAlreadyDeposited);
+
+                }
+
+                if let Some(username_info) = self.usernames.get(username) {
+
+                    self.ensure_transferable(&username_info)?;
+
+                    return Ok((sale, username_info.account_id));
+
+                } else {
+
+                    return Err(Error::NameNonexistent(username.clone()));
+
+                }
+
+            }
+
+            Err(Error::NoSalesForYou)
+
+        }
+
+        /// Removes the sale offer of a name, if there is one, and returns it.
+        fn remove_sale_offer(&mut self, username: &Username) -> Option<Sale> {
+
+            let sale = self.sales.take(username)?;
+
+            if let Some(index) = self.sale_name_index.take(username) {
+
+                let last = self.sale_names_len - 1;
+
+                if index != last {
+
+                    if let Some(last_name) = self.sale_names.get(last) {
+
+                        self.sale_names.insert(index, &last_name);
+                        self.sale_name_index.insert(&last_name, &index);
+
+                    }
+
+                }
+
+                self.sale_names.remove(last);
+
+                self.sale_names_len = last;
+
+            }
+
+            let mut usernames = self.sales_to_buyer.get(sale.to).unwrap_or_default();
+
+            usernames.retain(|name| name != username);
+
+            if usernames.is_empty() {
+
+                self.sales_to_buyer.remove(sale.to);
+
+            } else {
+
+                self.sales_to_buyer.insert(sale.to, &usernames);
+
+            }
+
+            Some(sale)
+
+        }
+
+        /// Stores a new sale offer, indexed under its buyer (see 'sales_to').
+        fn store_sale(&mut self, sale: &Sale) {
+
+            let mut usernames = self.sales_to_buyer.get(sale.to).unwrap_or_default();
+
+            Self::push_unique_name(&mut usernames, sale.username.clone());
+
+            self.sales_to_buyer.insert(sale.to, &usernames);
+
+            if !self.sale_name_index.contains(&sale.username) {
+
+                self.sale_names.insert(self.sale_names_len, &sale.username);
+                self.sale_name_index.insert(&sale.username, &self.sale_names_len);
+
+                self.sale_names_len += 1;
+
+            }
+
+            self.sales.insert(&sale.username, sale);
+
+        }
+
+        /// Finds the sale offer of a name, as long as its price can still be negotiated (see 'counter_offer').
+        fn negotiable_sale(&self, username: &Username) -> Result<Sale,Error> {
+
+            if let Some(sale) = self.sales.get(username) {
+
+                if self.sale_expired(&sale) {
+
+                    return Err(Error::SaleExpired);
+
+                }
+
+                if sale.deposited {
+
+                    return Err(Error::SaleAlreadyDeposited);
+
+                }
+
+                return Ok(sale);
+
+            }
+
+            Err(Error::UsernameNotInSale)
+
+        }
+
+        /// Changes the price of the sale offer of a name.
+        fn set_sale_price(&mut self, username: &Username, price: Balance) {
+
+            if let Some(mut sale) = self.sales.get(username) {
+
+                sale.price = price;
+
+                self.sales.insert(username, &sale);
+
+            }
+
+        }
+
+        /// Moves tokens accepted by the contract from one account to another, using the allowance given to the contract.
+        fn pull_tokens(&mut self, from: AccountId, to: AccountId, amount: Balance) -> Result<(),Error> {
+
+            if let Some(token) = self.payment_token {
+
+                if amount == 0 {
+
+                    return Ok(());
+
+                }
+
+                let result = build_call::<DefaultEnvironment>()
+                    .call(token)
+                    .gas_limit(0)
+                    .exec_input(
+                        ExecutionInput::new(Selector::new(ink::selector_bytes!("PSP22::transfer_from")))
+                            .push_arg(from)
+                            .push_arg(to)
+                            .push_arg(amount)
+                            .push_arg(Vec::<u8>::new())
+                    )
+                    .returns::<Result<(), PSP22Error>>()
+                    .try_invoke();
+
+                if let Ok(Ok(Ok(()))) = result {
+
+                    Ok(())
+
+                } else {
+
+                    Err(Error::TokenTransferFailed)
+
+                }
+
+            } else {
+
+                Err(Error::PaymentTokenNotSet)
+
+            }
+
+        }
+
+        /// Takes an account out of a channel, and keeps it from joining again if it is banned.
+        fn drop_channel_member(&mut self, channel: &String, account: AccountId, ban: bool) -> Result<(),Error> {
+
+            if let Some(mut channel_info) = self.channels.get(channel) {
+
+                if !self.channel_members.get((channel, account)).unwrap_or(false) {
+
+                    return Err(Error::NotChannelMember);
+
+                }
+
+                channel_info.member_count -= 1;
+
+                self.channels.insert(channel, &channel_info);
+
+                if ban {
+
+                    self.channel_members.insert((channel, account), &false);
+
+                } else {
+
+                    self.channel_members.remove((channel, account));
+
+                }
+
+                self.env().emit_event(ChannelMembershipChanged { channel: channel.clone(), account, member: false });
+
+                Ok(())
+
+            } else {
+
+                Err(Error::ChannelNonexistent)
+
+            }
+
+        }
+
+        /// Position of a name in the list of names offered to anyone.
+        fn listing_position(&self, username: &Username) -> Option<usize> {
+            self.listings.get().unwrap_or_default().iter().position(|listing| &listing.username == username)
+        }
+
+        /// Stops offering a name to anyone, returning the listing it had.
+        fn remove_listing(&mut self, username: &Username) -> Option<Listing> {
+
+            let pos = self.listing_position(username)?;
+
+            let mut listings = self.listings.get().unwrap_or_default();
+
+            let listing = listings.remove(pos);
+
+            self.listings.set(&listings);
+
+            Some(listing)
+
+        }
+
+        /// Pays the seller the price of a name minus the marketplace fee, and gives the name to the buyer.
+        fn complete_sale(&mut self, username: Username, seller: AccountId, buyer: AccountId, price: Balance) -> Result<(),Error> {
+
+            let fee = self.marketplace_fee(price)?;
+
+            self.credit_owner(fee)?;
+
+            let mut proceeds = price - fee;
+
+            if let Some((registrant, royalty)) = self.royalty(&username, seller, price)? {
+
+                self.credit_balance(registrant, royalty)?;
+
+                self.env().emit_event(RoyaltyPaid { username: username.clone(), to: registrant, amount: royalty });
+
+                proceeds -= royalty;
+
+            }
+
+            self.credit_balance(seller, proceeds)?;
+
+            self.move_username(&username, buyer)?;
+
+            self.env().emit_event(SaleCompleted { username, from: seller, to: buyer, price });
+
+            Ok(())
+
+        }
+
+        /// Royalty owed to the first registrant of a name resold at the specified price, unless they are the seller.
+        fn royalty(&self, username: &Username, seller: AccountId, price: Balance) -> Result<Option<(AccountId, Balance)>,Error> {
+
+            if self.royalty_bps == 0 {
+
+                return Ok(None);
+
+            }
+
+            if let Some(registrant) = self.first_registrants.get(username) {
+
+                if registrant == seller {
+
+                    return Ok(None);
+
+                }
+
+                if let Some(scaled) = price.checked_mul(self.royalty_bps as Balance) {
+
+                    return Ok(Some((registrant, scaled / 10_000)));
+
+                } else {
+
+                    return Err(Error::PriceOverflow);
+
+                }
+
+            }
+
+            Ok(None)
+
+        }
+
+        /// Tells you whether a name is offered to a buyer, listed or auctioned.
+        fn for_sale(&self, username: &Username) -> bool {
+
+            self.sales.contains(username) || self.listing_position(username).is_some() || self.auctions.contains(username)
+
+        }
+
+        /// Name identified by a PSP34 token identifier.
+        fn token_name(id: &Id) -> Option<Username> {
+            if let Id::Bytes(bytes) = id {
+                String::from_utf8(bytes.clone()).ok()
+            } else {
+                None
+            }
+        }
+
+        /// Reports an error of the contract through the PSP34 interface.
+        fn psp34_error(error: Error) -> PSP34Error {
+            PSP34Error::Custom(format!("{:?}", error))
+        }
+
+        /// Gives the buyer of a sale offer back the funds they deposited for it, if any.
+        fn refund_deposit(&mut self, sale: &Sale) -> Result<(),Error> {
+
+            if sale.deposited {
+
+                self.credit_balance(sale.to, sale.price)?;
+
+            }
+
+            Ok(())
+
+        }
+
+        /// Fails if the name changed hands more recently than the transfer cooldown allows.
+        fn ensure_transferable(&self, username_info: &UsernameInfo) -> Result<(),Error> {
+
+            if let Some(last_transfer) = username_info.last_transfer {
+
+                if self.env().block_timestamp() < last_transfer.saturating_add(self.transfer_cooldown) {
+
+                    return Err(Error::TransferCooldown);
+
+                }
+
+            }
+
+            Ok(())
+
+        }
+
+        /// Tells you whether a message reached the expiry set by its sender.
+        fn message_expired(&self, message: &Message) -> bool {
+
+            if let Some(expires_at) = message.expires_at {
+
+                return self.env().block_timestamp() >= expires_at;
+
+            }
+
+            false
+
+        }
+
+        /// Tells you whether a message can be shown to its recipient: it was delivered and has not expired.
+        fn message_visible(&self, message: &Message) -> bool {
+
+            if let Some(deliver_at) = message.deliver_at {
+
+                if self.env().block_timestamp() < deliver_at {
+
+                    return false;
+
+                }
+
+            }
+
+            !self.message_expired(message)
+
+        }
+
+        /// Emits 'MessageSent' for the scheduled messages sent to a name whose time was reached, and forgets them along
+        /// with the ones that were withdrawn or deleted. Returns how many messages were announced.
+        fn announce_due(&mut self, name: &Username) -> u32 {
+
+            let scheduled = self.scheduled.get(name).unwrap_or_default();
+
+            let mut waiting = Vec::new();
+
+            let mut count: u32 = 0;
+
+            for seq in scheduled.iter() {
+
+                if let Some(message) = self.inbox.get((name, *seq)) {
+
+                    if message.deliver_at.unwrap_or(0) <= self.env().block_timestamp() {
+
+                        self.env().emit_event(MessageSent { to_hash: self.name_hash(name), message_hash: message.hash });
+
+                        count += 1;
+
+                    } else {
+
+                        waiting.push(*seq);
+
+                    }
+
+                }
+
+            }
+
+            if waiting.len() != scheduled.len() {
+
+                if waiting.is_empty() {
+
+                    self.scheduled.remove(name);
+
+                } else {
+
+                    self.scheduled.insert(name, &waiting);
+
+                }
+
+            }
+
+            count
+
+        }
+
+        /// Counts the messages sent to a name that are scheduled and not delivered yet. They are always unread.
+        fn pending_count(&self, name: &Username) -> u32 {
+
+            let mut count: u32 = 0;
+
+            for seq in self.scheduled.get(name).unwrap_or_default().into_iter() {
+
+                if let Some(message) = self.inbox.get((name, seq)) {
+
+                    if message.deliver_at.unwrap_or(0) > self.env().block_timestamp() {
+
+                        count += 1;
+
+                    }
+
+                }
+
+            }
+
+            count
+
+        }
+
+        /// Messages sent to a name that were delivered and have not expired, in the order they were received.
+        fn load_inbox(&self, name: &Username, username_info: &UsernameInfo) -> Vec<Message> {
+
+            let mut messages = Vec::new();
+
+            for seq in username_info.first_seq..username_info.next_seq {
+
+                if let Some(message) = self.inbox.get((name, seq)) {
+
+                    if self.message_visible(&message) {
+
+                        messages.push(message);
+
+                    }
+
+                }
+
+            }
+
+            messages
+
+        }
+
+        /// Message with the specified hash sent to a name, if it was delivered and has not expired.
+        fn find_message(&self, name: &Username, hash: [u8;32]) -> Option<Message> {
+
+            let seq = self.inbox_index.get((name, hash))?;
+
+            self.inbox.get((name, seq)).filter(|message| self.message_visible(message))
+
+        }
+
+        /// Sequence number of the message at the specified position of the inbox of a name, counting the messages
+        /// that are shown (see 'load_inbox').
+        fn seq_at(&self, name: &Username, username_info: &UsernameInfo, index: u32) -> Option<u64> {
+
+            let mut position: u32 = 0;
+
+            for seq in username_info.first_seq..username_info.next_seq {
+
+                if let Some(message) = self.inbox.get((name, seq)) {
+
+                    if !self.message_visible(&message) {
+
+                        continue;
+
+                    }
+
+                    if position == index {
+
+                        return Some(seq);
+
+                    }
+
+                    position += 1;
+
+                }
+
+            }
+
+            None
+
+        }
+
+        /// Stores a message at the end of the inbox of a name. The message must carry the next sequence number of the name.
+        /// The information of the name is updated but not stored.
+        fn push_message(&mut self, name: &Username, username_info: &mut UsernameInfo, message: Message) {
+
+            self.inbox.insert((name, message.seq), &message);
+            self.inbox_index.insert((name, message.hash), &message.seq);
+
+            username_info.next_seq = message.seq + 1;
+            username_info.message_count += 1;
+            username_info.unread_count += 1;
+
+        }
+
+        /// Removes a message from the inbox of a name. The information of the name is updated but not stored.
+        fn take_message(&mut self, name: &Username, username_info: &mut UsernameInfo, seq: u64) -> Option<Message> {
+
+            let message = self.inbox.take((name, seq))?;
+
+            self.inbox_index.remove((name, message.hash));
+
+            self.forget_message(name, &message);
+
+            username_info.message_count -= 1;
+
+            if message.read_at.is_none() {
+
+                username_info.unread_count -= 1;
+
+            }
+
+            if seq == username_info.first_seq {
+
+                username_info.first_seq += 1;
+
+                while username_info.first_seq < username_info.next_seq && !self.inbox.contains((name, username_info.first_seq)) {
+
+                    username_info.first_seq += 1;
+
+                }
+
+            }
+
+            Some(message)
+
+        }
+
+        /// Adds a message to the messages sent from a name (see 'get_sent_messages'), and to the messages
+        /// its recipient received from that name (see 'get_messages_from').
+        fn record_sent(&mut self, from: &Username, to: &Username, seq: u64) {
+
+            if let Some(mut username_info) = self.usernames.get(from) {
+
+                self.outbox.insert((from, username_info.sent_count), &(to.clone(), seq));
+
+                username_info.sent_count += 1;
+
+                self.usernames.insert(from, &username_info);
+
+            }
+
+            let generation = self.inbox_generations.get(to).unwrap_or(0);
+
+            let received = self.sender_counts.get((to, generation, from)).unwrap_or(0);
+
+            self.sender_index.insert((to, generation, from, received), &seq);
+
+            self.sender_counts.insert((to, generation, from), &(received + 1));
+
+        }
+
+        /// Remembers the idempotency key a message was sent with, forgetting the keys sent to the same name
+        /// whose window has passed, and the oldest ones if there are more than 'MAX_RECENT_KEYS'.
+        fn remember_key(&mut self, key: IdempotencyKey, hash: [u8;32], timestamp: Timestamp) {
+
+            let (to, from, id) = key;
+
+            let mut recent = self.recent_keys.get(&to).unwrap_or_default();
+
+            // Keys are added in the order they are seen, so the expired ones come first.
+            let expired = recent.iter().take_while(|(_, _, seen_at)| timestamp >= seen_at.saturating_add(IDEMPOTENCY_WINDOW)).count();
+
+            let evicted = expired.max((recent.len() + 1).saturating_sub(MAX_RECENT_KEYS));
+
+            for (sender, old_id, _) in recent.drain(..evicted) {
+
+                self.seen_keys.remove((&to, &sender, &old_id));
+
+            }
+
+            self.seen_keys.insert((&to, &from, &id), &(hash, timestamp));
+
+            recent.push((from, id, timestamp));
+
+            self.recent_keys.insert(&to, &recent);
+
+        }
+
+        /// Forgets every idempotency key of the messages sent to a name.
+        fn forget_keys(&mut self, name: &Username) {
+
+            for (sender, id, _) in self.recent_keys.take(name).unwrap_or_default() {
+
+                self.seen_keys.remove((name, &sender, &id));
+
+            }
+
+        }
+
+        /// Makes sure the caller holds a name that sent or received the specified message.
+        fn message_party(&self, username: &Username, hash: [u8;32]) -> Result<(),Error> {
+
+            if !self.holds(self.env().caller(), username) {
+
+                return Err(Error::WrongAccount(username.clone()));
+
+            }
+
+            if let Some((recipient, seq)) = self.message_location.get(hash) {
+
+                if let Some(message) = self.inbox.get((&recipient, seq)) {
+
+                    if &recipient == username || &message.from == username {
+
+                        return Ok(());
+
+                    }
+
+                }
+
+            }
+
+            Err(Error::MessageNonexistent)
+
+        }
+
+        /// Whether an account is the contract owner or one of its co-owners (see 'co_set_owners').
+        fn is_owner(&self, account: AccountId) -> bool {
+            account == self.owner.account_id || self.owner.co_owners.contains(&account)
+        }
+
+        /// Checks that enough owners approved an action the contract owner is carrying out, counting the
+        /// contract owner, and uses their approvals up.
+        fn approve_action(&mut self, action: OwnerAction) -> Result<(),Error> {
+
+            if self.owner.threshold <= 1 {
+
+                return Ok(());
+
+            }
+
+            let action_hash = self.action_hash(action);
+
+            let approvals = self.action_approvals.get(action_hash).unwrap_or_default();
+
+            let count = approvals.iter().filter(|account| **account != self.owner.account_id && self.is_owner(**account)).count() + 1;
+
+            if count < self.owner.threshold as usize {
+
+                return Err(Error::NotEnoughApprovals);
+
+            }
+
+            self.action_approvals.remove(action_hash);
+
+            Ok(())
+
+        }
+
+        /// Tells you whether an account holds a name.
+        fn holds(&self, account: AccountId, name: &Username) -> bool {
+            self.usernames.get(name).map(|username_info| username_info.account_id == account).unwrap_or(false)
+        }
+
+        /// Forgets what is kept about a message removed from the inbox of a name: where it was stored
+        /// (see 'get_thread') and its reactions.
+        fn forget_message(&mut self, name: &Username, message: &Message) {
+
+            if self.message_location.get(message.hash) == Some((name.clone(), message.seq)) {
+
+                self.message_location.remove(message.hash);
+
+                self.reactions.remove(message.hash);
+
+            }
+
+        }
+
+        /// Removes every message from the inbox of a name, returning how many there were.
+        /// The information of the name is updated but not stored.
+        fn clear_inbox(&mut self, name: &Username, username_info: &mut UsernameInfo) -> u32 {
+
+            let mut count: u32 = 0;
+
+            for seq in username_info.first_seq..username_info.next_seq {
+
+                if let Some(message) = self.inbox.take((name, seq)) {
+
+                    self.inbox_index.remove((name, message.hash));
+
+                    self.forget_message(name, &message);
+
+                    count += 1;
+
+                }
+
+            }
+
+            self.scheduled.remove(name);
+
+            username_info.first_seq = username_info.next_seq;
+            username_info.message_count = 0;
+            username_info.unread_count = 0;
+
+            count
+
+        }
+
+        /// Hash of a name, used to refer to the recipient in 'MessageSent' events.
+        fn name_hash(&self, name: &Username) -> [u8;32] {
+            self.env().hash_bytes::<Sha2x256>(name.as_bytes())
+        }
+
+        /// Lets everyone know about the fees now in effect.
+        fn emit_fees_changed(&self) {
+            self.env().emit_event(FeesChanged {
+                registration: self.registration_fee,
+                message: self.message_fee,
+                renewal: self.renewal_fee,
+                sale_fee_bps: self.sale_fee_bps,
+            });
+        }
+
+        /// Gives a name, along with its inbox, to another account. The name stops being offered to anyone.
+        fn move_username(&mut self, name: &Username, to: AccountId) -> Result<(),Error> {
+
+            if let Some(mut username_info) = self.usernames.get(name) {
+
+                let from = username_info.account_id;
+
+                if let Some(mut user_info) = self.users.get(username_info.account_id) {
+
+                    let mut usernames = user_info.usernames.unwrap_or_default();
+
+                    usernames.retain(|u| u != name);
+
+                    user_info.usernames = if usernames.is_empty() { None } else { Some(usernames) };
+
+                    self.store_user(&username_info.account_id, &user_info)?;
+
+                }
+
+                let mut new_owner = self.users.get(to).unwrap_or(UserInfo { usernames: None, balance: 0 });
+
+                let mut usernames = new_owner.usernames.unwrap_or_default();
+
+                Self::push_unique_name(&mut usernames, name.clone());
+
+                new_owner.usernames = Some(usernames);
+
+                self.store_user(&to, &new_owner)?;
+
+                username_info.account_id = to;
+                username_info.last_transfer = Some(self.env().block_timestamp());
+                username_info.encryption_key = None;
+
+                self.usernames.insert(name, &username_info);
+
+                self.record_holder(name, to, self.env().block_timestamp());
+
+                self.token_approvals.remove(name);
+
+                self.remove_listing(name);
+
+                self.env().emit_event(Transfer { from: Some(from), to: Some(to), id: Id::Bytes(name.as_bytes().to_vec()) });
+
+            }
+
+            Ok(())
+
+        }
+
+        /// Removes every sale offer made to an account whose acceptance window has passed, returning how many there were.
+        fn remove_expired_sales_to(&mut self, buyer: AccountId) -> u32 {
+
+            let mut removed: u32 = 0;
+
+            for username in self.sales_to_buyer.get(buyer).unwrap_or_default() {
+
+                if let Some(sale) = self.sales.get(&username) {
+
+                    if self.sale_expired(&sale) {
+
+                        self.remove_sale_offer(&username);
+
+                        removed += 1;
+
+                    }
+
+                }
+
+            }
+
+            removed
+
+        }
+
+        /// Lightweight JSON check: the content must be valid UTF-8 and its braces and brackets
+        /// (outside of string literals) must be balanced. This is not a full parser.
+        fn json_well_formed(content: &[u8]) -> bool {
+
+            let text = match core::str::from_utf8(content) {
+                Ok(text) => text,
+                Err(_) => return false,
+            };
+
+            if text.trim().is_empty() {
+
+                return false;
+
+            }
+
+            let mut open = Vec::<char>::new();
+            let mut in_string = false;
+            let mut escaped = false;
+
+            for c in text.chars() {
+
+                if in_string {
+
+                    if escaped {
+                        escaped = false;
+                    } else if c == '\\' {
+                        escaped = true;
+                    } else if c == '"' {
+                        in_string = false;
+                    }
+
+                    continue;
+
+                }
+
+                match c {
+                    '"' => in_string = true,
+                    '{' | '[' => open.push(c),
+                    '}' if open.pop() != Some('{') => return false,
+                    ']' if open.pop() != Some('[') => return false,
+                    _ => {},
+                }
+
+            }
+
+            !in_string && open.is_empty()
+
+        }
+
+        /// Sets the bulk registration discount. Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_bulk_discount(&mut self, threshold: u32, percent: u8) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            if percent > 100 {
+
+                return Err(Error::InvalidPercentage);
+
+            }
+
+            self.approve_action(OwnerAction::SetBulkDiscount { threshold, percent })?;
+
+            self.bulk_discount_threshold = threshold;
+            self.bulk_discount_percent = percent;
+
+            self.env().emit_event(SettingChanged { setting: "co_set_bulk_discount".into() });
+
+            Ok(())
+
+        }
+
+        /// Total fee for registering the specified amount of names at once.
+        fn bulk_registration_total(&self, count: u32) -> Balance {
+
+            let total = self.registration_fee.saturating_mul(count as Balance);
+
+            if count >= self.bulk_discount_threshold {
+
+                // Split in two so the multiplication cannot overflow for large totals.
+                let percent = self.bulk_discount_percent as Balance;
+                let discount = total / 100 * percent + total % 100 * percent / 100;
+
+                return total - discount;
+
+            }
+
+            total
+
+        }
+
+        /// Adds the specified amount to an account's balance, creating the account if needed.
+        fn credit_balance(&mut self, account: AccountId, amount: Balance) -> Result<(),Error> {
+
+            if let Some(mut user_info) = self.users.get(account) {
+
+                user_info.balance = Self::checked_sum(user_info.balance, amount)?;
+
+                self.store_user(&account, &user_info)?;
+
+            } else {
+
+                self.store_user(&account, &UserInfo { usernames: None, balance: amount })?;
+
+            }
+
+            Ok(())
+
+        }
+
+        /// Takes what a payment is missing to reach the required amount from the balance of the paying account,
+        /// if the balance is enough, and returns the payment completed this way. Short payments are otherwise left as they are.
+        fn draw_balance(&mut self, account: AccountId, transferred: Balance, required: Balance) -> Result<Balance,Error> {
+
+            if transferred >= required {
+
+                return Ok(transferred);
+
+            }
+
+            if let Some(mut user_info) = self.users.get(account) {
+
+                let missing = required - transferred;
+
+                if user_info.balance >= missing {
+
+                    user_info.balance -= missing;
+
+                    self.store_user(&account, &user_info)?;
+
+                    return Ok(required);
+
+                }
+
+            }
+
+            Ok(transferred)
+
+        }
+
+        /// Connects a name to an account, with an empty inbox.
+        fn assign_username(&mut self, account: AccountId, name: Username, timestamp: Timestamp) -> Result<(),Error> {
+
+            let mut user_info = self.users.get(account).unwrap_or(UserInfo { usernames: None, balance: 0 });
+
+            let mut usernames = user_info.usernames.unwrap_or_default();
+
+            Self::push_unique_name(&mut usernames, name.clone());
+
+            user_info.usernames = Some(usernames);
+
+            self.store_user(&account, &user_info)?;
+
+            let new_username_info = UsernameInfo {
+                account_id: account,
+                first_seq: 0,
+                message_count: 0,
+                fee_payment_time: timestamp,
+                inbound_fee: 0,
+                next_seq: 0,
+                metadata: None,
+                last_transfer: None,
+                blocked: Vec::new(),
+                inbox_policy: InboxPolicy::Open,
+                approved_senders: Vec::new(),
+                unread_count: 0,
+                encryption_key: None,
+                sent_count: 0,
+            };
+
+            self.usernames.insert(&name, &new_username_info);
+
+            self.record_holder(&name, account, timestamp);
+
+            self.registry_add(&name);
+
+            self.env().emit_event(Transfer { from: None, to: Some(account), id: Id::Bytes(name.as_bytes().to_vec()) });
+
+            self.env().emit_event(UsernameRegistered { username: name, account });
+
+            Ok(())
+
+        }
+
+        /// States the funds held by the contract and the sum of what it owes: the owner's balance
+        /// plus every account's balance. The first should never be less than the second.
+        /// Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_reconcile(&self) -> Result<(Balance, Balance),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            Ok((self.env().balance(), Self::checked_sum(self.owner.balance, self.total_user_balance)?))
+
+        }
+
+        /// Adds the specified amount to the contract owner's balance.
+        fn credit_owner(&mut self, amount: Balance) -> Result<(),Error> {
+
+            self.owner.balance = Self::checked_sum(self.owner.balance, amount)?;
+
+            Ok(())
+
+        }
+
+        /// Adds two balances, failing instead of wrapping around if the result doesn't fit.
+        fn checked_sum(a: Balance, b: Balance) -> Result<Balance,Error> {
+
+            if let Some(sum) = a.checked_add(b) {
+
+                Ok(sum)
+
+            } else {
+
+                Err(Error::BalanceOverflow)
+
+            }
+
+        }
+
+        /// Stores the information of an account, keeping the sum of all account balances up to date.
+        fn store_user(&mut self, account: &AccountId, user_info: &UserInfo) -> Result<(),Error> {
+
+            let previous = self.users.get(account).map(|info| info.balance).unwrap_or(0);
+
+            if let Some(total) = (self.total_user_balance - previous).checked_add(user_info.balance) {
+
+                self.total_user_balance = total;
+
+            } else {
+
+                return Err(Error::BalanceOverflow);
+
+            }
+
+            self.users.insert(account, user_info);
+
+            Ok(())
+
+        }
+
+        /// Removes an account, keeping the sum of all account balances up to date.
+        fn remove_user(&mut self, account: &AccountId) {
+
+            if let Some(user_info) = self.users.get(account) {
+
+                self.total_user_balance -= user_info.balance;
+
+            }
+
+            self.users.remove(account);
+
+        }
+
+        /// Sets which message types can be sent, as a mask where bit N allows the type with index N.
+        /// Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_allowed_types(&mut self, mask: u32) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            self.allowed_message_types = mask;
+
+            self.env().emit_event(SettingChanged { setting: "co_set_allowed_types".into() });
+
+            Ok(())
+
+        }
+
+        /// Sets how long names stay valid without renewal and the grace period that follows.
+        /// A renewal period of 0 means names never expire. When expiry is turned on, names that were paid for before
+        /// get a full renewal period from then on. Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_expiry_periods(&mut self, renewal_period: Timestamp, grace_period: Timestamp) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            if self.renewal_period == 0 && renewal_period > 0 {
+
+                self.expiry_enabled_at = self.env().block_timestamp();
+
+            }
+
+            self.renewal_period = renewal_period;
+            self.grace_period = grace_period;
+
+            self.env().emit_event(SettingChanged { setting: "co_set_expiry_periods".into() });
+
+            Ok(())
+
+        }
+
+        /// When the renewal period of a name ends. It is counted from the last payment for the name, or from the
+        /// moment expiry was turned on if that is later (see 'co_set_expiry_periods').
+        fn grace_start(&self, username_info: &UsernameInfo) -> Timestamp {
+            username_info.fee_payment_time.max(self.expiry_enabled_at).saturating_add(self.renewal_period)
+        }
+
+        /// Whether the renewal and grace periods of a name have both passed.
+        fn name_expired(&self, username_info: &UsernameInfo) -> bool {
+
+            if self.renewal_period == 0 {
+
+                return false;
+
+            }
+
+            let expiry = self.grace_start(username_info).saturating_add(self.grace_period);
+
+            self.env().block_timestamp() >= expiry
+
+        }
+
+        /// Whether a name is registered and has not expired.
+        fn name_taken(&self, name: &Username) -> bool {
+
+            if let Some(username_info) = self.usernames.get(name) {
+
+                return !self.name_expired(&username_info);
+
+            }
+
+            false
+
+        }
+
+        /// Whether the name is within the length limits, is made of allowed characters,
+        /// and doesn't start or end with whitespace.
+        fn name_valid(&self, name: &Username) -> bool {
+
+            let length = name.chars().count() as u32;
+
+            if length < self.min_name_len || length > self.max_name_len {
+
+                return false;
+
+            }
+
+            if name.trim() != name {
+
+                return false;
+
+            }
+
+            name.chars().all(|c| c.is_alphanumeric() || c == ' ' || c == '_' || c == '-' || c == '.')
+
+        }
+
+        /// Whether the name is used by the contract itself as a sender, so nobody can register it.
+        fn name_reserved(name: &Username) -> bool {
+            name == SYSTEM_NAME || name == NOTE_TO_SELF_SENDER
+        }
+
+        /// Disconnects a name from the account holding it, dropping its messages and cancelling its sales, with any
+        /// deposit or bid given back. Whoever registers it next becomes its first registrant (see 'first_registrant_of')
+        /// and gets a new inbox generation (see 'get_messages_from').
+        fn release_username(&mut self, name: &Username) -> Result<(),Error> {
+
+            if let Some(mut username_info) = self.usernames.get(name) {
+
+                self.clear_inbox(name, &mut username_info);
+
+                let generation = self.inbox_generations.get(name).unwrap_or(0);
+
+                self.inbox_generations.insert(name, &(generation + 1));
+
+                self.forget_keys(name);
+
+                if let Some(mut user_info) = self.users.get(username_info.account_id) {
+
+                    if let Some(mut usernames) = user_info.usernames {
+
+                        usernames.retain(|u| u != name);
+
+                        user_info.usernames = if usernames.is_empty() { None } else { Some(usernames) };
+
+                    }
+
+                    self.store_user(&username_info.account_id, &user_info)?;
+
+                }
+
+                self.usernames.remove(name);
+
+                self.first_registrants.remove(name);
+
+                self.frozen_names.remove(name);
+
+                self.remove_listing(name);
+
+                self.token_approvals.remove(name);
+
+                if let Some(sale) = self.remove_sale_offer(name) {
+
+                    self.refund_deposit(&sale)?;
+
+                }
+
+                if let Some(auction) = self.auctions.take(name) {
+
+                    if let Some(bidder) = auction.highest_bidder {
+
+                        self.credit_balance(bidder, auction.highest_bid)?;
+
+                    }
+
+                }
+
+                self.registry_remove(name);
+
+                self.env().emit_event(Transfer { from: Some(username_info.account_id), to: None, id: Id::Bytes(name.as_bytes().to_vec()) });
+
+            }
+
+            Ok(())
+
+        }
+
+        /// Registers names to the specified accounts without charging any fee, skipping names that are invalid,
+        /// already taken, reserved, or that don't fit in the registry anymore.
+        /// Meant for migrating state after an upgrade. Returns the amount of names imported.
+        /// Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_import_usernames(&mut self, entries: Vec<(Username, AccountId)>) -> Result<u32,Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            let timestamp = self.env().block_timestamp();
+
+            let mut imported: u32 = 0;
+
+            for (name, account) in entries.into_iter() {
+
+                if !self.name_valid(&name) || Self::name_reserved(&name) || self.name_taken(&name) || !self.registry_has_room(core::slice::from_ref(&name)) {
+
+                    continue;
+
+                }
+
+                self.release_username(&name)?;
+
+                self.assign_username(account, name, timestamp)?;
+
+                imported += 1;
+
+            }
+
+            Ok(imported)
+
+        }
+
+        /// Sets how long a chain of replies can get. A limit of 0 means there is no limit.
+        /// Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_max_reply_depth(&mut self, max_reply_depth: u32) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            self.max_reply_depth = max_reply_depth;
+
+            self.env().emit_event(SettingChanged { setting: "co_set_max_reply_depth".into() });
+
+            Ok(())
+
+        }
+
+        /// Depth of a reply to the specified message, found by following the replied-to messages through
+        /// the given inboxes. The walk stops once the depth goes over the limit.
+        fn reply_depth(&self, parent: [u8;32], inboxes: &[&Username], limit: u32) -> u32 {
+
+            let mut depth: u32 = 1;
+            let mut current = parent;
+
+            while depth <= limit {
+
+                let parent_message = inboxes.iter()
+                    .find_map(|inbox| self.find_message(inbox, current));
+
+                if let Some(Message { mtype: MessageType::ReplyTo { hash }, .. }) = parent_message {
+
+                    current = hash;
+                    depth += 1;
+
+                } else {
+
+                    return depth;
+
+                }
+
+            }
+
+            depth
+
+        }
+
+        /// Appends a holder to the history of a name, forgetting the oldest one if the history is full.
+        /// The very first holder is also kept apart, as the first registrant of the name (see 'co_set_royalty').
+        fn record_holder(&mut self, name: &Username, account: AccountId, timestamp: Timestamp) {
+
+            if !self.first_registrants.contains(name) {
+
+                self.first_registrants.insert(name, &account);
+
+            }
+
+            let mut history = self.name_history.get(name).unwrap_or_default();
+
+            if history.len() >= MAX_NAME_HISTORY {
+
+                history.remove(0);
+
+            }
+
+            history.push((account, timestamp));
+
+            self.name_history.insert(name, &history);
+
+        }
+
+        /// When the current holder of a name got it, according to the history of the name.
+        fn held_since(&self, name: &Username) -> Option<Timestamp> {
+            self.name_history.get(name)?.last().map(|(_, timestamp)| *timestamp)
+        }
+
+        /// Moves the balance of each of the specified accounts to the owner's balance, if it is below the threshold.
+        /// Returns the total amount moved. Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_sweep_dust(&mut self, accounts: Vec<AccountId>, threshold: Balance) -> Result<Balance,Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            self.approve_action(OwnerAction::SweepDust { accounts: accounts.clone(), threshold })?;
+
+            let mut swept: Balance = 0;
+
+            for account in accounts.iter() {
+
+                if let Some(mut user_info) = self.users.get(account) {
+
+                    if user_info.balance == 0 || user_info.balance >= threshold {
+
+                        continue;
+
+                    }
+
+                    let amount = user_info.balance;
+
+                    user_info.balance = 0;
+
+                    self.store_user(account, &user_info)?;
+
+                    self.credit_owner(amount)?;
+
+                    swept = Self::checked_sum(swept, amount)?;
+
+                    self.env().emit_event(DustSwept { account: *account, amount });
+
+                }
+
+            }
+
+            Ok(swept)
+
+        }
+
+        /// Hash identifying a message sent in the current block.
+        fn message_hash(&self, block: BlockNumber, seq: u64, content: &Content) -> [u8;32] {
+
+            let mut to_be_hashed = Vec::<u8>::new();
+            to_be_hashed.extend(block.to_be_bytes());
+            to_be_hashed.extend(seq.to_be_bytes());
+            to_be_hashed.extend(content.iter()); // Mayber hashing only the message content is enough?
+
+            self.env().hash_bytes::<Sha2x256>(&to_be_hashed)
+
+        }
+
+        /// Exempts an account from paying registration fees, or removes the exemption.
+        /// Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_fee_exempt(&mut self, who: AccountId, exempt: bool) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            self.approve_action(OwnerAction::SetFeeExempt { who, exempt })?;
+
+            if exempt {
+
+                self.fee_exempt.insert(who, &true);
+
+            } else {
+
+                self.fee_exempt.remove(who);
+
+            }
+
+            self.env().emit_event(SettingChanged { setting: "co_set_fee_exempt".into() });
+
+            Ok(())
+
+        }
+
+        /// Adds a name to a list of names, unless it is already there.
+        fn push_unique_name(usernames: &mut Vec<Username>, name: Username) {
+
+            if !usernames.contains(&name) {
+
+                usernames.push(name);
+
+            }
+
+        }
+
+        /// Sets the maximum price a name can be offered for, or removes the limit.
+        /// Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_max_sale_price(&mut self, max_sale_price: Option<Balance>) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            self.max_sale_price = max_sale_price;
+
+            self.env().emit_event(SettingChanged { setting: "co_set_max_sale_price".into() });
+
+            Ok(())
+
+        }
+
+        /// Marketplace fee kept from a sale at the specified price.
+        fn marketplace_fee(&self, price: Balance) -> Result<Balance,Error> {
+
+            if let Some(scaled) = price.checked_mul(self.sale_fee_bps as Balance) {
+
+                Ok(scaled / 10_000)
+
+            } else {
+
+                Err(Error::PriceOverflow)
+
+            }
+
+        }
+
+        /// Lists up to 'limit' registered names starting with the specified prefix.
+        /// This goes through every registered name, so it is costly. Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_find_names_by_prefix(&self, prefix: String, limit: u32) -> Result<Vec<Username>,Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            let mut found = Vec::<Username>::new();
+
+            for index in 0..self.registry_len {
+
+                if found.len() as u32 >= limit {
+
+                    break;
+
+                }
+
+                if let Some(name) = self.registry.get(index) {
+
+                    if name.starts_with(&prefix) {
+
+                        found.push(name);
+
+                    }
+
+                }
+
+            }
+
+            Ok(found)
+
+        }
+
+        /// Adds a name to the list of registered names, unless it is already there.
+        fn registry_add(&mut self, name: &Username) {
+
+            if self.registry_index.contains(name) {
+
+                return;
+
+            }
+
+            self.registry.insert(self.registry_len, name);
+            self.registry_index.insert(name, &self.registry_len);
+
+            self.registry_len += 1;
+
+        }
+
+        /// Whether the list of registered names can take the specified names. Names already in it,
+        /// such as expired ones being registered again, don't take any extra room.
+        fn registry_has_room(&self, names: &[Username]) -> bool {
+
+            let new_names = names.iter().filter(|name| !self.registry_index.contains(*name)).count() as u64;
+
+            self.registry_len as u64 + new_names <= self.registry_cap as u64
+
+        }
+
+        /// Removes a name from the list of registered names, moving the last name into its place.
+        fn registry_remove(&mut self, name: &Username) {
+
+            if let Some(index) = self.registry_index.get(name) {
+
+                let last = self.registry_len - 1;
+
+                if index != last {
+
+                    if let Some(last_name) = self.registry.get(last) {
+
+                        self.registry.insert(index, &last_name);
+                        self.registry_index.insert(&last_name, &index);
+
+                    }
+
+                }
+
+                self.registry.remove(last);
+                self.registry_index.remove(name);
+
+                self.registry_len = last;
+
+            }
+
+        }
+
+        /// Rejects the zero account, which nobody controls.
+        fn ensure_nonzero(account: AccountId) -> Result<(),Error> {
+
+            if account == AccountId::from([0u8;32]) {
+
+                return Err(Error::ZeroAccount);
+
+            }
+
+            Ok(())
+
+        }
+
+        /// Rejects registrations, messages, sales and transfers while the contract is paused (see 'co_pause').
+        fn ensure_not_paused(&self) -> Result<(),Error> {
+
+            if self.contract_paused {
+
+                return Err(Error::ContractPaused);
+
+            }
+
+            Ok(())
+
+        }
+
+        /// Rejects anything sent from a name frozen by a moderator (see 'mod_freeze_username').
+        fn ensure_not_frozen(&self, name: &Username) -> Result<(),Error> {
+
+            if self.frozen_names.get(name).unwrap_or(false) {
+
+                return Err(Error::UsernameFrozen(name.clone()));
+
+            }
+
+            Ok(())
+
+        }
+
+        /// Sets the maximum amount of names the contract can hold. Names already registered
+        /// are kept even if the new limit is lower, but no new names can be registered.
+        /// Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_registry_cap(&mut self, registry_cap: u32) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            self.registry_cap = registry_cap;
+
+            self.env().emit_event(SettingChanged { setting: "co_set_registry_cap".into() });
+
+            Ok(())
+
+        }
+
+        /// Transfers all the funds held by the contract to the specified account, and states the amount transferred.
+        /// Meant for moving funds to a new version of the contract before retiring this one. This ignores the balances
+        /// of the owner and of every account, so it should only be used once the contract is paused and its state migrated.
+        /// Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_drain(&mut self, to: AccountId) -> Result<Balance,Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            Self::ensure_nonzero(to)?;
+
+            self.approve_action(OwnerAction::Drain { to })?;
+
+            let amount = self.env().balance();
+
+            if amount == 0 {
+
+                return Err(Error::NoBalance);
+
+            }
+
+            if self.env().transfer(to, amount).is_err() {
+
+                return Err(Error::TransferFailed { to, amount });
+
+            }
+
+            self.env().emit_event(ContractDrained { to, amount });
+
+            Ok(amount)
+
+        }
+
+        /// Sets several fees at once; fees that are not specified are left as they are.
+        /// The sale fee is a percentage of the price, so it cannot be more than 100.
+        /// Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_fees(&mut self, registration: Option<Balance>, message: Option<Balance>, renewal: Option<Balance>, sale_percent: Option<u8>) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            self.approve_action(OwnerAction::SetFees { registration, message, renewal, sale_percent })?;
+
+            if let Some(sale_percent) = sale_percent {
+
+                if sale_percent > 100 || sale_percent as u32 * 100 + self.royalty_bps as u32 > 10_000 {
+
+                    return Err(Error::InvalidPercentage);
+
+                }
+
+                self.sale_fee_bps = sale_percent as u16 * 100;
+
+            }
+
+            if let Some(registration) = registration {
+
+                self.registration_fee = registration;
+
+            }
+
+            if let Some(message) = message {
+
+                self.message_fee = message;
+
+            }
+
+            if let Some(renewal) = renewal {
+
+                self.renewal_fee = renewal;
+
+            }
+
+            self.emit_fees_changed();
+
+            Ok(())
+
+        }
+
+        /// Sets the part of the price kept from a sale, in basis points (500 is 5%). Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_sale_fee(&mut self, bps: u16) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            if bps as u32 + self.royalty_bps as u32 > 10_000 {
+
+                return Err(Error::InvalidPercentage);
+
+            }
+
+            self.approve_action(OwnerAction::SetSaleFee { bps })?;
+
+            self.sale_fee_bps = bps;
+
+            self.emit_fees_changed();
+
+            Ok(())
+
+        }
+
+        /// Sets the part of the price of a resold name paid to its first registrant, in basis points.
+        /// Together with the sale fee (see 'check_sale_fee'), it cannot exceed the whole price.
+        /// Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_royalty(&mut self, bps: u16) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            if bps as u32 + self.sale_fee_bps as u32 > 10_000 {
+
+                return Err(Error::InvalidPercentage);
+
+            }
+
+            self.approve_action(OwnerAction::SetRoyalty { bps })?;
+
+            self.royalty_bps = bps;
+
+            self.env().emit_event(SettingChanged { setting: "co_set_royalty".into() });
+
+            Ok(())
+
+        }
+
+        /// Tells you the part of the price of a resold name paid to its first registrant, in basis points (see 'co_set_royalty').
+        #[ink(message)]
+        pub fn check_royalty(&self) -> u16 {
+            self.royalty_bps
+        }
+
+        /// Tells you the account that first registered a name.
+        #[ink(message)]
+        pub fn first_registrant_of(&self, username: Username) -> Option<AccountId> {
+            self.first_registrants.get(&username)
+        }
+
+        /// Sets how long a name must stay with its holder after changing hands before it can be sold again.
+        /// A cooldown of 0 means there is no cooldown. Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_transfer_cooldown(&mut self, transfer_cooldown: Timestamp) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            self.transfer_cooldown = transfer_cooldown;
+
+            self.env().emit_event(SettingChanged { setting: "co_set_transfer_cooldown".into() });
+
+            Ok(())
+
+        }
+
+        /// Sets the PSP22 token that can be used instead of native value to pay for registrations and sales, or stops
+        /// accepting tokens. Amounts are the same in both currencies. Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_payment_token(&mut self, token: Option<AccountId>) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            self.approve_action(OwnerAction::SetPaymentToken { token })?;
+
+            self.payment_token = token;
+
+            self.env().emit_event(SettingChanged { setting: "co_set_payment_token".into() });
+
+            Ok(())
+
+        }
+
+        /// Tells you the PSP22 token accepted for payments, if any (see 'register_username_with_token').
+        #[ink(message)]
+        pub fn get_payment_token(&self) -> Option<AccountId> {
+            self.payment_token
+        }
+
+        /// Sets the minimum and maximum length, in characters, of names registered from now on.
+        /// The minimum must be at least 1 and not more than the maximum. Can only be called by the contract owner.
+        #[ink(message)]
+        pub fn co_set_name_length(&mut self, min_len: u32, max_len: u32) -> Result<(),Error> {
+
+            if self.env().caller() != self.owner.account_id {
+
+                return Err(Error::NotContractOwner);
+
+            }
+
+            if min_len == 0 || min_len > max_len {
+
+                return Err(Error::InvalidRange);
+
+            }
+
+            self.min_name_len = min_len;
+            self.max_name_len = max_len;
+
+            self.env().emit_event(SettingChanged { setting: "co_set_name_length".into() });
+
+            Ok(())
+
+        }
+
+    }
+
+    impl crate::TransmitterApi for Transmitter {
+
+        #[ink(message)]
+        fn check_fee(&self) -> Balance {
+            self.registration_fee
+        }
+
+        #[ink(message)]
+        fn register_username(&mut self, name: String) -> Result<(),Error> {
+
+            self.ensure_not_paused()?;
+
+            let transferred = self.env().transferred_value();
+            let timestamp = self.env().block_timestamp();
+
+            if !self.name_valid(&name) {
+
+                return Err(Error::InvalidName);
+
+            }
+
+            if Self::name_reserved(&name) {
+
+                return Err(Error::NameReserved);
+
+            }
+
+            if self.name_taken(&name) {
+
+                return Err(Error::NameTaken);
+
+            }
+
+            if !self.registry_has_room(core::slice::from_ref(&name)) {
+
+                return Err(Error::RegistryFull);
+
+            }
+
+            let fee = if self.fee_exempt.get(self.env().caller()).unwrap_or(false) { 0 } else { self.registration_fee };
+
+            let transferred = self.draw_balance(self.env().caller(), transferred, fee)?;
+
+            let mut user_balance: Balance = 0;
+
+            if transferred > fee {
+
+                self.credit_owner(fee)?;
+
+                user_balance += transferred - fee;
+
+            } else if transferred < fee {
+
+                user_balance += transferred;
+
+                let new_user_info = UserInfo { usernames: None, balance: user_balance };
+
+                self.store_user(&self.env().caller(), &new_user_info)?;
+
+                return Err(Error::PaymentFailed {
+                    received: transferred,
+                    required: fee,
+                    missing:  fee - transferred
+                });
+
+            } else {
+
+                self.credit_owner(transferred)?;
+
+            }
+
+            self.release_username(&name)?;
+
+            self.record_holder(&name, self.env().caller(), timestamp);
+
+            self.registry_add(&name);
+
+            self.env().emit_event(Transfer { from: None, to: Some(self.env().caller()), id: Id::Bytes(name.as_bytes().to_vec()) });
+
+            self.env().emit_event(UsernameRegistered { username: name.clone(), account: self.env().caller() });
+
+            if let Some(user_info) = self.users.get(self.env().caller()) {
+
+                let mut usernames = Vec::new();
+
+                if let Some(existing) = user_info.usernames {
+                    usernames = existing;
+                }
+
+                Self::push_unique_name(&mut usernames, name.clone());
+
+                let balance = Self::checked_sum(user_info.balance, user_balance)?;
+
+                let new_user_info = UserInfo {
+                    usernames: Some(usernames),
+                    balance,
+                };
+
+                self.store_user(&self.env().caller(), &new_user_info)?;
+
+
+                let new_username_info = UsernameInfo {
+                    account_id: self.env().caller(),
+                    first_seq: 0,
+                    message_count: 0,
+                    fee_payment_time: timestamp,
+                    inbound_fee: 0,
+                    next_seq: 0,
+                    metadata: None,
+                    last_transfer: None,
+                    blocked: Vec::new(),
+                    inbox_policy: InboxPolicy::Open,
+                    approved_senders: Vec::new(),
+                    unread_count: 0,
+                    encryption_key: None,
+                    sent_count: 0,
+                };
+
+                self.usernames.insert(&name, &new_username_info);
+
+                Ok(())
+
+            } else {
+
+
+                let mut usernames = Vec::<Username>::new();
+
+                Self::push_unique_name(&mut usernames, name.clone());
+
+                let new_user_info = UserInfo { usernames: Some(usernames), balance: user_balance };
+
+                self.store_user(&self.env().caller(), &new_user_info)?;
+
+
+                let new_username_info = UsernameInfo {
+                    account_id: self.env().caller(),
+                    first_seq: 0,
+                    message_count: 0,
+                    fee_payment_time: timestamp,
+                    inbound_fee: 0,
+                    next_seq: 0,
+                    metadata: None,
+                    last_transfer: None,
+                    blocked: Vec::new(),
+                    inbox_policy: InboxPolicy::Open,
+                    approved_senders: Vec::new(),
+                    unread_count: 0,
+                    encryption_key: None,
+                    sent_count: 0,
+                };
+
+                self.usernames.insert(&name, &new_username_info);
+
+                Ok(())
+
+            }
+
+        }
+
+        #[ink(message)]
+        fn resolve(&self, username: Username) -> Result<AccountId,Error> {
+
+            if let Some(username_info) = self.usernames.get(&username) {
+
+                if self.name_expired(&username_info) {
+
+                    return Err(Error::NameExpired);
+
+                }
+
+                Ok(username_info.account_id)
+
+            } else {
+
+                Err(Error::NameNonexistent(username))
+
+            }
+
+        }
+
+        #[ink(message)]
+        fn send_message(&mut self, from: Username, to: Username, mtype: MessageType, content: Content) -> Result<(),Error> {
+
+            self.deliver_message(from, to, mtype, content, None, SendOptions::default())?;
+
+            Ok(())
+
+        }
+
+        #[ink(message)]
+        fn send_message_from_contract(&mut self, from: Username, to: Username, mtype: MessageType, content: Content) -> Result<[u8;32],Error> {
+
+            self.deliver_message(from, to, mtype, content, None, SendOptions::default())
+
+        }
+
+        #[ink(message)]
+        fn get_all_messages(&self, belonging_to: Username) -> Result<Vec<Message>,Error> {
+            
+            if let Some(username_info) = self.usernames.get(&belonging_to) {
+
+                if self.env().caller() != username_info.account_id {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                let messages = self.load_inbox(&belonging_to, &username_info);
+
+                if messages.is_empty() {
+
+                    return Err(Error::NoMessages);
+
+                }
+
+                Ok(messages)
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+
+        }
+
+        #[ink(message)]
+        fn delete_message(&mut self, belonging_to: Username, hash: [u8;32]) -> Result<(),Error> {
+
+            if let Some(mut username_info) = self.usernames.get(&belonging_to) {
+
+                if username_info.account_id != self.env().caller() {
+
+                    return Err(Error::WrongAccount(belonging_to));
+
+                }
+
+                if username_info.message_count > 0 {
+
+                    if let Some(message) = self.find_message(&belonging_to, hash) {
+
+                        self.take_message(&belonging_to, &mut username_info, message.seq);
+
+                        self.usernames.insert(&belonging_to, &username_info);
+
+                        self.env().emit_event(MessageDeleted { hash, by: belonging_to });
+
+                        Ok(())
+
+                    } else {
+
+                        Err(Error::MessageNonexistent)
+
+                    }
+
+                } else {
+                    
+                    Err(Error::NoMessages)
+
+                }
+
+            } else {
+
+                Err(Error::NameNonexistent(belonging_to))
+
+            }
+        }
+
+    }
+
+    impl crate::PSP34 for Transmitter {
+
+        #[ink(message)]
+        fn collection_id(&self) -> Id {
+            Id::Bytes(AsRef::<[u8]>::as_ref(&self.env().account_id()).to_vec())
+        }
+
+        #[ink(message)]
+        fn balance_of(&self, owner: AccountId) -> u32 {
+            if let Some(user_info) = self.users.get(owner) {
+                user_info.usernames.map(|usernames| usernames.len() as u32).unwrap_or(0)
+            } else {
+                0
+            }
+        }
+
+        #[ink(message)]
+        fn owner_of(&self, id: Id) -> Option<AccountId> {
+            Self::token_name(&id)
+                .and_then(|name| self.usernames.get(&name))
+                .map(|username_info| username_info.account_id)
+        }
+
+        #[ink(message)]
+        fn allowance(&self, owner: AccountId, operator: AccountId, id: Option<Id>) -> bool {
+            if self.operator_approvals.contains((owner, operator)) {
+                return true;
+            }
+            if let Some(name) = id.as_ref().and_then(Self::token_name) {
+                return self.token_approvals.get(&name) == Some(operator) && self.owner_of(Id::Bytes(name.into_bytes())) == Some(owner);
+            }
+            false
+        }
+
+        /// Allows or forbids an operator to transfer one of your names, or all of them if no name is specified.
+        #[ink(message)]
+        fn approve(&mut self, operator: AccountId, id: Option<Id>, approved: bool) -> Result<(), PSP34Error> {
+
+            let caller = self.env().caller();
+
+            if operator == caller {
+
+                return Err(PSP34Error::SelfApprove);
+
+            }
+
+            if let Some(token) = &id {
+
+                if let Some(name) = Self::token_name(token) {
+
+                    if let Some(username_info) = self.usernames.get(&name) {
+
+                        if username_info.account_id != caller {
+
+                            return Err(PSP34Error::NotApproved);
+
+                        }
+
+                        if approved {
+
+                            self.token_approvals.insert(&name, &operator);
+
+                        } else {
+
+                            self.token_approvals.remove(&name);
+
+                        }
+
+                    } else {
+
+                        return Err(PSP34Error::TokenNotExists);
+
+                    }
+
+                } else {
+
+                    return Err(PSP34Error::TokenNotExists);
+
+                }
+
+            } else if approved {
+
+                self.operator_approvals.insert((caller, operator), &());
+
+            } else {
+
+                self.operator_approvals.remove((caller, operator));
+
+            }
+
+            self.env().emit_event(Approval { owner: caller, operator, id, approved });
+
+            Ok(())
+
+        }
+
+        /// Transfers a name, along with its inbox, to another account. Names that are for sale cannot be transferred.
+        #[ink(message)]
+        fn transfer(&mut self, to: AccountId, id: Id, _data: Vec<u8>) -> Result<(), PSP34Error> {
+
+            self.ensure_not_paused().map_err(Self::psp34_error)?;
+
+            let caller = self.env().caller();
+
+            if let Some(name) = Self::token_name(&id) {
+
+                if let Some(username_info) = self.usernames.get(&name) {
+
+                    if !self.allowance(username_info.account_id, caller, Some(id)) && username_info.account_id != caller {
+
+                        return Err(PSP34Error::NotApproved);
+
+                    }
+
+                    if self.for_sale(&name) {
+
+                        return Err(PSP34Error::Custom(String::from("UsernameAlreadyInSale")));
+
+                    }
+
+                    Self::ensure_nonzero(to).map_err(Self::psp34_error)?;
+
+                    self.ensure_transferable(&username_info).map_err(Self::psp34_error)?;
+
+                    self.move_username(&name, to).map_err(Self::psp34_error)?;
+
+                    return Ok(());
+
+                }
+
+            }
+
+            Err(PSP34Error::TokenNotExists)
+
+        }
+
+        #[ink(message)]
+        fn total_supply(&self) -> u128 {
+            self.registry_len as u128
+        }
+
+    }
+
+
+    // WARNING: Tests are incomplete. I chose to test manually using 'contracts-ui.substrate.io'
+
+    #[cfg(test)]
+    mod tests {
+
+        use super::*;
+
+        use ink::env::{test, DefaultEnvironment};
+
+        type Event = <Transmitter as ink::reflect::ContractEventBase>::Type;
+
+        fn accounts() -> test::DefaultAccounts<DefaultEnvironment> {
+            test::default_accounts::<DefaultEnvironment>()
+        }
+
+        fn set_caller(caller: AccountId) {
+            test::set_caller::<DefaultEnvironment>(caller);
+        }
+
+        fn set_value(value: Balance) {
+            test::set_value_transferred::<DefaultEnvironment>(value);
+        }
+
+        /// Like 'set_value', but also moves the funds from the caller to the contract.
+        fn pay(value: Balance) {
+            test::transfer_in::<DefaultEnvironment>(value);
+        }
+
+        /// Instantiates the contract from alice's account, so alice is the contract owner.
+        fn deploy() -> Transmitter {
+            test::set_callee::<DefaultEnvironment>(AccountId::from([0xff;32]));
+            set_caller(accounts().alice);
+            Transmitter::new()
+        }
+
+        /// Registers a name for the given account, paying exactly the registration fee.
+        fn register(transmitter: &mut Transmitter, caller: AccountId, name: &str) {
+            set_caller(caller);
+            set_value(transmitter.check_fee());
+            transmitter.register_username(name.into()).expect("registration failed");
+            set_value(0);
+        }
+
+        fn last_event() -> Event {
+            let event = test::recorded_events().last().expect("no event was emitted");
+            <Event as scale::Decode>::decode(&mut &event.data[..]).expect("invalid event data")
+        }
+
+        fn send_text(transmitter: &mut Transmitter, caller: AccountId, from: &str, to: &str, content: &str) {
+            set_caller(caller);
+            transmitter.send_message(from.into(), to.into(), MessageType::Text, content.into()).expect("send failed");
+        }
+
+        /// We test a simple use case of our contract.
+        #[ink::test]
+        fn it_works() {
+
+            
+        }
+
+        #[ink::test]
+        fn owner_can_purge_inbox() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "one");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "two");
+
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_purge_inbox("Bob".into()), Ok(2));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_all_messages("Bob".into()).err(), Some(Error::NoMessages));
+
+            set_caller(accounts.bob);
+            transmitter.sell_username_to("Bob".into(), accounts.django, 10, 1_000).unwrap();
+            set_caller(accounts.charlie);
+            transmitter.sell_username_to("Charlie".into(), accounts.django, 10, 1_000).unwrap();
+
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_purge_all_sales(1, 10), Ok(1));
+            assert!(matches!(last_event(), Event::SalesPurged(event) if event.offset == 1 && event.count == 1));
+            assert_eq!(transmitter.co_purge_all_sales(0, 10), Ok(1));
+            assert_eq!(transmitter.co_purge_all_sales(0, 10), Ok(0));
+            assert!(transmitter.get_sale("Bob".into()).is_err());
+        }
+
+        #[ink::test]
+        fn non_owner_cannot_purge() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.co_purge_inbox("Bob".into()), Err(Error::NotContractOwner));
+            assert_eq!(transmitter.co_purge_all_sales(0, 10), Err(Error::NotContractOwner));
+        }
+
+        #[ink::test]
+        fn delete_message_at_shifts_neighbours() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "first");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "second");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "third");
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.delete_message_at("Bob".into(), 1), Err(Error::WrongAccount("Bob".into())));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.delete_message_at("Bob".into(), 3), Err(Error::MessageNonexistent));
+            assert_eq!(transmitter.delete_message_at("Bob".into(), 1), Ok(()));
+
+            let messages = transmitter.get_all_messages("Bob".into()).unwrap_or_default();
+            assert_eq!(messages.len(), 2);
+            assert_eq!(messages[0].content, Content::from("first"));
+            assert_eq!(messages[1].content, Content::from("third"));
+        }
+
+        #[ink::test]
+        fn sale_offers_expire() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.bob, "Sponge");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            test::set_block_timestamp::<DefaultEnvironment>(1_000);
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.sell_username_to("Bob".into(), accounts.charlie, 10, 500), Ok(()));
+            assert_eq!(transmitter.sell_username_to("Sponge".into(), accounts.charlie, 10, 500), Ok(()));
+
+            // Before the expiry the offer can be executed.
+            test::set_block_timestamp::<DefaultEnvironment>(1_499);
+            set_caller(accounts.charlie);
+            set_value(10);
+            assert_eq!(transmitter.buy_username("Bob".into()), Ok(()));
+
+            // Afterwards it is rejected and no longer listed.
+            test::set_block_timestamp::<DefaultEnvironment>(1_500);
+            assert_eq!(transmitter.buy_username("Sponge".into()), Err(Error::SaleExpired));
+            set_value(0);
+            assert_eq!(transmitter.get_sale_propositions().err(), Some(Error::NoSalesForYou));
+            assert_eq!(transmitter.sales.get(String::from("Sponge")), None);
+            assert_eq!(transmitter.sales_to_buyer.get(accounts.charlie), None);
+        }
+
+        #[ink::test]
+        fn json_messages_are_checked() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            set_caller(accounts.charlie);
+
+            let valid = r#"{"list": [1, 2, {"text": "a } in a string"}]}"#;
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Json, valid.into()), Ok(()));
+
+            for malformed in [r#"{"list": [1, 2}"#, "{", "]", r#"{"open": "string}"#, ""] {
+                assert_eq!(
+                    transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Json, malformed.into()),
+                    Err(Error::InvalidJson)
+                );
+            }
+
+            let invalid_utf8: Content = vec![b'{', 0xff, b'}'];
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Json, invalid_utf8), Err(Error::InvalidJson));
+        }
+
+        #[ink::test]
+        fn bulk_registration_is_discounted() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+            assert_eq!(transmitter.co_set_fee(10), Ok(()));
+
+            let names: Vec<String> = ["a1", "a2", "a3", "a4", "a5"].iter().map(|n| n.to_string()).collect();
+
+            set_caller(accounts.bob);
+            set_value(50);
+            assert_eq!(transmitter.register_usernames(names.clone()), Ok(()));
+            set_value(0);
+
+            // 10% off the 50 total: 45 goes to the owner, the remaining 5 to bob's balance.
+            assert_eq!(transmitter.get_usernames(), Ok(names));
+            assert_eq!(transmitter.get_balance(), Ok(5));
+            assert_eq!(transmitter.owner.balance, 45);
+
+            // Below the threshold there is no discount.
+            set_value(20);
+            assert_eq!(transmitter.register_usernames(vec!["b1".into(), "b2".into()]), Ok(()));
+            assert_eq!(transmitter.owner.balance, 65);
+
+            // A taken name rejects the whole batch.
+            assert_eq!(
+                transmitter.register_usernames(vec!["c1".into(), "a1".into()]),
+                Err(Error::NamesRefused(vec![(String::from("a1"), Error::NameTaken)]))
+            );
+            set_value(0);
+            assert_eq!(transmitter.get_usernames().map(|names| names.len()), Ok(7));
+
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_set_bulk_discount(2, 101), Err(Error::InvalidPercentage));
+        }
+
+        #[ink::test]
+        fn get_sale_reads_offer_back() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_sale("Bob".into()), Err(Error::UsernameNotInSale));
+            assert_eq!(transmitter.sell_username_to("Bob".into(), accounts.charlie, 42, 1_000), Ok(()));
+
+            set_caller(accounts.django);
+            assert_eq!(
+                transmitter.get_sale("Bob".into()),
+                Ok(Sale { username: "Bob".into(), to: accounts.charlie, price: 42, expires_at: 1_000, deposited: false })
+            );
+            assert_eq!(transmitter.get_sale("Nobody".into()), Err(Error::UsernameNotInSale));
+        }
+
+        #[ink::test]
+        fn signature_is_stored_verbatim() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            let mut signature = [0u8;64];
+            signature[0] = 1;
+            signature[63] = 255;
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.send_message_signed("Charlie".into(), "Bob".into(), MessageType::Text, "signed".into(), signature), Ok(()));
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, "unsigned".into()), Ok(()));
+
+            set_caller(accounts.bob);
+            let messages = transmitter.get_all_messages("Bob".into()).unwrap_or_default();
+            assert_eq!(messages[0].signature, Some(Signature(signature)));
+            assert_eq!(messages[1].signature, None);
+        }
+
+        #[ink::test]
+        fn reconcile_holds_after_deposits_and_withdrawals() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            // Overpaying, underpaying and exact payments.
+            set_caller(accounts.bob);
+            pay(5);
+            assert_eq!(transmitter.register_username("Bob".into()), Ok(()));
+            set_caller(accounts.charlie);
+            pay(0);
+            assert!(transmitter.register_username("Charlie".into()).is_err());
+            pay(1);
+            assert_eq!(transmitter.register_username("Charlie".into()), Ok(()));
+            set_value(0);
+
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_reconcile(), Ok((6, 6)));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.withdraw_balance(), Ok(()));
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_withdraw_amount(1), Ok(()));
+            assert_eq!(transmitter.co_reconcile(), Ok((1, 1)));
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.close_account(), Ok(()));
+            assert_eq!(transmitter.co_reconcile(), Err(Error::NotContractOwner));
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_reconcile(), Ok((1, 1)));
+        }
+
+        #[ink::test]
+        fn disallowed_message_types_are_rejected() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.co_set_allowed_types(0), Err(Error::NotContractOwner));
+
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_set_allowed_types(!(1 << MessageType::Json.index())), Ok(()));
+
+            set_caller(accounts.charlie);
+            assert_eq!(
+                transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Json, "{}".into()),
+                Err(Error::MessageTypeNotAllowed)
+            );
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, "hi".into()), Ok(()));
+        }
+
+        #[ink::test]
+        fn exported_inbox_decodes_into_messages() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "one");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "two");
+
+            assert_eq!(transmitter.export_inbox("Bob".into()), Err(Error::WrongAccount("Bob".into())));
+
+            set_caller(accounts.bob);
+            let blob = transmitter.export_inbox("Bob".into()).expect("export failed");
+            let decoded = <Vec<Message> as scale::Decode>::decode(&mut &blob[..]).expect("decoding failed");
+
+            assert_eq!(Ok(decoded), transmitter.get_all_messages("Bob".into()));
+        }
+
+        #[ink::test]
+        fn names_go_through_grace_before_expiring() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+            assert_eq!(transmitter.co_set_expiry_periods(1_000, 500), Ok(()));
+
+            test::set_block_timestamp::<DefaultEnvironment>(0);
+            register(&mut transmitter, accounts.bob, "Bob");
+
+            // Active.
+            test::set_block_timestamp::<DefaultEnvironment>(999);
+            assert!(!transmitter.is_in_grace("Bob".into()));
+
+            // In grace: nobody else can take it, but the holder can renew it.
+            test::set_block_timestamp::<DefaultEnvironment>(1_200);
+            assert!(transmitter.is_in_grace("Bob".into()));
+            set_caller(accounts.charlie);
+            set_value(1);
+            assert_eq!(transmitter.register_username("Bob".into()), Err(Error::NameTaken));
+            assert_eq!(transmitter.renew_username("Bob".into()), Err(Error::WrongAccount("Bob".into())));
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.renew_username("Bob".into()), Ok(()));
+            assert!(!transmitter.is_in_grace("Bob".into()));
+
+            // Expired: the holder can no longer renew and anyone can register it.
+            test::set_block_timestamp::<DefaultEnvironment>(2_700);
+            assert!(!transmitter.is_in_grace("Bob".into()));
+            assert_eq!(transmitter.renew_username("Bob".into()), Err(Error::NameExpired));
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.register_username("Bob".into()), Ok(()));
+            set_value(0);
+
+            assert_eq!(transmitter.get_usernames(), Ok(vec!["Bob".into()]));
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_usernames(), Err(Error::NoNames));
+        }
+
+        #[ink::test]
+        fn expired_names_lose_their_sales() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+            assert_eq!(transmitter.co_set_expiry_periods(1_000, 500), Ok(()));
+
+            test::set_block_timestamp::<DefaultEnvironment>(0);
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.bob, "Sponge");
+
+            set_caller(accounts.bob);
+            transmitter.sell_username_to("Bob".into(), accounts.django, 10, 1_000_000).unwrap();
+            transmitter.start_auction("Sponge".into(), 5, 1_000).unwrap();
+            set_caller(accounts.django);
+            set_value(20);
+            transmitter.place_bid("Sponge".into()).unwrap();
+            set_value(0);
+
+            test::set_block_timestamp::<DefaultEnvironment>(2_000);
+            register(&mut transmitter, accounts.charlie, "Bob");
+            register(&mut transmitter, accounts.charlie, "Sponge");
+
+            set_caller(accounts.django);
+            set_value(10);
+            assert_eq!(transmitter.buy_username("Bob".into()), Err(Error::NoSalesForYou));
+            set_value(0);
+            assert_eq!(transmitter.settle_auction("Sponge".into()), Err(Error::UsernameNotInSale));
+            assert_eq!(transmitter.get_balance(), Ok(20));
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.transfer_username("Bob".into(), accounts.eve), Ok(()));
+            assert_eq!(transmitter.resolve("Sponge".into()), Ok(accounts.charlie));
+        }
+
+        #[ink::test]
+        fn approvals_end_when_names_expire() {
+
+            use crate::PSP34;
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+            assert_eq!(transmitter.co_set_expiry_periods(1_000, 500), Ok(()));
+            let bob = Id::Bytes("Bob".as_bytes().to_vec());
+
+            test::set_block_timestamp::<DefaultEnvironment>(0);
+            register(&mut transmitter, accounts.bob, "Bob");
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.approve(accounts.django, Some(bob.clone()), true), Ok(()));
+
+            test::set_block_timestamp::<DefaultEnvironment>(2_000);
+            register(&mut transmitter, accounts.charlie, "Bob");
+
+            set_caller(accounts.django);
+            assert!(!transmitter.allowance(accounts.charlie, accounts.django, Some(bob.clone())));
+            assert_eq!(transmitter.transfer(accounts.django, bob, Vec::new()), Err(PSP34Error::NotApproved));
+            assert_eq!(transmitter.resolve("Bob".into()), Ok(accounts.charlie));
+        }
+
+        #[ink::test]
+        fn forwarded_message_keeps_content() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            register(&mut transmitter, accounts.django, "Django");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "pass it on");
+
+            set_caller(accounts.bob);
+            let hash = transmitter.get_all_messages("Bob".into()).unwrap_or_default()[0].hash;
+            assert_eq!(transmitter.forward_message("Bob".into(), [0u8;32], "Django".into()), Err(Error::MessageNonexistent));
+            let forwarded = transmitter.forward_message("Bob".into(), hash, "Django".into()).expect("forwarding failed");
+
+            set_caller(accounts.django);
+            let messages = transmitter.get_all_messages("Django".into()).unwrap_or_default();
+            assert_eq!(messages.len(), 1);
+            assert_eq!(messages[0].hash, forwarded);
+            assert_eq!(messages[0].from, "Bob");
+            assert_eq!(messages[0].mtype, MessageType::Forwarded { original_hash: hash, original_from: "Charlie".into() });
+            assert_eq!(messages[0].content, Content::from("pass it on"));
+
+            assert_eq!(transmitter.forward_message("Bob".into(), hash, "Charlie".into()), Err(Error::WrongAccount("Bob".into())));
+        }
+
+        #[ink::test]
+        fn messages_can_require_acknowledgement() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "no ack needed");
+
+            set_caller(accounts.charlie);
+            let options = SendOptions { requires_ack: true, ..Default::default() };
+            assert!(transmitter.send_message_with_options("Charlie".into(), "Bob".into(), MessageType::Text, "ack me".into(), options).is_ok());
+
+            set_caller(accounts.bob);
+            let messages = transmitter.get_all_messages("Bob".into()).unwrap_or_default();
+            let (plain, hash) = (messages[0].hash, messages[1].hash);
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.is_acknowledged("Bob".into(), hash), Ok(false));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.acknowledge("Bob".into(), plain), Err(Error::AcknowledgementNotRequired));
+            assert_eq!(transmitter.acknowledge("Bob".into(), hash), Ok(()));
+
+            match last_event() {
+                Event::MessageAcknowledged(event) => {
+                    assert_eq!(event.hash, hash);
+                    assert_eq!(event.by, "Bob");
+                },
+                _ => panic!("expected a MessageAcknowledged event"),
+            }
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.is_acknowledged("Bob".into(), hash), Ok(true));
+            set_caller(accounts.django);
+            assert_eq!(transmitter.is_acknowledged("Bob".into(), hash), Err(Error::WrongAccount("Bob".into())));
+        }
+
+        #[ink::test]
+        fn import_skips_taken_names() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+
+            let entries: Vec<(Username, AccountId)> = vec![
+                ("Bob".into(), accounts.charlie),
+                ("Charlie".into(), accounts.charlie),
+                ("Django".into(), accounts.django),
+            ];
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.co_import_usernames(entries.clone()), Err(Error::NotContractOwner));
+
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_import_usernames(entries.clone()), Ok(2));
+            assert_eq!(transmitter.co_import_usernames(entries), Ok(0));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_usernames(), Ok(vec!["Bob".into()]));
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.get_usernames(), Ok(vec!["Charlie".into()]));
+            set_caller(accounts.django);
+            assert_eq!(transmitter.get_usernames(), Ok(vec!["Django".into()]));
+        }
+
+        #[ink::test]
+        fn balance_or_zero_does_not_need_an_account() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.balance_or_zero(), 0);
+            assert_eq!(transmitter.get_balance(), Err(Error::NoAccount));
+
+            set_value(4);
+            assert_eq!(transmitter.register_username("Bob".into()), Ok(()));
+            set_value(0);
+            assert_eq!(transmitter.balance_or_zero(), 3);
+        }
+
+        #[ink::test]
+        fn inbound_fee_is_paid_to_recipient() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.set_inbound_fee("Bob".into(), 5), Err(Error::WrongAccount("Bob".into())));
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.set_inbound_fee("Bob".into(), 5), Ok(()));
+            assert_eq!(transmitter.check_inbound_fee("Bob".into()), Ok(5));
+
+            set_caller(accounts.charlie);
+            set_value(4);
+            assert_eq!(
+                transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, "cheap".into()),
+                Err(Error::PaymentFailed { received: 4, required: 5, missing: 1 })
+            );
+            set_value(7);
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, "paid".into()), Ok(()));
+            set_value(0);
+            assert_eq!(transmitter.get_balance(), Ok(2));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_balance(), Ok(5));
+            assert_eq!(transmitter.get_all_messages("Bob".into()).map(|messages| messages.len()), Ok(1));
+        }
+
+        #[ink::test]
+        fn dashboard_aggregates_names_balance_and_counts() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.dashboard(), Err(Error::NoAccount));
+            set_value(3);
+            assert_eq!(transmitter.register_username("Bob".into()), Ok(()));
+            set_value(0);
+            register(&mut transmitter, accounts.bob, "Robert");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "one");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "two");
+
+            set_caller(accounts.bob);
+            assert_eq!(
+                transmitter.dashboard(),
+                Ok((vec!["Bob".into(), "Robert".into()], 2, vec![("Bob".into(), 2), ("Robert".into(), 0)]))
+            );
+        }
+
+        #[ink::test]
+        fn sender_stats_counts_per_sender() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "A");
+            register(&mut transmitter, accounts.django, "B");
+            send_text(&mut transmitter, accounts.charlie, "A", "Bob", "one");
+            send_text(&mut transmitter, accounts.django, "B", "Bob", "two");
+            send_text(&mut transmitter, accounts.charlie, "A", "Bob", "three");
+
+            assert_eq!(transmitter.sender_stats("Bob".into()), Err(Error::WrongAccount("Bob".into())));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.sender_stats("Bob".into()), Ok(vec![("A".into(), 2), ("B".into(), 1)]));
+        }
+
+        #[ink::test]
+        fn replies_are_limited_in_depth() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+            assert_eq!(transmitter.co_set_max_reply_depth(2), Ok(()));
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "root");
+
+            set_caller(accounts.bob);
+            let root = transmitter.get_all_messages("Bob".into()).unwrap_or_default()[0].hash;
+            let reply = MessageType::ReplyTo { hash: root };
+            assert_eq!(transmitter.send_message("Bob".into(), "Charlie".into(), reply, "depth 1".into()), Ok(()));
+
+            set_caller(accounts.charlie);
+            let first = transmitter.get_all_messages("Charlie".into()).unwrap_or_default()[0].hash;
+            let reply = MessageType::ReplyTo { hash: first };
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), reply, "depth 2".into()), Ok(()));
+
+            set_caller(accounts.bob);
+            let second = transmitter.get_all_messages("Bob".into()).unwrap_or_default()[1].hash;
+            let reply = MessageType::ReplyTo { hash: second };
+            assert_eq!(transmitter.send_message("Bob".into(), "Charlie".into(), reply, "depth 3".into()), Err(Error::ReplyTooDeep));
+
+            // A new reply to the root is still fine.
+            let reply = MessageType::ReplyTo { hash: root };
+            assert_eq!(transmitter.send_message("Bob".into(), "Charlie".into(), reply, "depth 1 again".into()), Ok(()));
+        }
+
+        #[ink::test]
+        fn name_history_tracks_holders() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+            assert_eq!(transmitter.co_set_expiry_periods(1_000, 0), Ok(()));
+            assert_eq!(transmitter.name_history("Bob".into()), Err(Error::NameNonexistent("Bob".into())));
+
+            test::set_block_timestamp::<DefaultEnvironment>(10);
+            register(&mut transmitter, accounts.bob, "Bob");
+
+            test::set_block_timestamp::<DefaultEnvironment>(2_000);
+            register(&mut transmitter, accounts.charlie, "Bob");
+
+            assert_eq!(transmitter.name_history("Bob".into()), Ok(vec![(accounts.bob, 10), (accounts.charlie, 2_000)]));
+
+            for round in 1..=MAX_NAME_HISTORY as u64 {
+                test::set_block_timestamp::<DefaultEnvironment>(2_000 + round * 1_000);
+                register(&mut transmitter, accounts.django, "Bob");
+            }
+
+            let history = transmitter.name_history("Bob".into()).unwrap_or_default();
+            assert_eq!(history.len(), MAX_NAME_HISTORY);
+            assert!(history.iter().all(|(account, _)| *account == accounts.django));
+        }
+
+        #[ink::test]
+        fn dust_below_threshold_is_swept() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            for (account, paid) in [(accounts.bob, 2), (accounts.charlie, 3), (accounts.django, 11)] {
+                set_caller(account);
+                set_value(paid);
+                assert_eq!(transmitter.register_username(format!("{:?}", paid)), Ok(()));
+            }
+            set_value(0);
+
+            let sweep = vec![accounts.bob, accounts.charlie, accounts.django, accounts.eve];
+
+            assert_eq!(transmitter.co_sweep_dust(sweep.clone(), 5), Err(Error::NotContractOwner));
+
+            let events_before = test::recorded_events().count();
+
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_sweep_dust(sweep, 5), Ok(3));
+            assert_eq!(test::recorded_events().count(), events_before + 2);
+            assert_eq!(transmitter.co_get_balance(), Ok(6));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_balance(), Ok(0));
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.get_balance(), Ok(0));
+            set_caller(accounts.django);
+            assert_eq!(transmitter.get_balance(), Ok(10));
+        }
+
+        #[ink::test]
+        fn note_to_self_reaches_all_own_names() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.note_to_self("nobody home".into()), Ok(0));
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.bob, "Robert");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.note_to_self("buy milk".into()), Ok(2));
+
+            for name in ["Bob", "Robert"] {
+                let messages = transmitter.get_all_messages(name.into()).unwrap_or_default();
+                assert_eq!(messages.len(), 1);
+                assert_eq!(messages[0].from, NOTE_TO_SELF_SENDER);
+                assert_eq!(messages[0].content, Content::from("buy milk"));
+            }
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.get_all_messages("Charlie".into()).err(), Some(Error::NoMessages));
+        }
+
+        #[ink::test]
+        fn compressed_flag_is_stored() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            set_caller(accounts.charlie);
+            let options = SendOptions { compressed: true, ..Default::default() };
+            let content: Content = vec![0x1f, 0x8b, 0x08, 0x00];
+            assert!(transmitter.send_message_with_options("Charlie".into(), "Bob".into(), MessageType::Text, content.clone(), options).is_ok());
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "plain");
+
+            set_caller(accounts.bob);
+            let messages = transmitter.get_all_messages("Bob".into()).unwrap_or_default();
+            assert!(messages[0].compressed);
+            assert_eq!(messages[0].content, content);
+            assert!(!messages[1].compressed);
+        }
+
+        #[ink::test]
+        fn registration_time_is_the_block_timestamp() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            test::set_block_timestamp::<DefaultEnvironment>(1_234);
+            register(&mut transmitter, accounts.bob, "Bob");
+
+            assert_eq!(transmitter.registration_time("Bob".into()), Ok(1_234));
+            assert_eq!(transmitter.registration_time("Nobody".into()), Err(Error::NameNonexistent("Nobody".into())));
+        }
+
+        #[ink::test]
+        fn fee_exempt_accounts_register_for_free() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.co_set_fee_exempt(accounts.bob, true), Err(Error::NotContractOwner));
+
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_set_fee_exempt(accounts.bob, true), Ok(()));
+
+            set_caller(accounts.bob);
+            set_value(0);
+            assert_eq!(transmitter.register_username("Bob".into()), Ok(()));
+
+            set_caller(accounts.charlie);
+            assert_eq!(
+                transmitter.register_username("Charlie".into()),
+                Err(Error::PaymentFailed { received: 0, required: 1, missing: 1 })
+            );
+
+            assert_eq!(transmitter.owner.balance, 0);
+        }
+
+        #[ink::test]
+        fn reassigning_a_name_does_not_duplicate_it() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            assert_eq!(transmitter.assign_username(accounts.bob, "Bob".into(), 0), Ok(()));
+            assert_eq!(transmitter.assign_username(accounts.bob, "Bob".into(), 0), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_usernames(), Ok(vec!["Bob".into()]));
+        }
+
+        #[ink::test]
+        fn failed_transfers_report_the_amount() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            // The value is only recorded, not moved, so the contract cannot pay it out.
+            set_caller(accounts.bob);
+            set_value(8);
+            assert_eq!(transmitter.register_username("Bob".into()), Ok(()));
+            set_value(0);
+
+            assert_eq!(transmitter.withdraw_balance(), Err(Error::TransferFailed { to: accounts.bob, amount: 7 }));
+            assert_eq!(transmitter.close_account(), Err(Error::TransferFailed { to: accounts.bob, amount: 7 }));
+
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_withdraw_amount(1), Err(Error::TransferFailed { to: accounts.alice, amount: 1 }));
+            assert_eq!(transmitter.co_withdraw_all_balance(), Err(Error::TransferFailed { to: accounts.alice, amount: 1 }));
+        }
+
+        #[ink::test]
+        fn huge_sale_prices_are_rejected() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.sell_username_to("Bob".into(), accounts.charlie, Balance::MAX - 1, 1_000), Err(Error::PriceOverflow));
+            assert_eq!(transmitter.sell_username_to("Bob".into(), accounts.charlie, Balance::MAX / 10_000, 1_000), Ok(()));
+            assert_eq!(transmitter.cancel_sale("Bob".into()), Ok(()));
+
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_set_max_sale_price(Some(1_000)), Ok(()));
+            assert_eq!(transmitter.check_max_sale_price(), Some(1_000));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.sell_username_to("Bob".into(), accounts.charlie, 1_001, 1_000), Err(Error::PriceTooHigh));
+            assert_eq!(transmitter.sell_username_to("Bob".into(), accounts.charlie, 1_000, 1_000), Ok(()));
+        }
+
+        #[ink::test]
+        fn messages_can_be_selected_by_time_range() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            for timestamp in [100, 200, 300, 400] {
+                test::set_block_timestamp::<DefaultEnvironment>(timestamp);
+                send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", &format!("at {}", timestamp));
+            }
+
+            set_caller(accounts.bob);
+            let window = transmitter.get_messages_range("Bob".into(), 200, 300).unwrap_or_default();
+            assert_eq!(window.iter().map(|m| m.timestamp).collect::<Vec<_>>(), vec![200, 300]);
+
+            assert_eq!(transmitter.get_messages_range("Bob".into(), 301, 300), Err(Error::InvalidRange));
+            assert_eq!(transmitter.get_messages_range("Bob".into(), 500, 600), Ok(vec![]));
+        }
+
+        #[ink::test]
+        fn owner_can_find_names_by_prefix() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                self.usernames.insert(&name, &new_username_info);
+            for name in ["bob", "bobby", "charlie", "bobcat", "alice"] {
+                register(&mut transmitter, accounts.bob, name);
+            }
 
-                return Ok(());
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.co_find_names_by_prefix("bob".into(), 10), Err(Error::NotContractOwner));
+            assert_eq!(transmitter.close_account(), Ok(()));
+            register(&mut transmitter, accounts.charlie, "bobcat");
+            register(&mut transmitter, accounts.charlie, "bob");
+            register(&mut transmitter, accounts.charlie, "charlie");
+
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_find_names_by_prefix("bob".into(), 10), Ok(vec!["bobcat".into(), "bob".into()]));
+            assert_eq!(transmitter.co_find_names_by_prefix("bob".into(), 1), Ok(vec!["bobcat".into()]));
+            assert_eq!(transmitter.co_find_names_by_prefix("x".into(), 10), Ok(vec![]));
+            assert_eq!(transmitter.registry_len, 3);
+        }
 
-            } else {
+        #[ink::test]
+        fn constructor_sets_fee_and_owner() {
 
+            let accounts = accounts();
+            set_caller(accounts.alice);
 
-                let mut usernames = Vec::<Username>::new();
+            let transmitter = Transmitter::new_with_config(25, None);
+            assert_eq!(transmitter.check_fee(), 25);
+            assert_eq!(transmitter.owner.account_id, accounts.alice);
 
-                usernames.push(name.clone());
+            let mut transmitter = Transmitter::new_with_config(3, Some(accounts.bob));
+            assert_eq!(transmitter.check_fee(), 3);
+            assert_eq!(transmitter.co_set_fee(4), Err(Error::NotContractOwner));
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.co_set_fee(4), Ok(()));
+        }
 
-                let new_user_info = UserInfo { usernames: Some(usernames), balance: user_balance };
+        #[ink::test]
+        fn zero_account_is_rejected() {
 
-                self.users.insert(&self.env().caller(), &new_user_info);
+            let accounts = accounts();
+            let mut transmitter = deploy();
+            let zero = AccountId::from([0u8;32]);
 
+            register(&mut transmitter, accounts.bob, "Bob");
 
-                let new_username_info = UsernameInfo {
-                    account_id: self.env().caller(),
-                    messages: None,
-                    fee_payment_time: timestamp,
-                };
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.sell_username_to("Bob".into(), zero, 10, 1_000), Err(Error::ZeroAccount));
 
-                self.usernames.insert(&name, &new_username_info);
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_propose_owner(zero), Err(Error::ZeroAccount));
+            assert_eq!(transmitter.owner.account_id, accounts.alice);
+        }
 
-                return Ok(());
+        #[ink::test]
+        fn whoami_returns_the_caller() {
 
-            }
+            let accounts = accounts();
+            let transmitter = deploy();
 
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.whoami(), accounts.bob);
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.whoami(), accounts.charlie);
         }
 
-        /// Lists the names registered to your account.
-        #[ink(message)]
-        pub fn get_usernames(&self) -> Result<Vec<Username>,Error> {
+        #[ink::test]
+        fn sequence_numbers_survive_deletions() {
 
-            if let Some(user_info) = self.users.get(&self.env().caller()) {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                if let Some(usernames) = user_info.usernames {
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "zero");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "one");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "two");
 
-                    return Ok(usernames);
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.delete_message_at("Bob".into(), 1), Ok(()));
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "three");
 
-                } else {
+            set_caller(accounts.bob);
+            let messages = transmitter.get_all_messages_ordered("Bob".into()).unwrap_or_default();
+            assert_eq!(messages.iter().map(|m| m.seq).collect::<Vec<_>>(), vec![0, 2, 3]);
+            assert_eq!(messages[2].content, Content::from("three"));
+        }
 
-                    return Err(Error::NoNames);
+        #[ink::test]
+        fn delete_messages_reports_failures() {
 
-                }
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-            } else {
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "one");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "two");
 
-                return Err(Error::NoAccount);
+            set_caller(accounts.bob);
+            let hashes: Vec<[u8;32]> = transmitter.get_all_messages("Bob".into()).unwrap_or_default().iter().map(|m| m.hash).collect();
 
-            }
+            let result = transmitter.delete_messages("Bob".into(), vec![hashes[0], [9u8;32], hashes[1]]);
+            assert_eq!(
+                result,
+                Ok(BatchResult { succeeded: vec![hashes[0], hashes[1]], failed: vec![([9u8;32], Error::MessageNonexistent)] })
+            );
+            assert_eq!(transmitter.get_all_messages("Bob".into()).err(), Some(Error::NoMessages));
         }
 
-        /// Attempts to state the balance associated to your account.
-        #[ink(message)]
-        pub fn get_balance(&self) -> Result<Balance,Error> {
+        #[ink::test]
+        fn registrations_over_the_cap_are_refused() {
 
-            if let None = self.users.get(&self.env().caller()) {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                return Err(Error::NoAccount);
+            assert_eq!(transmitter.co_set_registry_cap(2), Ok(()));
 
-            }
-        
-            if let Some(user_info) = self.users.get(&self.env().caller()) {
+            register(&mut transmitter, accounts.bob, "Bob");
 
-                return Ok(user_info.balance);
+            set_caller(accounts.charlie);
+            set_value(2);
+            assert_eq!(transmitter.register_usernames(vec!["Charlie".into(), "Carl".into()]), Err(Error::RegistryFull));
 
-            } else {
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-                return Ok(0);
+            set_caller(accounts.django);
+            set_value(1);
+            assert_eq!(transmitter.register_username("Django".into()), Err(Error::RegistryFull));
+            assert_eq!(transmitter.registry_len, 2);
 
-            }
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.co_set_registry_cap(3), Err(Error::NotContractOwner));
         }
 
-        /// Attempts to send a message to another user using one of your names.
-        /// The name from which you wish the message to be sent must be specified.
-        #[ink(message)]
-        pub fn send_message(&mut self, from: Username, to: Username, mtype: MessageType, content: Content) -> Result<(),Error> {
+        #[ink::test]
+        fn custom_messages_are_filtered_by_tag() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            set_caller(accounts.charlie);
+            let invoice_v1 = MessageType::Custom { tag: "invoice".into(), schema_version: 1 };
+            let invoice_v2 = MessageType::Custom { tag: "invoice".into(), schema_version: 2 };
+            let receipt = MessageType::Custom { tag: "receipt".into(), schema_version: 1 };
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), invoice_v1, Content::from("old")), Ok(()));
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), receipt, Content::from("paid")), Ok(()));
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), invoice_v2, Content::from("new")), Ok(()));
+
+            set_caller(accounts.bob);
+            let invoices = transmitter.get_messages_by_tag("Bob".into(), "invoice".into()).unwrap_or_default();
+            assert_eq!(invoices.len(), 2);
+            assert_eq!(invoices[0].mtype, MessageType::Custom { tag: "invoice".into(), schema_version: 1 });
+            assert_eq!(invoices[1].mtype, MessageType::Custom { tag: "invoice".into(), schema_version: 2 });
+            assert_eq!(invoices[1].content, Content::from("new"));
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.get_messages_by_tag("Bob".into(), "invoice".into()), Err(Error::WrongAccount("Bob".into())));
+        }
 
-            let timestamp = self.env().block_timestamp();
+        #[ink::test]
+        fn owner_can_drain_the_contract() {
 
-            if let Some(username_info) = self.usernames.get(&from) {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                if username_info.account_id != self.env().caller() {
+            test::set_account_balance::<DefaultEnvironment>(AccountId::from([0xff;32]), 500);
+            let before = test::get_account_balance::<DefaultEnvironment>(accounts.eve).unwrap_or_default();
 
-                    return Err(Error::WrongAccount(from));
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.co_drain(accounts.bob), Err(Error::NotContractOwner));
 
-                }
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_drain(accounts.eve), Ok(500));
+            assert_eq!(test::get_account_balance::<DefaultEnvironment>(accounts.eve), Ok(before + 500));
 
-                if let Some(username_info) = self.usernames.get(&to) {
+            match last_event() {
+                Event::ContractDrained(event) => {
+                    assert_eq!(event.to, accounts.eve);
+                    assert_eq!(event.amount, 500);
+                }
+                _ => panic!("expected a ContractDrained event"),
+            }
 
-                    let mut messages = Vec::new();
+            assert_eq!(transmitter.co_drain(accounts.eve), Err(Error::NoBalance));
+        }
 
-                    if let Some(existing) = username_info.messages {
+        #[ink::test]
+        fn sale_propositions_can_be_indexed() {
 
-                        messages = existing;
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                    }
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-                    let mut to_be_hashed = Vec::<u8>::new();
-                    to_be_hashed.extend(self.env().block_number().to_be_bytes());
-                    to_be_hashed.extend(content.clone().iter()); // Mayber hashing only the message content is enough?
+            set_caller(accounts.django);
+            assert_eq!(transmitter.sale_proposition_count(), 0);
+            assert_eq!(transmitter.sale_proposition_at(0), Err(Error::NoSalesForYou));
 
-                    let hash = self.env().hash_bytes::<Sha2x256>(&to_be_hashed);
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.sell_username_to("Bob".into(), accounts.django, 10, 1_000), Ok(()));
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.sell_username_to("Charlie".into(), accounts.django, 20, 1_000), Ok(()));
 
-                    messages.push( Message { from, mtype, content, hash, timestamp });
+            set_caller(accounts.django);
+            assert_eq!(transmitter.sale_proposition_count(), 2);
+            assert_eq!(transmitter.sale_proposition_at(0).map(|sale| sale.username), Ok("Bob".into()));
+            assert_eq!(transmitter.sale_proposition_at(1).map(|sale| sale.price), Ok(20));
+            assert_eq!(transmitter.sale_proposition_at(2), Err(Error::InvalidRange));
 
-                    let new_username_info = UsernameInfo {
-                        account_id: username_info.account_id,
-                        messages: Some(messages),
-                        fee_payment_time: username_info.fee_payment_time,
-                    };
+            set_caller(accounts.eve);
+            assert_eq!(transmitter.sale_proposition_count(), 0);
+        }
 
-                    self.usernames.insert(&to, &new_username_info);
+        #[ink::test]
+        fn balances_do_not_wrap_around() {
 
-                    return Ok(());
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                } else {
+            set_caller(accounts.bob);
+            set_value(Balance::MAX);
+            assert_eq!(transmitter.register_username("Bob".into()), Ok(()));
+            assert_eq!(transmitter.get_balance(), Ok(Balance::MAX - 1));
 
-                    return Err(Error::NameNonexistent(to));
+            set_value(3);
+            assert_eq!(transmitter.register_username("Bobby".into()), Err(Error::BalanceOverflow));
 
-                }
+            transmitter.owner.balance = Balance::MAX;
+            set_caller(accounts.charlie);
+            set_value(1);
+            assert_eq!(transmitter.register_username("Charlie".into()), Err(Error::BalanceOverflow));
+        }
 
-                
-            } else {
+        #[ink::test]
+        fn unread_messages_are_reported() {
 
-                return Err(Error::NameNonexistent(from));
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-            }
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-        }
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.has_unread("Bob".into()), Ok(false));
 
-        /// Attempts to make all the messages that were sent to a specific name of yours available.
-        #[ink(message,payable)]
-        pub fn get_all_messages(&self, belonging_to: Username) -> Result<Vec<Message>,Error> {
-            
-            if let Some(username_info) = self.usernames.get(&belonging_to) {
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "news");
 
-                if self.env().caller() != username_info.account_id {
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.has_unread("Bob".into()), Ok(true));
 
-                    return Err(Error::WrongAccount(belonging_to));
+            let hash = transmitter.get_all_messages("Bob".into()).unwrap_or_default()[0].hash;
+            assert_eq!(transmitter.mark_as_read("Bob".into(), [0u8;32]), Err(Error::MessageNonexistent));
+            assert_eq!(transmitter.mark_as_read("Bob".into(), hash), Ok(()));
+            assert_eq!(transmitter.has_unread("Bob".into()), Ok(false));
 
-                }
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.has_unread("Bob".into()), Err(Error::WrongAccount("Bob".into())));
+        }
 
-                if let Some(messages) = username_info.messages {
+        #[ink::test]
+        fn reserved_names_cannot_be_registered() {
 
-                    if messages.len() == 0 {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                        return Err(Error::NoMessages);
+            set_caller(accounts.bob);
+            set_value(1);
+            assert_eq!(transmitter.register_username(SYSTEM_NAME.into()), Err(Error::NameReserved));
+            assert_eq!(transmitter.register_username(NOTE_TO_SELF_SENDER.into()), Err(Error::NameReserved));
 
-                    }
+            set_value(2);
+            assert_eq!(
+                transmitter.register_usernames(vec!["Bob".into(), SYSTEM_NAME.into()]),
+                Err(Error::NamesRefused(vec![(String::from(SYSTEM_NAME), Error::NameReserved)]))
+            );
 
-                    return Ok(messages);
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_import_usernames(vec![(SYSTEM_NAME.into(), accounts.bob)]), Ok(0));
+        }
 
-                } else {
+        #[ink::test]
+        fn several_fees_are_set_at_once() {
 
-                    return Err(Error::NoMessages);
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                }
+            assert_eq!(transmitter.check_fees(), (1, 0, 1, 5));
+            assert_eq!(transmitter.co_set_fees(Some(7), None, Some(3), Some(101)), Err(Error::InvalidPercentage));
+            assert_eq!(transmitter.check_fees(), (1, 0, 1, 5));
 
-            } else {
+            assert_eq!(transmitter.co_set_fees(Some(7), Some(2), None, Some(10)), Ok(()));
+            assert_eq!(transmitter.check_fees(), (7, 2, 1, 10));
+            assert_eq!(transmitter.check_fee(), 7);
 
-                return Err(Error::NameNonexistent(belonging_to));
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-            }
+            set_caller(accounts.charlie);
+            set_value(1);
+            assert_eq!(
+                transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, Content::from("hi")),
+                Err(Error::PaymentFailed { received: 1, required: 2, missing: 1 })
+            );
+            set_value(2);
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, Content::from("hi")), Ok(()));
 
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.co_set_fees(None, Some(0), None, None), Err(Error::NotContractOwner));
         }
 
-        /// Attempts to find and delete the specified message. The account name and message hash must be specified.
-        #[ink(message)]
-        pub fn delete_message(&mut self, belonging_to: Username, hash: [u8;32]) -> Result<(),Error> {
-
-            if let Some(username_info) = self.usernames.get(&belonging_to) {
-
-                if username_info.account_id != self.env().caller() {
+        #[ink::test]
+        fn message_hash_can_be_recomputed() {
 
-                    return Err(Error::WrongAccount(belonging_to));
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                }
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "check me");
+            test::advance_block::<DefaultEnvironment>();
 
-                if let Some(mut messages) = username_info.messages {
+            set_caller(accounts.bob);
+            let hash = transmitter.get_all_messages("Bob".into()).unwrap_or_default()[0].hash;
+            let (message, recomputed) = transmitter.get_message_with_proof("Bob".into(), hash).expect("message not found");
+            assert_eq!(message.hash, hash);
+            assert_eq!(recomputed, hash);
 
-                    let mut msg_pos = None;
+            assert_eq!(transmitter.get_message_with_proof("Bob".into(), [0u8;32]).err(), Some(Error::MessageNonexistent));
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.get_message_with_proof("Bob".into(), hash).err(), Some(Error::WrongAccount("Bob".into())));
+        }
 
-                    for (pos,message) in messages.iter().enumerate() {
+        #[ink::test]
+        fn escrowed_sale_is_completed_by_the_seller() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.sell_username_to("Bob".into(), accounts.charlie, 100, 1_000), Ok(()));
+            assert_eq!(transmitter.seller_accept("Bob".into()), Err(Error::SaleNotDeposited));
+
+            set_caller(accounts.charlie);
+            set_value(99);
+            assert_eq!(
+                transmitter.buyer_deposit("Bob".into()),
+                Err(Error::PaymentFailed { received: 99, required: 100, missing: 1 })
+            );
+            set_value(100);
+            assert_eq!(transmitter.buyer_deposit("Bob".into()), Ok(()));
+            assert_eq!(transmitter.buyer_deposit("Bob".into()), Err(Error::SaleAlreadyDeposited));
+            set_value(0);
+
+            // Deposited offers stay open past their expiry.
+            test::set_block_timestamp::<DefaultEnvironment>(2_000);
+
+            set_caller(accounts.django);
+            assert_eq!(transmitter.seller_accept("Bob".into()), Err(Error::WrongAccount("Bob".into())));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.seller_accept("Bob".into()), Ok(()));
+            assert_eq!(transmitter.get_balance(), Ok(95));
+            assert_eq!(transmitter.get_usernames(), Err(Error::NoNames));
+            assert_eq!(transmitter.get_sale("Bob".into()), Err(Error::UsernameNotInSale));
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.get_usernames(), Ok(vec!["Bob".into()]));
+            assert_eq!(transmitter.get_all_messages("Bob".into()).err(), Some(Error::NoMessages));
+        }
 
-                        if message.hash == hash {
+        #[ink::test]
+        fn escrowed_deposit_can_be_reclaimed() {
 
-                            msg_pos = Some(pos);
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                        } 
+            register(&mut transmitter, accounts.bob, "Bob");
 
-                    }
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.sell_username_to("Bob".into(), accounts.charlie, 100, 1_000), Ok(()));
 
-                    if let Some(pos) = msg_pos {
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.buyer_reclaim("Bob".into()), Err(Error::SaleNotDeposited));
+            set_value(100);
+            assert_eq!(transmitter.buyer_deposit("Bob".into()), Ok(()));
+            set_value(0);
+            assert_eq!(transmitter.buy_username("Bob".into()), Err(Error::SaleAlreadyDeposited));
+            assert_eq!(transmitter.buyer_reclaim("Bob".into()), Ok(()));
+            assert_eq!(transmitter.get_balance(), Ok(100));
 
-                        messages.remove(pos);
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.seller_accept("Bob".into()), Err(Error::SaleNotDeposited));
+            assert_eq!(transmitter.get_usernames(), Ok(vec!["Bob".into()]));
+        }
 
-                        let username_info = UsernameInfo {
-                            account_id: self.env().caller(),
-                            messages: if messages.len() == 0 { None } else { Some(messages) },
-                            fee_payment_time: username_info.fee_payment_time,
-                        };
+        #[ink::test]
+        fn names_can_have_metadata() {
 
-                        self.usernames.insert(&belonging_to, &username_info);
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                        return Ok(());
+            register(&mut transmitter, accounts.bob, "Bob");
+            assert_eq!(transmitter.username_profile("Bob".into()).map(|profile| profile.metadata), Ok(None));
 
-                    } else {
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.set_metadata("Bob".into(), Some("Robert, builder".into())), Ok(()));
+            assert_eq!(
+                transmitter.username_profile("Bob".into()),
+                Ok(UsernameProfile { account_id: accounts.bob, fee_payment_time: 0, inbound_fee: 0, metadata: Some("Robert, builder".into()) })
+            );
 
-                        return Err(Error::MessageNonexistent);
+            let too_long = String::from_utf8(vec![b'a'; MAX_METADATA_LEN + 1]).unwrap_or_default();
+            assert_eq!(transmitter.set_metadata("Bob".into(), Some(too_long)), Err(Error::MetadataTooLong));
 
-                    }
+            assert_eq!(transmitter.set_metadata("Bob".into(), None), Ok(()));
+            assert_eq!(transmitter.username_profile("Bob".into()).map(|profile| profile.metadata), Ok(None));
 
-                } else {
-                    
-                    return Err(Error::NoMessages);
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.set_metadata("Bob".into(), Some("not Bob".into())), Err(Error::WrongAccount("Bob".into())));
+            assert_eq!(transmitter.username_profile("Nobody".into()), Err(Error::NameNonexistent("Nobody".into())));
+        }
 
-                }
+        #[ink::test]
+        fn names_cannot_change_hands_during_the_cooldown() {
 
-            } else {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                return Err(Error::NameNonexistent(belonging_to));
+            assert_eq!(transmitter.co_set_transfer_cooldown(5_000), Ok(()));
 
-            }
-        }
+            register(&mut transmitter, accounts.bob, "Bob");
 
-        /// Removes all messages that are in sotrage. This operation is not undoable, so proceed with caution.
-        #[ink(message)]
-        pub fn delete_all_messages(&mut self, username: Username) -> Result<(),Error> {
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.sell_username_to("Bob".into(), accounts.charlie, 10, 100_000), Ok(()));
+            set_caller(accounts.charlie);
+            set_value(10);
+            assert_eq!(transmitter.buyer_deposit("Bob".into()), Ok(()));
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.seller_accept("Bob".into()), Ok(()));
 
-            if let Some(mut username_info) = self.usernames.get(&username) {
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.sell_username_to("Bob".into(), accounts.django, 10, 100_000), Ok(()));
+            set_caller(accounts.django);
+            assert_eq!(transmitter.buyer_deposit("Bob".into()), Ok(()));
+            set_value(0);
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.seller_accept("Bob".into()), Err(Error::TransferCooldown));
 
-                if username_info.account_id != self.env().caller() {
+            test::set_block_timestamp::<DefaultEnvironment>(5_000);
+            assert_eq!(transmitter.seller_accept("Bob".into()), Ok(()));
 
-                    return Err(Error::WrongAccount(username));
+            set_caller(accounts.django);
+            assert_eq!(transmitter.get_usernames(), Ok(vec!["Bob".into()]));
+            assert_eq!(transmitter.co_set_transfer_cooldown(0), Err(Error::NotContractOwner));
+        }
 
-                }
+        #[ink::test]
+        fn unread_messages_are_counted() {
 
-                username_info.messages = None;
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                self.usernames.insert(&username, &username_info);
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "one");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "two");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "three");
 
-                return Ok(());
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_unread_count("Bob".into()), Ok(3));
 
-            } else {
+            let hash = transmitter.get_all_messages("Bob".into()).unwrap_or_default()[1].hash;
+            assert_eq!(transmitter.mark_as_read("Bob".into(), hash), Ok(()));
+            assert_eq!(transmitter.get_unread_count("Bob".into()), Ok(2));
 
-                return Err(Error::NameNonexistent(username));
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.get_unread_count("Bob".into()), Err(Error::WrongAccount("Bob".into())));
+        }
 
-            }
+        #[ink::test]
+        fn retried_messages_are_stored_once() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            set_caller(accounts.charlie);
+            let options = SendOptions { idempotency_key: Some([7u8;16]), ..Default::default() };
+            let first = transmitter.send_message_with_options("Charlie".into(), "Bob".into(), MessageType::Text, "once".into(), options.clone());
+            test::advance_block::<DefaultEnvironment>();
+            let retry = transmitter.send_message_with_options("Charlie".into(), "Bob".into(), MessageType::Text, "once".into(), options.clone());
+            assert!(first.is_ok());
+            assert_eq!(retry, first);
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_all_messages("Bob".into()).unwrap_or_default().len(), 1);
+
+            // Once the window has passed, the key can be used again.
+            test::set_block_timestamp::<DefaultEnvironment>(IDEMPOTENCY_WINDOW);
+            set_caller(accounts.charlie);
+            let later = transmitter.send_message_with_options("Charlie".into(), "Bob".into(), MessageType::Text, "once".into(), options);
+            assert!(later.is_ok());
+            assert_ne!(later, first);
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_all_messages("Bob".into()).unwrap_or_default().len(), 2);
         }
 
-        /// Attempts to send the balance associated to your account back to you.
-        #[ink(message)]
-        pub fn withdraw_balance(&mut self) -> Result<(),Error> {
+        #[ink::test]
+        fn expired_idempotency_keys_are_forgotten() {
 
-            if let Some(mut user_info) = self.users.get(&self.env().caller()) {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                if user_info.balance == 0 {
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-                    return Err(Error::NoBalance);
+            set_caller(accounts.charlie);
+            let options = |key: u8| SendOptions { idempotency_key: Some([key;16]), ..Default::default() };
+            transmitter.send_message_with_options("Charlie".into(), "Bob".into(), MessageType::Text, "first".into(), options(1)).unwrap();
 
-                }
+            test::set_block_timestamp::<DefaultEnvironment>(IDEMPOTENCY_WINDOW);
+            transmitter.send_message_with_options("Charlie".into(), "Bob".into(), MessageType::Text, "second".into(), options(2)).unwrap();
 
-                if let Err(_) = self.env().transfer(self.env().caller(), user_info.balance) {
+            let bob = String::from("Bob");
+            let charlie = String::from("Charlie");
+            assert!(!transmitter.seen_keys.contains((&bob, &charlie, &[1u8;16])));
+            assert!(transmitter.seen_keys.contains((&bob, &charlie, &[2u8;16])));
 
-                    return Err(Error::WithdrawFailed);
+            for key in 3..3 + MAX_RECENT_KEYS as u8 {
+                transmitter.send_message_with_options("Charlie".into(), "Bob".into(), MessageType::Text, "more".into(), options(key)).unwrap();
+            }
+            assert!(!transmitter.seen_keys.contains((&bob, &charlie, &[2u8;16])));
+            assert_eq!(transmitter.recent_keys.get(&bob).unwrap_or_default().len(), MAX_RECENT_KEYS);
 
-                } else {
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.close_account(), Ok(()));
+            assert!(!transmitter.seen_keys.contains((&bob, &charlie, &[3u8;16])));
+            assert_eq!(transmitter.recent_keys.get(&bob), None);
+        }
 
-                    user_info.balance = 0;
+        #[ink::test]
+        fn buying_a_name_transfers_it_and_pays_the_seller() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.bob, "Sponge");
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.sell_username_to("Bob".into(), accounts.charlie, 100, 1_000), Ok(()));
+
+            set_caller(accounts.django);
+            set_value(100);
+            assert_eq!(transmitter.buy_username("Bob".into()), Err(Error::NoSalesForYou));
+
+            set_caller(accounts.charlie);
+            set_value(60);
+            assert_eq!(
+                transmitter.buy_username("Bob".into()),
+                Err(Error::PaymentFailed { received: 60, required: 100, missing: 40 })
+            );
+            set_value(110);
+            assert_eq!(transmitter.buy_username("Bob".into()), Ok(()));
+            set_value(0);
+
+            assert_eq!(transmitter.get_usernames(), Ok(vec!["Bob".into()]));
+            assert_eq!(transmitter.get_balance(), Ok(10));
+            assert_eq!(transmitter.username_profile("Bob".into()).map(|profile| profile.account_id), Ok(accounts.charlie));
+            assert_eq!(transmitter.get_sale("Bob".into()), Err(Error::UsernameNotInSale));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_usernames(), Ok(vec!["Sponge".into()]));
+            assert_eq!(transmitter.get_balance(), Ok(95));
+
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_get_balance(), Ok(2 + 5));
+        }
 
-                    self.users.insert(&self.env().caller(), &user_info);
+        #[ink::test]
+        fn buyer_can_refuse_an_offer() {
 
-                    return Ok(());
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                }
+            register(&mut transmitter, accounts.bob, "Bob");
 
-            } else {
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.sell_username_to("Bob".into(), accounts.charlie, 10, 1_000), Ok(()));
 
-                return Err(Error::NoBalance);
+            set_caller(accounts.django);
+            assert_eq!(transmitter.refuse_to_buy("Bob".into()), Err(Error::NoSalesForYou));
 
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.refuse_to_buy("Bob".into()), Ok(()));
+            match last_event() {
+                Event::SaleRefused(event) => {
+                    assert_eq!(event.username, "Bob");
+                    assert_eq!(event.by, accounts.charlie);
+                }
+                _ => panic!("expected a SaleRefused event"),
             }
+            assert_eq!(transmitter.get_sale("Bob".into()), Err(Error::UsernameNotInSale));
+            assert_eq!(transmitter.sales_to_buyer.get(accounts.charlie), None);
+            assert_eq!(transmitter.refuse_to_buy("Bob".into()), Err(Error::NoSalesForYou));
         }
 
-        /// Makes a sale offer to the specified user. The same registration fee is charged.
-        #[ink(message)]
-        pub fn sell_username_to(&mut self, username: Username, to: AccountId, price: Balance) -> Result<(),Error> {
+        #[ink::test]
+        fn state_changes_emit_events() {
 
-            if let Some(username_info) = self.usernames.get(&username) {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                if username_info.account_id != self.env().caller() {
+            register(&mut transmitter, accounts.bob, "Bob");
+            match last_event() {
+                Event::UsernameRegistered(event) => {
+                    assert_eq!(event.username, "Bob");
+                    assert_eq!(event.account, accounts.bob);
+                }
+                _ => panic!("expected a UsernameRegistered event"),
+            }
 
-                    return Err(Error::WrongAccount(username));
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "hello");
+            set_caller(accounts.bob);
+            let hash = transmitter.get_all_messages("Bob".into()).unwrap_or_default()[0].hash;
+            match last_event() {
+                Event::MessageSent(event) => {
+                    assert_eq!(event.to_hash, transmitter.name_hash(&"Bob".into()));
+                    assert_eq!(event.message_hash, hash);
+                }
+                _ => panic!("expected a MessageSent event"),
+            }
 
+            assert_eq!(transmitter.delete_message("Bob".into(), hash), Ok(()));
+            assert!(matches!(last_event(), Event::MessageDeleted(event) if event.hash == hash && event.by == "Bob"));
+
+            assert_eq!(transmitter.sell_username_to("Bob".into(), accounts.charlie, 20, 1_000), Ok(()));
+            assert!(matches!(last_event(), Event::SaleOffered(event) if event.username == "Bob" && event.price == 20));
+
+            set_caller(accounts.charlie);
+            set_value(20);
+            assert_eq!(transmitter.buy_username("Bob".into()), Ok(()));
+            set_value(0);
+            match last_event() {
+                Event::SaleCompleted(event) => {
+                    assert_eq!(event.username, "Bob");
+                    assert_eq!(event.from, accounts.bob);
+                    assert_eq!(event.to, accounts.charlie);
+                    assert_eq!(event.price, 20);
                 }
+                _ => panic!("expected a SaleCompleted event"),
+            }
 
-                if let Some(sale_offers) = self.sale_offers.get() {
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_set_fees(None, Some(3), None, None), Ok(()));
+            assert!(matches!(last_event(), Event::FeesChanged(event) if event.message == 3 && event.registration == 1));
 
-                    if let Some(mut sale_offers) = sale_offers {
+            assert_eq!(transmitter.co_set_registry_cap(100), Ok(()));
+            assert!(matches!(last_event(), Event::SettingChanged(event) if event.setting == "co_set_registry_cap"));
 
-                        for sale in sale_offers.iter() {
+            assert_eq!(transmitter.co_propose_owner(accounts.django), Ok(()));
+            assert!(matches!(last_event(), Event::OwnershipProposed(event) if event.from == accounts.alice && event.to == accounts.django));
 
-                            if sale.username == username {
-        
-                                return Err(Error::UsernameAlreadyInSale);
-        
-                            }
-        
-                        }
-    
-                        sale_offers.push(Sale { username, to, price });
-    
-                        self.sale_offers.set(&Some(sale_offers));
-    
-                        return Ok(());
+            set_caller(accounts.django);
+            assert_eq!(transmitter.accept_ownership(), Ok(()));
+            assert!(matches!(last_event(), Event::OwnershipTransferred(event) if event.from == accounts.alice && event.to == accounts.django));
+        }
 
-                    } else {
+        #[ink::test]
+        fn messages_are_stored_one_per_cell() {
 
-                        let mut sale_offers = Vec::<Sale>::new();
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                        sale_offers.push(Sale { username, to, price });
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "first");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "second");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "third");
 
-                        self.sale_offers.set(&Some(sale_offers));
+            set_caller(accounts.bob);
+            let hashes: Vec<[u8;32]> = transmitter.get_all_messages("Bob".into()).unwrap_or_default().iter().map(|m| m.hash).collect();
 
-                        return Ok(());
+            assert_eq!(transmitter.delete_message("Bob".into(), hashes[1]), Ok(()));
+            assert!(!transmitter.inbox.contains((String::from("Bob"), 1u64)));
+            assert!(!transmitter.inbox_index.contains((String::from("Bob"), hashes[1])));
 
-                    }
+            // Deleting the oldest message moves the start of the inbox past the gap.
+            assert_eq!(transmitter.delete_message("Bob".into(), hashes[0]), Ok(()));
+            let info = transmitter.usernames.get(String::from("Bob")).expect("name not found");
+            assert_eq!((info.first_seq, info.next_seq, info.message_count), (2, 3, 1));
 
-                } else {
+            // Closing the account drops the remaining messages as well.
+            assert_eq!(transmitter.close_account(), Ok(()));
+            assert!(!transmitter.inbox.contains((String::from("Bob"), 2u64)));
+            assert!(!transmitter.inbox_index.contains((String::from("Bob"), hashes[2])));
+        }
+
+        #[ink::test]
+        fn invalid_names_are_rejected() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            set_caller(accounts.bob);
+            set_value(1);
+            assert_eq!(transmitter.register_username("".into()), Err(Error::InvalidName));
+            assert_eq!(transmitter.register_username(" Bob".into()), Err(Error::InvalidName));
+            assert_eq!(transmitter.register_username("Bob\n".into()), Err(Error::InvalidName));
+            assert_eq!(transmitter.register_username("B@b".into()), Err(Error::InvalidName));
+            assert_eq!(transmitter.register_username("B".repeat(33)), Err(Error::InvalidName));
+            assert_eq!(transmitter.register_username("Bob the 2nd".into()), Ok(()));
+
+            set_value(2);
+            assert_eq!(
+                transmitter.register_usernames(vec!["b_1".into(), "b 2 ".into()]),
+                Err(Error::NamesRefused(vec![(String::from("b 2 "), Error::InvalidName)]))
+            );
+            set_value(0);
+
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_set_name_length(0, 10), Err(Error::InvalidRange));
+            assert_eq!(transmitter.co_set_name_length(5, 4), Err(Error::InvalidRange));
+            assert_eq!(transmitter.co_set_name_length(3, 4), Ok(()));
+            assert_eq!(transmitter.check_name_length(), (3, 4));
+
+            set_caller(accounts.charlie);
+            set_value(1);
+            assert_eq!(transmitter.register_username("Al".into()), Err(Error::InvalidName));
+            assert_eq!(transmitter.register_username("Carl".into()), Ok(()));
+            assert_eq!(transmitter.co_set_name_length(1, 64), Err(Error::NotContractOwner));
+        }
 
-                    let mut sale_offers = Vec::<Sale>::new();
+        #[ink::test]
+        fn expiry_follows_the_last_payment() {
 
-                    sale_offers.push(Sale { username, to, price });
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                    self.sale_offers.set(&Some(sale_offers));
+            register(&mut transmitter, accounts.bob, "Bob");
+            assert_eq!(transmitter.get_expiry("Bob".into()), Ok(None));
+            assert_eq!(transmitter.get_expiry("Nobody".into()), Err(Error::NameNonexistent("Nobody".into())));
 
-                    return Ok(());
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_set_expiry_periods(1_000, 500), Ok(()));
+            assert_eq!(transmitter.get_expiry("Bob".into()), Ok(Some((1_000, 1_500))));
 
-                }
+            test::set_block_timestamp::<DefaultEnvironment>(1_200);
+            set_caller(accounts.bob);
+            set_value(1);
+            assert_eq!(transmitter.renew_username("Bob".into()), Ok(()));
+            set_value(0);
+            assert_eq!(transmitter.get_expiry("Bob".into()), Ok(Some((2_200, 2_700))));
+        }
 
+        #[ink::test]
+        fn names_resolve_to_their_holder() {
 
-            } else {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                return Err(Error::NameNonexistent(username));
+            register(&mut transmitter, accounts.bob, "Bob");
 
-            }
+            set_caller(accounts.django);
+            assert_eq!(transmitter.resolve("Bob".into()), Ok(accounts.bob));
+            assert_eq!(transmitter.resolve("Nobody".into()), Err(Error::NameNonexistent("Nobody".into())));
 
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_set_expiry_periods(1_000, 500), Ok(()));
+            test::set_block_timestamp::<DefaultEnvironment>(1_500);
+            assert_eq!(transmitter.resolve("Bob".into()), Err(Error::NameExpired));
         }
 
-        /// Cancels the sale offer of the specified username.
-        #[ink(message)]
-        pub fn cancel_sale(&mut self, username: Username) -> Result<(),Error> {
+        #[ink::test]
+        fn listed_names_can_be_bought_by_anyone() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.bob, "Sponge");
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.list_username_for_sale("Bob".into(), 100), Ok(()));
+            assert_eq!(transmitter.list_username_for_sale("Sponge".into(), 50), Ok(()));
+            assert_eq!(transmitter.sell_username_to("Bob".into(), accounts.charlie, 10, 1_000), Err(Error::UsernameAlreadyInSale));
+            assert_eq!(transmitter.list_username_for_sale("Bob".into(), 80), Ok(()));
+
+            let listings = transmitter.get_open_listings(0, 10);
+            assert_eq!(listings.len(), 2);
+            assert_eq!(listings[0], Listing { username: "Bob".into(), seller: accounts.bob, price: 80 });
+            assert_eq!(transmitter.get_open_listings(1, 10).len(), 1);
+            assert_eq!(transmitter.get_open_listings(0, 1).len(), 1);
+
+            set_caller(accounts.django);
+            set_value(79);
+            assert_eq!(
+                transmitter.buy_listed_username("Bob".into()),
+                Err(Error::PaymentFailed { received: 79, required: 80, missing: 1 })
+            );
+            set_value(80);
+            assert_eq!(transmitter.buy_listed_username("Bob".into()), Ok(()));
+            set_value(0);
+            assert_eq!(transmitter.resolve("Bob".into()), Ok(accounts.django));
+            assert_eq!(transmitter.get_open_listings(0, 10).len(), 1);
+            assert_eq!(transmitter.buy_listed_username("Bob".into()), Err(Error::UsernameNotInSale));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_balance(), Ok(76));
+            assert_eq!(transmitter.unlist_username("Sponge".into()), Ok(()));
+            assert_eq!(transmitter.get_open_listings(0, 10), Vec::new());
+        }
 
-            if let Some(username_info) = self.usernames.get(&username) {
+        #[ink::test]
+        fn auctions_go_to_the_highest_bidder() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+
+            set_caller(accounts.bob);
+            test::set_block_timestamp::<DefaultEnvironment>(0);
+            assert_eq!(transmitter.start_auction("Bob".into(), 50, 1_000), Ok(()));
+            assert_eq!(transmitter.start_auction("Bob".into(), 50, 1_000), Err(Error::UsernameAlreadyInSale));
+            assert_eq!(transmitter.list_username_for_sale("Bob".into(), 50), Err(Error::UsernameAlreadyInSale));
+
+            set_caller(accounts.charlie);
+            set_value(40);
+            assert_eq!(transmitter.place_bid("Bob".into()), Err(Error::BidTooLow));
+            set_value(60);
+            assert_eq!(transmitter.place_bid("Bob".into()), Ok(()));
+
+            set_caller(accounts.django);
+            assert_eq!(transmitter.place_bid("Bob".into()), Err(Error::BidTooLow));
+            set_value(100);
+            assert_eq!(transmitter.place_bid("Bob".into()), Ok(()));
+            set_value(0);
+            assert_eq!(transmitter.settle_auction("Bob".into()), Err(Error::AuctionNotEnded));
+
+            test::set_block_timestamp::<DefaultEnvironment>(1_000);
+
+            set_caller(accounts.eve);
+            set_value(200);
+            assert_eq!(transmitter.place_bid("Bob".into()), Err(Error::AuctionEnded));
+            set_value(0);
+            assert_eq!(transmitter.settle_auction("Bob".into()), Ok(()));
+            assert_eq!(transmitter.get_auction("Bob".into()), None);
+            assert_eq!(transmitter.resolve("Bob".into()), Ok(accounts.django));
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.get_balance(), Ok(60));
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_balance(), Ok(95));
+        }
 
-                if username_info.account_id != self.env().caller() {
+        #[ink::test]
+        fn names_can_be_traded_as_psp34_tokens() {
+
+            use crate::PSP34;
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.bob, "Sponge");
+
+            let bob = Id::Bytes("Bob".as_bytes().to_vec());
+
+            assert_eq!(transmitter.total_supply(), 2);
+            assert_eq!(transmitter.balance_of(accounts.bob), 2);
+            assert_eq!(transmitter.owner_of(bob.clone()), Some(accounts.bob));
+            assert_eq!(transmitter.owner_of(Id::U8(1)), None);
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.transfer(accounts.charlie, bob.clone(), Vec::new()), Err(PSP34Error::NotApproved));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.approve(accounts.bob, None, true), Err(PSP34Error::SelfApprove));
+            assert_eq!(transmitter.approve(accounts.charlie, Some(bob.clone()), true), Ok(()));
+            assert!(transmitter.allowance(accounts.bob, accounts.charlie, Some(bob.clone())));
+            assert!(!transmitter.allowance(accounts.bob, accounts.charlie, None));
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.transfer(accounts.django, bob.clone(), Vec::new()), Ok(()));
+            assert_eq!(transmitter.owner_of(bob.clone()), Some(accounts.django));
+            assert_eq!(transmitter.resolve("Bob".into()), Ok(accounts.django));
+            assert_eq!(transmitter.balance_of(accounts.bob), 1);
+            assert_eq!(transmitter.balance_of(accounts.django), 1);
+            assert!(!transmitter.allowance(accounts.django, accounts.charlie, Some(bob.clone())));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.list_username_for_sale("Sponge".into(), 10), Ok(()));
+            assert_eq!(
+                transmitter.transfer(accounts.charlie, Id::Bytes("Sponge".as_bytes().to_vec()), Vec::new()),
+                Err(PSP34Error::Custom("UsernameAlreadyInSale".into()))
+            );
+        }
 
-                    return Err(Error::WrongAccount(username));
+        #[ink::test]
+        fn payment_token_must_be_configured() {
 
-                }
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                if let Some(sale_offers) = self.sale_offers.get() {
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.register_username_with_token("Bob".into()), Err(Error::PaymentTokenNotSet));
+            assert_eq!(transmitter.co_set_payment_token(Some(accounts.frank)), Err(Error::NotContractOwner));
 
-                    if let Some(mut sale_offers) = sale_offers {
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_set_payment_token(Some(accounts.frank)), Ok(()));
+            assert_eq!(transmitter.get_payment_token(), Some(accounts.frank));
+            assert_eq!(transmitter.co_set_fee_exempt(accounts.bob, true), Ok(()));
 
-                        let mut sale_pos: Option<usize> = None;
+            // Nothing is paid, so the token is not called.
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.register_username_with_token("Bob".into()), Ok(()));
+            assert_eq!(transmitter.resolve("Bob".into()), Ok(accounts.bob));
+            assert_eq!(transmitter.register_username_with_token("Bob".into()), Err(Error::NameTaken));
+        }
 
-                        for (pos, sale) in sale_offers.iter().enumerate() {
-    
-                            if sale.username == username {
-    
-                                sale_pos = Some(pos);
-    
-                                break;
-    
-                            }
-    
-                        }
-    
-                        if let Some(pos) = sale_pos {
-    
-                            sale_offers.remove(pos);
+        #[ink::test]
+        fn channel_members_can_post() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.create_channel("rust".into()), Ok(()));
+            assert_eq!(transmitter.create_channel("rust".into()), Err(Error::ChannelExists));
+            assert_eq!(transmitter.post_to_channel("rust".into(), "Bob".into(), b"hi".to_vec()), Ok(()));
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.post_to_channel("rust".into(), "Charlie".into(), b"hey".to_vec()), Err(Error::NotChannelMember));
+            assert_eq!(transmitter.join_channel("rust".into()), Ok(()));
+            assert_eq!(transmitter.join_channel("rust".into()), Err(Error::AlreadyChannelMember));
+            assert_eq!(transmitter.post_to_channel("rust".into(), "Bob".into(), b"hey".to_vec()), Err(Error::WrongAccount("Bob".into())));
+            assert_eq!(transmitter.post_to_channel("rust".into(), "Charlie".into(), b"hey".to_vec()), Ok(()));
+            assert_eq!(transmitter.remove_from_channel("rust".into(), accounts.bob), Err(Error::NotChannelCreator));
+
+            let posts = transmitter.get_channel_messages("rust".into(), 0, 10).unwrap();
+            assert_eq!(posts.len(), 2);
+            assert_eq!(posts[1].from, "Charlie");
+            assert_eq!(posts[1].content, b"hey".to_vec());
+            assert_eq!(transmitter.get_channel_messages("rust".into(), 1, 10).unwrap().len(), 1);
+            assert_eq!(transmitter.get_channel_messages("go".into(), 0, 10), Err(Error::ChannelNonexistent));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.remove_from_channel("rust".into(), accounts.charlie), Ok(()));
+            assert!(!transmitter.is_channel_member("rust".into(), accounts.charlie));
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.join_channel("rust".into()), Err(Error::BannedFromChannel));
+            assert_eq!(transmitter.leave_channel("rust".into()), Err(Error::NotChannelMember));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.leave_channel("rust".into()), Ok(()));
+            assert_eq!(transmitter.channels.get(String::from("rust")).unwrap().member_count, 0);
+        }
 
-                            if sale_offers.len() == 0 {
+        #[ink::test]
+        fn broadcast_reaches_every_recipient_with_one_hash() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            register(&mut transmitter, accounts.django, "Django");
+
+            set_caller(accounts.django);
+            assert_eq!(transmitter.set_inbound_fee("Django".into(), 10), Ok(()));
+
+            set_caller(accounts.bob);
+            set_value(15);
+            let result = transmitter.broadcast_message(
+                "Bob".into(),
+                vec!["Charlie".into(), "Nobody".into(), "Django".into(), "Charlie".into()],
+                MessageType::Text,
+                b"hello all".to_vec()
+            ).unwrap();
+            set_value(0);
+
+            assert_eq!(result.succeeded, vec![String::from("Charlie"), String::from("Django")]);
+            assert_eq!(result.failed, vec![(String::from("Nobody"), Error::NameNonexistent("Nobody".into()))]);
+            assert_eq!(transmitter.get_balance(), Ok(5));
+
+            set_caller(accounts.charlie);
+            let charlie_hash = transmitter.get_all_messages("Charlie".into()).unwrap()[0].hash;
+            set_caller(accounts.django);
+            let django_hash = transmitter.get_all_messages("Django".into()).unwrap()[0].hash;
+            assert_eq!(charlie_hash, django_hash);
+            assert_eq!(transmitter.get_balance(), Ok(10));
+
+            set_caller(accounts.charlie);
+            assert_eq!(
+                transmitter.broadcast_message("Bob".into(), vec!["Django".into()], MessageType::Text, Vec::new()).err(),
+                Some(Error::WrongAccount("Bob".into()))
+            );
+        }
 
-                                self.sale_offers.set(&None);
+        #[ink::test]
+        fn blocked_senders_cannot_deliver() {
 
-                            } else {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                                self.sale_offers.set(&Some(sale_offers));
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-                            }
-    
-                            return Ok(());
-    
-                        } else {
-    
-                            return Err(Error::UsernameNotInSale);
-    
-                        }
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.block_sender("Bob".into(), "Charlie".into()), Err(Error::WrongAccount("Bob".into())));
 
-                    } else {
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.block_sender("Bob".into(), "Charlie".into()), Ok(()));
 
-                        return Err(Error::UsernameNotInSale);
+            set_caller(accounts.charlie);
+            assert_eq!(
+                transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, b"spam".to_vec()),
+                Err(Error::SenderBlocked)
+            );
 
-                    }
-                    
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.unblock_sender("Bob".into(), "Charlie".into()), Ok(()));
 
-                } else {
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, b"hi".to_vec()), Ok(()));
+        }
 
-                    return Err(Error::UsernameNotInSale);
+        #[ink::test]
+        fn allowlist_inbox_accepts_only_approved_senders() {
 
-                }
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-            } else {
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            register(&mut transmitter, accounts.django, "Django");
 
-                return Err(Error::NameNonexistent(username));
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.set_inbox_policy("Bob".into(), InboxPolicy::AllowlistOnly), Ok(()));
+            assert_eq!(transmitter.approve_sender("Bob".into(), "Charlie".into()), Ok(()));
 
-            }
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, b"hi".to_vec()), Ok(()));
 
-        }
+            set_caller(accounts.django);
+            assert_eq!(
+                transmitter.send_message("Django".into(), "Bob".into(), MessageType::Text, b"hi".to_vec()),
+                Err(Error::SenderNotApproved)
+            );
 
-        /// Gets any sale propositions made to you.
-        #[ink(message)]
-        pub fn get_sale_propositions(&mut self) -> Result<Vec<Sale>, Error> {
-            
-            let sale_offers = self.sale_offers.get();
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.revoke_sender("Bob".into(), "Charlie".into()), Ok(()));
 
-            if let Some(sale_offers) = sale_offers {
+            set_caller(accounts.charlie);
+            assert_eq!(
+                transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, b"hi".to_vec()),
+                Err(Error::SenderNotApproved)
+            );
 
-                if let Some(sale_offers) = sale_offers {
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.set_inbox_policy("Bob".into(), InboxPolicy::Open), Ok(()));
 
-                    let mut sales_to_user = Vec::<Sale>::new();
+            set_caller(accounts.django);
+            assert_eq!(transmitter.send_message("Django".into(), "Bob".into(), MessageType::Text, b"hi".to_vec()), Ok(()));
+        }
 
-                    for sale in sale_offers.iter() {
-    
-                        if sale.to == self.env().caller() {
-    
-                            sales_to_user.push(Sale { username: sale.username.clone(), to: sale.to, price: sale.price } );
-    
-                        }
-    
-                    }
-    
-                    if sales_to_user.len() == 0 {
-    
-                        return Err(Error::NoSalesForYou);
-    
-                    } else {
-    
-                        return Ok(sales_to_user);
-    
-                    }
+        #[ink::test]
+        fn approved_senders_skip_the_inbound_fee() {
 
-                } else {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                    return Err(Error::NoSalesForYou);
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-                }
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.set_inbound_fee("Bob".into(), 20), Ok(()));
 
-            } else {
+            set_caller(accounts.charlie);
+            assert_eq!(
+                transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, b"hi".to_vec()),
+                Err(Error::PaymentFailed { received: 0, required: 20, missing: 20 })
+            );
 
-                return Err(Error::NoSalesForYou);
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.approve_sender("Bob".into(), "Charlie".into()), Ok(()));
 
-            }
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, b"hi".to_vec()), Ok(()));
 
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_balance(), Ok(0));
         }
 
-        /// Executes a proposed sale.
-        #[ink(message,payable)]
-        pub fn buy_username(&mut self, username: Username) -> Result<(),Error> {
-
-
-            // Get the value that was transferred to contract.
-            let transferred = self.env().transferred_value();
-
-            let mut sale: Sale = Sale { username: "".into(), to: [0u8;32].into(), price: 0 };
-            let mut sale_pos: Option<usize> = None;
+        #[ink::test]
+        fn expired_messages_are_hidden_and_pruned() {
 
-            if let Some(sales) = self.sale_offers.get() {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                if let Some(sales) = sales {
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-                    for (pos,s) in sales.iter().enumerate() {
+            test::set_block_timestamp::<DefaultEnvironment>(0);
+            set_caller(accounts.charlie);
+            let options = SendOptions { expires_at: Some(1_000), ..Default::default() };
+            assert!(transmitter.send_message_with_options("Charlie".into(), "Bob".into(), MessageType::Text, b"soon gone".to_vec(), options).is_ok());
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, b"kept".to_vec()), Ok(()));
 
-                        if &s.username == &username {
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_all_messages("Bob".into()).unwrap().len(), 2);
+            assert_eq!(transmitter.prune_expired("Bob".into()), Ok(0));
 
-                            sale.username = s.username.clone();
-                            sale.to = s.to;
-                            sale.price = s.price;
+            test::set_block_timestamp::<DefaultEnvironment>(1_000);
+            let messages = transmitter.get_all_messages("Bob".into()).unwrap();
+            assert_eq!(messages.len(), 1);
+            assert_eq!(messages[0].content, b"kept".to_vec());
 
-                            sale_pos = Some(pos);
+            set_caller(accounts.django);
+            assert_eq!(transmitter.prune_expired("Bob".into()), Ok(1));
+            assert_eq!(transmitter.usernames.get(String::from("Bob")).unwrap().message_count, 1);
+        }
 
-                            break;
-                        }
+        #[ink::test]
+        fn read_receipts_record_the_read_time() {
 
-                    }
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                }
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "first");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "second");
 
-            } else {
+            set_caller(accounts.bob);
+            let hash = transmitter.get_all_messages("Bob".into()).unwrap()[0].hash;
+            assert_eq!(transmitter.get_unread_messages("Bob".into()).unwrap().len(), 2);
 
-                return Err(Error::NoSalesForYou);
+            test::set_block_timestamp::<DefaultEnvironment>(500);
+            assert_eq!(transmitter.mark_as_read("Bob".into(), hash), Ok(()));
+            assert!(matches!(last_event(), Event::MessageRead(event) if event.hash == hash));
 
-            }
+            test::set_block_timestamp::<DefaultEnvironment>(900);
+            assert_eq!(transmitter.mark_as_read("Bob".into(), hash), Ok(()));
+            assert_eq!(transmitter.get_all_messages("Bob".into()).unwrap()[0].read_at, Some(500));
 
-            if sale.to != self.env().caller() {
+            let unread = transmitter.get_unread_messages("Bob".into()).unwrap();
+            assert_eq!(unread.len(), 1);
+            assert_eq!(unread[0].content, b"second".to_vec());
 
-                return Err(Error::NoSalesForYou);
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.get_unread_messages("Bob".into()), Err(Error::WrongAccount("Bob".into())));
+        }
 
-            }
+        #[ink::test]
+        fn unread_counter_follows_the_inbox() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "one");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "two");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "three");
+
+            set_caller(accounts.bob);
+            let messages = transmitter.get_all_messages("Bob".into()).unwrap();
+            assert_eq!(transmitter.get_unread_count("Bob".into()), Ok(3));
+
+            assert_eq!(transmitter.mark_as_read("Bob".into(), messages[0].hash), Ok(()));
+            assert_eq!(transmitter.delete_message("Bob".into(), messages[0].hash), Ok(()));
+            assert_eq!(transmitter.delete_message("Bob".into(), messages[1].hash), Ok(()));
+            assert_eq!(transmitter.get_unread_count("Bob".into()), Ok(1));
+
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "four");
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_unread_count("Bob".into()), Ok(2));
+            assert_eq!(transmitter.mark_all_read("Bob".into()), Ok(2));
+            assert_eq!(transmitter.get_unread_count("Bob".into()), Ok(0));
+            assert_eq!(transmitter.has_unread("Bob".into()), Ok(false));
+            assert_eq!(transmitter.mark_all_read("Bob".into()), Ok(0));
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.mark_all_read("Bob".into()), Err(Error::WrongAccount("Bob".into())));
+        }
 
-            if transferred < sale.price {
+        #[ink::test]
+        fn threads_follow_replies_across_inboxes() {
 
-                if let Some(mut user_info) = self.users.get(&self.env().caller()) {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                    user_info.balance += transferred;
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            register(&mut transmitter, accounts.django, "Django");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "question");
 
-                    self.users.insert(&self.env().caller(), &user_info);
+            set_caller(accounts.bob);
+            let root = transmitter.get_all_messages("Bob".into()).unwrap()[0].hash;
+            let answer = transmitter.send_message_with_options("Bob".into(), "Charlie".into(), MessageType::ReplyTo { hash: root }, b"answer".to_vec(), SendOptions::default()).unwrap();
 
-                } else {
+            set_caller(accounts.charlie);
+            assert!(transmitter.send_message_with_options("Charlie".into(), "Bob".into(), MessageType::ReplyTo { hash: answer }, b"thanks".to_vec(), SendOptions::default()).is_ok());
 
-                    let new_user_info = UserInfo {
-                        usernames: None,
-                        balance: transferred,
-                    };
+            let thread = transmitter.get_thread(root, 10).unwrap();
+            let contents: Vec<Vec<u8>> = thread.into_iter().map(|message| message.content).collect();
+            assert_eq!(contents, vec![b"question".to_vec(), b"answer".to_vec(), b"thanks".to_vec()]);
+            assert_eq!(transmitter.get_thread(root, 2).unwrap().len(), 2);
+            assert_eq!(transmitter.get_thread([0u8;32], 10), Err(Error::MessageNonexistent));
 
-                    self.users.insert(&self.env().caller(), &new_user_info);
+            set_caller(accounts.django);
+            assert_eq!(transmitter.get_thread(root, 10), Err(Error::WrongAccount("Bob".into())));
+        }
 
-                }
+        #[ink::test]
+        fn encryption_keys_are_published_per_name() {
 
-            } else if transferred == sale.price {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                if let Some(username_info) = self.usernames.get(&username) {
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-                    if let Some(previous_owner) = self.users.get(&username_info.account_id) {
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_encryption_key("Bob".into()), Ok(None));
+            assert_eq!(transmitter.set_encryption_key("Bob".into(), [9u8;32]), Ok(()));
+            assert_eq!(transmitter.get_encryption_key("Bob".into()), Ok(Some([9u8;32])));
 
-                        if let Some(new_owner) = self.users.get(&self.env().caller()) {
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.set_encryption_key("Bob".into(), [1u8;32]), Err(Error::WrongAccount("Bob".into())));
+            assert_eq!(transmitter.get_encryption_key("Nobody".into()), Err(Error::NameNonexistent("Nobody".into())));
 
-                            let mut usernames = Vec::<Username>::new();
-        
-                            if let Some(u_n) = new_owner.usernames {
-        
-                                usernames = u_n;
-        
-                            }
-        
-                            usernames.push(username.clone());
-        
-                            let new_user_info = UserInfo {
-                                usernames: Some(usernames),
-                                balance: new_owner.balance,
-                            };
-        
-        
-                            self.users.insert(&self.env().caller(), &new_user_info);
-        
-                        } else {
-        
-                            let mut usernames = Vec::<Username>::new();
-        
-                            usernames.push(username.clone());
-        
-                            let new_user_info = UserInfo {
-                                usernames: Some(usernames),
-                                balance: 0,
-                            };
-        
-                            self.users.insert(&self.env().caller(), &new_user_info);
-        
-                        }
+            let mtype = MessageType::Encrypted { scheme: "x25519-xsalsa20-poly1305".into() };
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), mtype.clone(), vec![1, 2, 3]), Ok(()));
 
-                        self.owner.balance += self.registration_fee;
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_all_messages("Bob".into()).unwrap()[0].mtype, mtype);
+        }
 
-                        let mut usernames = Vec::<Username>::new();
+        #[ink::test]
+        fn sent_messages_are_listed_for_the_sender() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            register(&mut transmitter, accounts.django, "Django");
+            send_text(&mut transmitter, accounts.bob, "Bob", "Charlie", "one");
+            send_text(&mut transmitter, accounts.bob, "Bob", "Django", "two");
+            send_text(&mut transmitter, accounts.bob, "Bob", "Charlie", "three");
+
+            set_caller(accounts.bob);
+            let sent = transmitter.get_sent_messages("Bob".into(), 0, 10).unwrap();
+            assert_eq!(sent.len(), 3);
+            assert_eq!(sent[1].0, "Django");
+            assert_eq!(sent[1].1.content, b"two".to_vec());
+            assert_eq!(transmitter.get_sent_messages("Bob".into(), 1, 1).unwrap()[0].1.content, b"two".to_vec());
+
+            set_caller(accounts.charlie);
+            let hash = transmitter.get_all_messages("Charlie".into()).unwrap()[0].hash;
+            assert_eq!(transmitter.delete_message("Charlie".into(), hash), Ok(()));
+            assert_eq!(transmitter.get_sent_messages("Bob".into(), 0, 10), Err(Error::WrongAccount("Bob".into())));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_sent_messages("Bob".into(), 0, 10).unwrap().len(), 2);
+        }
 
-                        if let Some(u_n) = previous_owner.usernames {
+        #[ink::test]
+        fn scheduled_messages_show_up_on_time() {
 
-                            usernames = u_n;
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                        }
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-                        let mut pos: Option<usize> = None;
+            test::set_block_timestamp::<DefaultEnvironment>(0);
+            set_caller(accounts.charlie);
+            let later = transmitter.schedule_message("Charlie".into(), "Bob".into(), MessageType::Text, b"later".to_vec(), 1_000).unwrap();
+            let never = transmitter.schedule_message("Charlie".into(), "Bob".into(), MessageType::Text, b"never".to_vec(), 1_000).unwrap();
 
-                        for (p,u) in usernames.iter().enumerate() {
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_all_messages("Bob".into()), Err(Error::NoMessages));
+            assert_eq!(transmitter.cancel_scheduled(never), Err(Error::WrongAccount("Charlie".into())));
 
-                            if u == &username {
-                                pos = Some(p);
-                                break;
-                            } 
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.cancel_scheduled(never), Ok(()));
+            assert_eq!(transmitter.cancel_scheduled(never), Err(Error::MessageNonexistent));
 
-                        }
+            test::set_block_timestamp::<DefaultEnvironment>(1_000);
+            assert_eq!(transmitter.cancel_scheduled(later), Err(Error::MessageAlreadyDelivered));
 
-                        if let Some(pos) = pos {
+            set_caller(accounts.bob);
+            let messages = transmitter.get_all_messages("Bob".into()).unwrap();
+            assert_eq!(messages.len(), 1);
+            assert_eq!(messages[0].hash, later);
+        }
 
-                            usernames.remove(pos);
+        #[ink::test]
+        fn forwarding_keeps_the_original_lineage() {
 
-                        }
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                        let new_user_info = UserInfo {
-                            usernames: Some(usernames),
-                            balance: transferred - self.registration_fee,
-                        };
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            register(&mut transmitter, accounts.django, "Django");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "rumour");
 
-                        self.users.insert(&username_info.account_id, &new_user_info);
+            set_caller(accounts.bob);
+            let original = transmitter.get_all_messages("Bob".into()).unwrap()[0].hash;
+            let first = transmitter.forward_message("Bob".into(), original, "Django".into()).unwrap();
 
-                    } else {
+            set_caller(accounts.django);
+            let second = transmitter.forward_message("Django".into(), first, "Charlie".into()).unwrap();
 
-                        if let Some(mut user_info) = self.users.get(&self.env().caller()) {
+            set_caller(accounts.charlie);
+            let messages = transmitter.get_all_messages("Charlie".into()).unwrap();
+            assert_eq!(messages[0].hash, second);
+            assert_eq!(messages[0].from, "Django");
+            assert_eq!(messages[0].mtype, MessageType::Forwarded { original_hash: original, original_from: "Charlie".into() });
+        }
 
-                            user_info.balance += transferred;
+        #[ink::test]
+        fn senders_can_edit_their_messages() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "helo");
+
+            set_caller(accounts.bob);
+            let hash = transmitter.get_all_messages("Bob".into()).unwrap()[0].hash;
+            assert_eq!(transmitter.edit_message("Charlie".into(), "Bob".into(), hash, b"hello".to_vec()), Err(Error::WrongAccount("Charlie".into())));
+
+            set_caller(accounts.charlie);
+            test::advance_block::<DefaultEnvironment>();
+            let edited = transmitter.edit_message("Charlie".into(), "Bob".into(), hash, b"hello".to_vec()).unwrap();
+            assert_ne!(edited, hash);
+            assert_eq!(transmitter.edit_message("Charlie".into(), "Bob".into(), hash, b"again".to_vec()), Err(Error::MessageNonexistent));
+            assert_eq!(transmitter.get_sent_messages("Charlie".into(), 0, 10).unwrap()[0].1.hash, edited);
+
+            set_caller(accounts.bob);
+            let message = &transmitter.get_all_messages("Bob".into()).unwrap()[0];
+            assert_eq!(message.content, b"hello".to_vec());
+            assert_eq!(message.hash, edited);
+            assert_eq!(message.edited_from, Some(hash));
+            assert_eq!(transmitter.get_message_with_proof("Bob".into(), edited).unwrap().1, edited);
+        }
 
-                            self.users.insert(&self.env().caller(), &user_info);
+        #[ink::test]
+        fn both_ends_of_a_message_can_react() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            register(&mut transmitter, accounts.django, "Django");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "joke");
+
+            set_caller(accounts.bob);
+            let hash = transmitter.get_all_messages("Bob".into()).unwrap()[0].hash;
+            assert_eq!(transmitter.react_to_message("Bob".into(), hash, "laugh".into()), Ok(()));
+            assert_eq!(transmitter.react_to_message("Bob".into(), hash, "x".repeat(17)), Err(Error::ReactionTooLong));
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.react_to_message("Charlie".into(), hash, "thumbs up".into()), Ok(()));
+            assert_eq!(transmitter.react_to_message("Charlie".into(), hash, "wink".into()), Ok(()));
+            assert_eq!(
+                transmitter.get_reactions("Charlie".into(), hash),
+                Ok(vec![(String::from("Bob"), String::from("laugh")), (String::from("Charlie"), String::from("wink"))])
+            );
+
+            set_caller(accounts.django);
+            assert_eq!(transmitter.react_to_message("Django".into(), hash, "meh".into()), Err(Error::MessageNonexistent));
+            assert_eq!(transmitter.get_reactions("Bob".into(), hash), Err(Error::WrongAccount("Bob".into())));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.react_to_message("Bob".into(), hash, String::new()), Ok(()));
+            assert_eq!(transmitter.get_reactions("Bob".into(), hash).unwrap().len(), 1);
+            assert_eq!(transmitter.delete_message("Bob".into(), hash), Ok(()));
+            assert_eq!(transmitter.reactions.get(hash), None);
+        }
 
-                        } else {
+        #[ink::test]
+        fn attachments_must_reference_their_content() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            let attachment = |content_uri: &str, content_hash: [u8;32]| MessageType::Attachment {
+                subject: "Holiday pictures".into(),
+                content_uri: content_uri.into(),
+                content_hash,
+                mime: "image/png".into(),
+            };
+
+            set_caller(accounts.charlie);
+            let uri = "ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), attachment(uri, [0u8;32]), Vec::new()), Err(Error::InvalidAttachment));
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), attachment("", [3u8;32]), Vec::new()), Err(Error::InvalidAttachment));
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), attachment(&"x".repeat(257), [3u8;32]), Vec::new()), Err(Error::InvalidAttachment));
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), attachment(uri, [3u8;32]), Vec::new()), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_all_messages("Bob".into()).unwrap()[0].mtype, attachment(uri, [3u8;32]));
+        }
 
-                            let new_user_info = UserInfo {
-                                usernames: None,
-                                balance: transferred,
-                            };
+        #[ink::test]
+        fn requests_are_matched_with_their_response() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            set_caller(accounts.bob);
+            let first = transmitter.send_request("Bob".into(), "Charlie".into(), b"price?".to_vec()).unwrap();
+            let second = transmitter.send_request("Bob".into(), "Charlie".into(), b"stock?".to_vec()).unwrap();
+            assert_ne!(first, second);
+            assert_eq!(transmitter.get_response_for(second), Ok(None));
+            assert_eq!(
+                transmitter.send_message("Bob".into(), "Charlie".into(), MessageType::Response { request_id: first }, Vec::new()),
+                Err(Error::MessageTypeNotAllowed)
+            );
+            assert_eq!(transmitter.send_response("Bob".into(), second, Vec::new()).err(), Some(Error::WrongAccount("Bob".into())));
+
+            set_caller(accounts.charlie);
+            let request = &transmitter.get_all_messages("Charlie".into()).unwrap()[1];
+            assert_eq!(request.mtype, MessageType::Request { id: second });
+            let hash = transmitter.send_response("Charlie".into(), second, b"12".to_vec()).unwrap();
+            assert_eq!(transmitter.send_response("Charlie".into(), second, b"13".to_vec()).err(), Some(Error::AlreadyResponded));
+            assert_eq!(transmitter.get_response_for(second).err(), Some(Error::WrongAccount("Bob".into())));
+            assert_eq!(transmitter.get_response_for(99).err(), Some(Error::RequestNonexistent));
+
+            set_caller(accounts.bob);
+            let response = transmitter.get_response_for(second).unwrap().expect("no response");
+            assert_eq!(response.hash, hash);
+            assert_eq!(response.mtype, MessageType::Response { request_id: second });
+            assert_eq!(response.content, b"12".to_vec());
+        }
 
-                            self.users.insert(&self.env().caller(), &new_user_info);
+        #[ink::test]
+        fn registering_and_releasing_names_mint_and_burn_tokens() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            let transfers = || -> Vec<(Option<AccountId>, Option<AccountId>)> {
+                test::recorded_events()
+                    .filter_map(|event| match <Event as scale::Decode>::decode(&mut &event.data[..]) {
+                        Ok(Event::Transfer(event)) => Some((event.from, event.to)),
+                        _ => None,
+                    })
+                    .collect()
+            };
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            assert_eq!(transfers(), vec![(None, Some(accounts.bob))]);
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.close_account(), Ok(()));
+            assert_eq!(transfers(), vec![(None, Some(accounts.bob)), (Some(accounts.bob), None)]);
+        }
 
-                        }
+        #[ink::test]
+        fn names_can_be_given_away() {
 
-                    }
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                } else {
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.bob, "Sponge");
+            send_text(&mut transmitter, accounts.bob, "Bob", "Sponge", "keep this");
 
-                    if let Some(mut user_info) = self.users.get(&self.env().caller()) {
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.transfer_username("Sponge".into(), accounts.charlie), Err(Error::WrongAccount("Sponge".into())));
 
-                        user_info.balance += transferred;
-    
-                        self.users.insert(&self.env().caller(), &user_info);
-    
-                    } else {
-    
-                        let new_user_info = UserInfo {
-                            usernames: None,
-                            balance: transferred,
-                        };
-    
-                        self.users.insert(&self.env().caller(), &new_user_info);
-    
-                    }
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.transfer_username("Sponge".into(), AccountId::from([0u8;32])), Err(Error::ZeroAccount));
+            assert_eq!(transmitter.transfer_username("Sponge".into(), accounts.charlie), Ok(()));
+            assert!(matches!(last_event(), Event::Transfer(event) if event.to == Some(accounts.charlie)));
+            assert_eq!(transmitter.get_usernames(), Ok(vec![String::from("Bob")]));
 
-                }
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.get_usernames(), Ok(vec![String::from("Sponge")]));
+            assert_eq!(transmitter.get_all_messages("Sponge".into()).unwrap().len(), 1);
+        }
 
-                if let Some(sales) = self.sale_offers.get() {
+        #[ink::test]
+        fn batch_registration_lists_every_refused_name() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+
+            set_caller(accounts.charlie);
+            set_value(10);
+            assert_eq!(
+                transmitter.register_usernames(vec!["Charlie".into(), "Bob".into(), " x".into(), "Charlie".into(), SYSTEM_NAME.into()]),
+                Err(Error::NamesRefused(vec![
+                    (String::from("Bob"), Error::NameTaken),
+                    (String::from(" x"), Error::InvalidName),
+                    (String::from("Charlie"), Error::NameTaken),
+                    (String::from(SYSTEM_NAME), Error::NameReserved),
+                ]))
+            );
+            assert_eq!(transmitter.resolve("Charlie".into()), Err(Error::NameNonexistent("Charlie".into())));
+            assert_eq!(transmitter.get_usernames(), Err(Error::NoAccount));
+            assert_eq!(transmitter.register_usernames(vec!["Charlie".into(), "Carl".into()]), Ok(()));
+            set_value(0);
+            assert_eq!(transmitter.resolve("Carl".into()), Ok(accounts.charlie));
+        }
 
-                    if let Some(mut sales) = sales {
+        #[ink::test]
+        fn accounts_can_choose_a_primary_name() {
 
-                        if let Some(pos) = sale_pos {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                            sales.remove(pos);
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.bob, "Robert");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-                            self.sale_offers.set(&Some(sales));
+            assert_eq!(transmitter.primary_of(accounts.bob), None);
 
-                        }
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.set_primary_username("Charlie".into()), Err(Error::WrongAccount("Charlie".into())));
+            assert_eq!(transmitter.set_primary_username("Robert".into()), Ok(()));
+            assert_eq!(transmitter.primary_of(accounts.bob), Some(String::from("Robert")));
 
-                    }
+            assert_eq!(transmitter.transfer_username("Robert".into(), accounts.charlie), Ok(()));
+            assert_eq!(transmitter.primary_of(accounts.bob), None);
+        }
 
-                }
+        #[ink::test]
+        fn contracts_can_send_from_their_names() {
 
-            } else {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                if let Some(username_info) = self.usernames.get(&username) {
+            // Off-chain, a contract calling the transmitter looks like any other caller.
+            let bot = AccountId::from([7u8;32]);
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, bot, "my_bot");
 
-                    if let Some(previous_owner) = self.users.get(&username_info.account_id) {
+            set_caller(bot);
+            let hash = transmitter.send_message_from_contract("my_bot".into(), "Bob".into(), MessageType::Text, b"beep".to_vec()).unwrap();
+            assert_eq!(
+                transmitter.send_message_from_contract("Bob".into(), "my_bot".into(), MessageType::Text, Vec::new()),
+                Err(Error::WrongAccount("Bob".into()))
+            );
 
-                        if let Some(new_owner) = self.users.get(&self.env().caller()) {
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_all_messages("Bob".into()).unwrap()[0].hash, hash);
+        }
 
-                            let mut usernames = Vec::<Username>::new();
-        
-                            if let Some(u_n) = new_owner.usernames {
-        
-                                usernames = u_n;
-        
-                            }
-        
-                            usernames.push(username.clone());
-        
-                            let new_user_info = UserInfo {
-                                usernames: Some(usernames),
-                                balance: new_owner.balance + (transferred - sale.price),
-                            };
-        
-        
-                            self.users.insert(&self.env().caller(), &new_user_info);
-        
-                        } else {
-        
-                            let mut usernames = Vec::<Username>::new();
-        
-                            usernames.push(username.clone());
-        
-                            let new_user_info = UserInfo {
-                                usernames: Some(usernames),
-                                balance: transferred - sale.price,
-                            };
-        
-                            self.users.insert(&self.env().caller(), &new_user_info);
-        
-                        }
+        #[ink::test]
+        fn delegates_can_send_until_revoked_or_expired() {
 
-                        self.owner.balance += self.registration_fee;
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                        let mut usernames = Vec::<Username>::new();
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-                        if let Some(u_n) = previous_owner.usernames {
+            set_caller(accounts.django);
+            assert_eq!(transmitter.authorize_sender("Charlie".into(), accounts.django, 1_000), Err(Error::WrongAccount("Charlie".into())));
 
-                            usernames = u_n;
+            set_caller(accounts.charlie);
+            transmitter.authorize_sender("Charlie".into(), accounts.django, 1_000).unwrap();
+            assert_eq!(transmitter.delegate_expiry("Charlie".into(), accounts.django), Some(1_000));
 
-                        }
+            send_text(&mut transmitter, accounts.django, "Charlie", "Bob", "from the bot");
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_all_messages("Bob".into()).unwrap()[0].from, "Charlie");
 
-                        let mut pos: Option<usize> = None;
+            // Delegates can send, but nothing else.
+            set_caller(accounts.django);
+            assert_eq!(transmitter.get_all_messages("Charlie".into()), Err(Error::WrongAccount("Charlie".into())));
 
-                        for (p,u) in usernames.iter().enumerate() {
+            set_caller(accounts.charlie);
+            transmitter.revoke_delegate("Charlie".into(), accounts.django).unwrap();
+            set_caller(accounts.django);
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, Vec::new()), Err(Error::WrongAccount("Charlie".into())));
 
-                            if u == &username {
-                                pos = Some(p);
-                                break;
-                            } 
+            set_caller(accounts.charlie);
+            transmitter.authorize_sender("Charlie".into(), accounts.django, 1_000).unwrap();
+            test::set_block_timestamp::<DefaultEnvironment>(1_000);
+            assert_eq!(transmitter.delegate_expiry("Charlie".into(), accounts.django), None);
+            set_caller(accounts.django);
+            assert_eq!(transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, Vec::new()), Err(Error::WrongAccount("Charlie".into())));
+        }
 
-                        }
+        #[ink::test]
+        fn backup_accounts_recover_names_and_balance_after_the_delay() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.bob, "Bobby");
+            transmitter.store_user(&accounts.bob, &UserInfo { usernames: Some(vec!["Bob".into(), "Bobby".into()]), balance: 30 }).unwrap();
+
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.recover_account(accounts.bob), Err(Error::NotRecoveryAccount));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.set_recovery_account(accounts.bob), Err(Error::InvalidRecoveryAccount));
+            transmitter.set_recovery_account(accounts.charlie).unwrap();
+
+            set_caller(accounts.django);
+            assert_eq!(transmitter.recover_account(accounts.bob), Err(Error::NotRecoveryAccount));
+
+            // The holder of the key can stop a recovery started by the backup.
+            set_caller(accounts.charlie);
+            transmitter.recover_account(accounts.bob).unwrap();
+            assert!(matches!(last_event(), Event::RecoveryStarted(event) if event.ready_at == RECOVERY_DELAY));
+            set_caller(accounts.bob);
+            transmitter.cancel_recovery().unwrap();
+            assert_eq!(transmitter.get_recovery(accounts.bob), Some(Recovery { backup: accounts.charlie, started_at: None }));
+
+            set_caller(accounts.charlie);
+            transmitter.recover_account(accounts.bob).unwrap();
+            assert_eq!(transmitter.recover_account(accounts.bob), Err(Error::RecoveryNotReady));
+
+            test::set_block_timestamp::<DefaultEnvironment>(RECOVERY_DELAY);
+            transmitter.recover_account(accounts.bob).unwrap();
+            assert!(matches!(last_event(), Event::AccountRecovered(event) if event.usernames.len() == 2 && event.balance == 30));
+
+            assert_eq!(transmitter.resolve("Bob".into()), Ok(accounts.charlie));
+            assert_eq!(transmitter.resolve("Bobby".into()), Ok(accounts.charlie));
+            assert_eq!(transmitter.get_balance(), Ok(30));
+            assert_eq!(transmitter.total_user_balance, 30);
+            assert_eq!(transmitter.users.get(accounts.bob), None);
+
+            // What the account gets afterwards is not swept right away.
+            assert_eq!(transmitter.get_recovery(accounts.bob), Some(Recovery { backup: accounts.charlie, started_at: None }));
+            register(&mut transmitter, accounts.bob, "Roberto");
+            set_caller(accounts.charlie);
+            transmitter.recover_account(accounts.bob).unwrap();
+            assert!(matches!(last_event(), Event::RecoveryStarted(_)));
+            assert_eq!(transmitter.resolve("Roberto".into()), Ok(accounts.bob));
+        }
 
-                        if let Some(pos) = pos {
+        #[ink::test]
+        fn ownership_changes_only_once_accepted() {
 
-                            usernames.remove(pos);
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                        }
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.co_propose_owner(accounts.bob), Err(Error::NotContractOwner));
 
-                        let new_user_info = UserInfo {
-                            usernames: Some(usernames),
-                            balance: sale.price - self.registration_fee,
-                        };
+            set_caller(accounts.alice);
+            transmitter.co_propose_owner(accounts.charlie).unwrap();
+            assert_eq!(transmitter.get_pending_owner(), Some(accounts.charlie));
+            assert_eq!(transmitter.owner.account_id, accounts.alice);
 
-                        self.users.insert(&username_info.account_id, &new_user_info);
+            // A mistyped proposal can be replaced before anyone accepts it.
+            transmitter.co_propose_owner(accounts.django).unwrap();
 
-                    } else {
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.accept_ownership(), Err(Error::NotPendingOwner));
 
-                        if let Some(mut user_info) = self.users.get(&self.env().caller()) {
+            set_caller(accounts.django);
+            transmitter.accept_ownership().unwrap();
+            assert_eq!(transmitter.owner.account_id, accounts.django);
+            assert_eq!(transmitter.get_pending_owner(), None);
+            assert_eq!(transmitter.accept_ownership(), Err(Error::NotPendingOwner));
+        }
 
-                            user_info.balance += transferred;
+        #[ink::test]
+        fn sensitive_owner_actions_need_approvals() {
 
-                            self.users.insert(&self.env().caller(), &user_info);
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                        } else {
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_set_owners(vec![accounts.bob], 3), Err(Error::InvalidThreshold));
+            transmitter.co_set_owners(vec![accounts.bob, accounts.charlie], 2).unwrap();
+            assert_eq!(transmitter.get_owners(), (vec![accounts.bob, accounts.charlie], 2));
 
-                            let new_user_info = UserInfo {
-                                usernames: None,
-                                balance: transferred,
-                            };
+            assert_eq!(transmitter.co_set_fee(42), Err(Error::NotEnoughApprovals));
 
-                            self.users.insert(&self.env().caller(), &new_user_info);
+            // Approvals are given per action: approving another fee does not count.
+            set_caller(accounts.bob);
+            let other = transmitter.action_hash(OwnerAction::SetFee { new_fee: 7 });
+            transmitter.co_approve_action(other).unwrap();
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_set_fee(42), Err(Error::NotEnoughApprovals));
 
-                        }
+            set_caller(accounts.django);
+            let action_hash = transmitter.action_hash(OwnerAction::SetFee { new_fee: 42 });
+            assert_eq!(transmitter.co_approve_action(action_hash), Err(Error::NotContractOwner));
 
-                    }
+            set_caller(accounts.charlie);
+            transmitter.co_approve_action(action_hash).unwrap();
+            assert!(matches!(last_event(), Event::ActionApproved(event) if event.action_hash == action_hash && event.by == accounts.charlie));
 
-                } else {
+            // Co-owners approve, but only the contract owner carries actions out.
+            assert_eq!(transmitter.co_set_fee(42), Err(Error::NotContractOwner));
 
-                    if let Some(mut user_info) = self.users.get(&self.env().caller()) {
+            set_caller(accounts.alice);
+            transmitter.co_set_fee(42).unwrap();
+            assert_eq!(transmitter.registration_fee, 42);
 
-                        user_info.balance += transferred;
-    
-                        self.users.insert(&self.env().caller(), &user_info);
-    
-                    } else {
-    
-                        let new_user_info = UserInfo {
-                            usernames: None,
-                            balance: transferred,
-                        };
-    
-                        self.users.insert(&self.env().caller(), &new_user_info);
-    
-                    }
+            // The approvals were used up.
+            assert_eq!(transmitter.co_set_fee(42), Err(Error::NotEnoughApprovals));
 
-                }
+            // Settings that move funds or waive fees need approvals too.
+            assert_eq!(transmitter.co_set_fee_exempt(accounts.django, true), Err(Error::NotEnoughApprovals));
+            assert_eq!(transmitter.co_set_bulk_discount(2, 50), Err(Error::NotEnoughApprovals));
+            assert_eq!(transmitter.co_set_payment_token(Some(accounts.eve)), Err(Error::NotEnoughApprovals));
+            assert_eq!(transmitter.co_sweep_dust(vec![accounts.bob], Balance::MAX), Err(Error::NotEnoughApprovals));
 
-                if let Some(sales) = self.sale_offers.get() {
+            // Actions that are not sensitive need no approvals.
+            assert_eq!(transmitter.co_set_registry_cap(100), Ok(()));
+        }
 
-                    if let Some(mut sales) = sales {
+        #[ink::test]
+        fn moderators_remove_messages_and_freeze_names() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "spam");
+
+            set_caller(accounts.bob);
+            let hash = transmitter.get_all_messages("Bob".into()).unwrap()[0].hash;
+
+            set_caller(accounts.django);
+            assert_eq!(transmitter.mod_remove_message("Bob".into(), hash), Err(Error::NotModerator));
+            assert_eq!(transmitter.co_set_role(accounts.django, Some(Role::Moderator)), Err(Error::NotContractOwner));
+
+            set_caller(accounts.alice);
+            assert_eq!(transmitter.co_set_role(accounts.django, Some(Role::Owner)), Err(Error::InvalidRole));
+            transmitter.co_set_role(accounts.django, Some(Role::Moderator)).unwrap();
+            assert_eq!(transmitter.role_of(accounts.django), Some(Role::Moderator));
+            assert_eq!(transmitter.role_of(accounts.alice), Some(Role::Owner));
+
+            set_caller(accounts.django);
+            transmitter.mod_remove_message("Bob".into(), hash).unwrap();
+            assert!(matches!(last_event(), Event::MessageRemoved(event) if event.hash == hash && event.by == accounts.django));
+            assert_eq!(transmitter.mod_remove_message("Bob".into(), hash), Err(Error::MessageNonexistent));
+
+            transmitter.mod_freeze_username("Charlie".into(), true).unwrap();
+            assert!(transmitter.is_frozen("Charlie".into()));
+            set_caller(accounts.charlie);
+            assert_eq!(
+                transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Text, Vec::new()),
+                Err(Error::UsernameFrozen("Charlie".into()))
+            );
+
+            // Moderators cannot touch the settings reserved to the contract owner.
+            set_caller(accounts.django);
+            assert_eq!(transmitter.co_set_fee(1), Err(Error::NotContractOwner));
+            transmitter.mod_freeze_username("Charlie".into(), false).unwrap();
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "sorry");
+
+            set_caller(accounts.alice);
+            transmitter.co_set_role(accounts.django, None).unwrap();
+            set_caller(accounts.django);
+            assert_eq!(transmitter.mod_freeze_username("Charlie".into(), true), Err(Error::NotModerator));
+        }
 
-                        if let Some(pos) = sale_pos {
+        #[ink::test]
+        fn pausing_halts_registrations_messages_and_sales() {
 
-                            sales.remove(pos);
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                            self.sale_offers.set(&Some(sales));
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-                        }
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.co_pause(), Err(Error::NotContractOwner));
 
-                    }
+            set_caller(accounts.alice);
+            transmitter.co_pause().unwrap();
+            assert!(transmitter.is_paused());
 
-                }
+            set_caller(accounts.django);
+            set_value(transmitter.check_fee());
+            assert_eq!(transmitter.register_username("Django".into()), Err(Error::ContractPaused));
+            set_value(0);
 
-            }
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.send_message("Bob".into(), "Charlie".into(), MessageType::Text, Vec::new()), Err(Error::ContractPaused));
+            assert_eq!(transmitter.list_username_for_sale("Bob".into(), 10), Err(Error::ContractPaused));
+            assert_eq!(transmitter.transfer_username("Bob".into(), accounts.django), Err(Error::ContractPaused));
 
-            return Ok(());
+            // Settings can still be changed, and balances withdrawn.
+            assert_eq!(transmitter.block_sender("Bob".into(), "Charlie".into()), Ok(()));
+            assert_eq!(transmitter.withdraw_balance(), Err(Error::NoBalance));
+            assert_eq!(transmitter.withdraw(1), Err(Error::InsufficientBalance));
 
+            set_caller(accounts.alice);
+            transmitter.co_unpause().unwrap();
+            send_text(&mut transmitter, accounts.bob, "Bob", "Charlie", "back");
         }
 
-        /// A sale proposition made to you is cancelled.
-        #[ink(message)]
-        pub fn refuse_to_buy(&mut self, username: Username) -> Result<(),Error> {
-            
-            if let None = self.usernames.get(&username) {
-                return Err(Error::NameNonexistent(username));
-            }
+        #[ink::test]
+        fn sale_fee_is_set_in_basis_points() {
 
-            if let Some(sales) = self.sale_offers.get() {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                if let Some(mut sales) = sales {
+            assert_eq!(transmitter.check_sale_fee(), 500);
+            assert_eq!(transmitter.co_set_sale_fee(10_001), Err(Error::InvalidPercentage));
+            transmitter.co_set_sale_fee(250).unwrap();
+            assert!(matches!(last_event(), Event::FeesChanged(event) if event.sale_fee_bps == 250));
+            assert_eq!(transmitter.check_fees().3, 2);
 
-                    let mut sale_pos: Option<usize> = None;
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.co_set_sale_fee(0), Err(Error::NotContractOwner));
 
-                    for (pos,sale) in sales.iter().enumerate() {
+            register(&mut transmitter, accounts.bob, "Bob");
+            transmitter.sell_username_to("Bob".into(), accounts.charlie, 1_000, 1_000).unwrap();
 
-                        if (&sale.username == &username) && (sale.to == self.env().caller()) {
+            set_caller(accounts.charlie);
+            set_value(1_000);
+            transmitter.buy_username("Bob".into()).unwrap();
+            set_value(0);
 
-                            sale_pos = Some(pos);
-                            break;
+            assert_eq!(transmitter.owner.balance, transmitter.check_fee() + 25);
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_balance(), Ok(975));
+        }
 
-                        } 
+        #[ink::test]
+        fn first_registrants_earn_royalties_on_resales() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            assert_eq!(transmitter.co_set_royalty(9_600), Err(Error::InvalidPercentage));
+            transmitter.co_set_royalty(1_000).unwrap();
+            assert_eq!(transmitter.check_royalty(), 1_000);
+            let fee = transmitter.owner.balance;
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            assert_eq!(transmitter.first_registrant_of("Bob".into()), Some(accounts.bob));
+
+            // No royalty is owed when the first registrant sells.
+            transmitter.sell_username_to("Bob".into(), accounts.charlie, 1_000, 1_000).unwrap();
+            set_caller(accounts.charlie);
+            set_value(1_000);
+            transmitter.buy_username("Bob".into()).unwrap();
+            set_value(0);
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_balance(), Ok(950));
+
+            set_caller(accounts.charlie);
+            transmitter.sell_username_to("Bob".into(), accounts.django, 2_000, 1_000).unwrap();
+            set_caller(accounts.django);
+            set_value(2_000);
+            transmitter.buy_username("Bob".into()).unwrap();
+            set_value(0);
+            assert!(matches!(last_event(), Event::SaleCompleted(_)));
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_balance(), Ok(1_150));
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.get_balance(), Ok(1_700));
+            assert_eq!(transmitter.owner.balance, fee + transmitter.check_fee() + 50 + 100);
+            assert_eq!(transmitter.first_registrant_of("Bob".into()), Some(accounts.bob));
+        }
 
-                    }
+        #[ink::test]
+        fn deposits_pay_for_registrations() {
 
-                    if let Some(pos) = sale_pos {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                        sales.remove(pos);
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.deposit(), Err(Error::NoBalance));
 
-                        self.sale_offers.set(&Some(sales));
+            set_value(transmitter.check_fee() * 2);
+            assert_eq!(transmitter.deposit(), Ok(2));
+            assert!(matches!(last_event(), Event::BalanceDeposited(event) if event.account == accounts.bob && event.amount == 2));
+            set_value(0);
 
-                        return Ok(());
+            transmitter.register_username("Bob".into()).unwrap();
+            transmitter.register_username("Bobby".into()).unwrap();
+            assert_eq!(transmitter.get_balance(), Ok(0));
+            assert_eq!(transmitter.owner.balance, 2);
+            assert_eq!(transmitter.total_user_balance, 0);
 
-                    } else {
+            assert!(matches!(transmitter.register_username("Roberto".into()), Err(Error::PaymentFailed { .. })));
+        }
 
-                        return Err(Error::NoSalesForYou);
+        #[ink::test]
+        fn balances_can_be_withdrawn_in_part() {
 
-                    }
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                } else {
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.withdraw(1), Err(Error::InsufficientBalance));
 
-                    return Err(Error::NoSalesForYou);
+            pay(100);
+            transmitter.deposit().unwrap();
+            set_value(0);
 
-                }
+            assert_eq!(transmitter.withdraw(101), Err(Error::InsufficientBalance));
+            assert_eq!(transmitter.withdraw(40), Ok(()));
+            assert!(matches!(last_event(), Event::BalanceWithdrawn(event) if event.account == accounts.bob && event.amount == 40));
+            assert_eq!(transmitter.get_balance(), Ok(60));
+            assert_eq!(transmitter.total_user_balance, 60);
+        }
 
-            } else {
+        #[ink::test]
+        fn allowed_accounts_spend_balances_within_the_cap() {
 
-                return Err(Error::NoSalesForYou);
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-            }
-        }
+            set_caller(accounts.bob);
+            set_value(10);
+            transmitter.deposit().unwrap();
+            set_value(0);
 
-        /// Attempts to close your account. Any remaining balance will be sent back to you.
-        #[ink(message)]
-        pub fn close_account(&mut self) -> Result<(),Error> {
-            if let Some(user_info) = self.users.get(&self.env().caller()) {
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.transfer_internal_from(accounts.bob, accounts.charlie, 1), Err(Error::InsufficientAllowance));
 
-                if user_info.balance > 0 {
+            set_caller(accounts.bob);
+            transmitter.approve_spending(accounts.charlie, 20).unwrap();
+            assert_eq!(transmitter.spending_allowance(accounts.bob, accounts.charlie), 20);
 
-                    if let Err(_) = self.env().transfer(self.env().caller(), user_info.balance) {
+            // The allowance can exceed the balance, but the balance still limits what can be moved.
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.transfer_internal_from(accounts.bob, accounts.charlie, 11), Err(Error::InsufficientBalance));
 
-                        return Err(Error::CloseAccountFailed);
+            transmitter.transfer_internal_from(accounts.bob, accounts.charlie, 4).unwrap();
+            assert!(matches!(last_event(), Event::BalanceTransferred(event) if event.from == accounts.bob && event.amount == 4));
+            assert_eq!(transmitter.spending_allowance(accounts.bob, accounts.charlie), 16);
+            assert_eq!(transmitter.get_balance(), Ok(4));
 
-                    }
+            // The spender can use what it pulled to register a name.
+            transmitter.register_username("Charlie".into()).unwrap();
+            assert_eq!(transmitter.get_balance(), Ok(3));
 
-                }
-            
-                if let Some(usernames) = user_info.usernames {
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_balance(), Ok(6));
+            transmitter.approve_spending(accounts.charlie, 0).unwrap();
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.transfer_internal_from(accounts.bob, accounts.charlie, 1), Err(Error::InsufficientAllowance));
+        }
 
-                    for username in usernames.iter() {
+        #[ink::test]
+        fn buyers_and_sellers_negotiate_sale_prices() {
 
-                        self.usernames.remove(username);
-    
-                    }
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                }
+            register(&mut transmitter, accounts.bob, "Bob");
+            transmitter.sell_username_to("Bob".into(), accounts.charlie, 1_000, 1_000).unwrap();
 
-                self.users.remove(&self.env().caller());
+            // The seller can only counter a counter-offer.
+            assert_eq!(transmitter.counter_offer("Bob".into(), 900), Err(Error::NoCounterOffer));
 
-                return Ok(());
+            set_caller(accounts.django);
+            assert_eq!(transmitter.counter_offer("Bob".into(), 10), Err(Error::NoSalesForYou));
 
-            } else {
+            set_caller(accounts.charlie);
+            transmitter.counter_offer("Bob".into(), 600).unwrap();
+            assert_eq!(transmitter.get_negotiation("Bob".into()), Some(Negotiation { buyer: accounts.charlie, price: 600, by_buyer: true }));
 
-                return Err(Error::NoAccount);
+            set_caller(accounts.bob);
+            transmitter.counter_offer("Bob".into(), 800).unwrap();
+            assert_eq!(transmitter.get_sale("Bob".into()).unwrap().price, 800);
+            assert_eq!(transmitter.accept_counter_offer("Bob".into()), Err(Error::NoCounterOffer));
 
-            }
-        }
+            set_caller(accounts.charlie);
+            transmitter.counter_offer("Bob".into(), 700).unwrap();
 
-        /// Transfers the contract ownership. Can only be called by the current owner.
-        #[ink(message)]
-        pub fn co_transfer_contract_ownership(&mut self, new_owner: AccountId) -> Result<(),Error> {
+            set_caller(accounts.bob);
+            transmitter.reject_counter_offer("Bob".into()).unwrap();
+            assert_eq!(transmitter.get_negotiation("Bob".into()), None);
+            assert_eq!(transmitter.get_sale("Bob".into()).unwrap().price, 800);
 
-            if self.env().caller() == self.owner.account_id {
+            set_caller(accounts.charlie);
+            transmitter.counter_offer("Bob".into(), 750).unwrap();
 
-                self.owner.account_id = new_owner;
+            set_caller(accounts.bob);
+            transmitter.accept_counter_offer("Bob".into()).unwrap();
+            assert!(matches!(last_event(), Event::SaleOffered(event) if event.price == 750));
 
-                return Ok(());
+            set_caller(accounts.charlie);
+            set_value(750);
+            transmitter.buy_username("Bob".into()).unwrap();
+            set_value(0);
+            assert_eq!(transmitter.resolve("Bob".into()), Ok(accounts.charlie));
+        }
 
-            } else {
+        #[ink::test]
+        fn anyone_purges_expired_offers() {
 
-                return Err(Error::NotContractOwner);
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-            }
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.bob, "Bobby");
+            transmitter.sell_username_to("Bob".into(), accounts.charlie, 10, 100).unwrap();
+            transmitter.sell_username_to("Bobby".into(), accounts.charlie, 10, 1_000).unwrap();
 
-        }
+            test::set_block_timestamp::<DefaultEnvironment>(100);
+            set_caller(accounts.django);
+            assert_eq!(transmitter.purge_expired_offers(accounts.charlie), 1);
+            assert_eq!(transmitter.purge_expired_offers(accounts.charlie), 0);
 
-        /// Updated the contract code. Can only be called by the contract owner.
-        #[ink(message)]
-        pub fn co_set_code(&mut self, code_hash: ink::primitives::Hash) -> Result<(),Error> {
+            assert_eq!(transmitter.sales.get(String::from("Bob")), None);
+            assert_eq!(transmitter.sales_to_buyer.get(accounts.charlie), Some(vec!["Bobby".into()]));
+        }
 
-            if self.env().caller() == self.owner.account_id {
+        #[ink::test]
+        fn inbox_summary_is_reported() {
 
-                match self.env().set_code_hash(&code_hash) {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                    Ok(()) => {
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-                        return Ok(());
+            set_caller(accounts.bob);
+            assert_eq!(
+                transmitter.get_inbox_summary("Bob".into()),
+                Ok(InboxSummary { total: 0, unread: 0, oldest_timestamp: None, newest_timestamp: None })
+            );
 
-                    },
-                    Err(_) => {
+            for timestamp in [10, 20, 30] {
+                test::set_block_timestamp::<DefaultEnvironment>(timestamp);
+                send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "hello");
+            }
 
-                        return Err(Error::UpgradeFailed)
+            set_caller(accounts.bob);
+            let hashes: Vec<[u8;32]> = transmitter.get_all_messages("Bob".into()).unwrap().iter().map(|message| message.hash).collect();
+            transmitter.mark_as_read("Bob".into(), hashes[1]).unwrap();
+            transmitter.delete_message("Bob".into(), hashes[2]).unwrap();
 
-                    }
+            assert_eq!(
+                transmitter.get_inbox_summary("Bob".into()),
+                Ok(InboxSummary { total: 2, unread: 1, oldest_timestamp: Some(10), newest_timestamp: Some(20) })
+            );
+            assert_eq!(transmitter.get_message_count("Bob".into()), Ok(2));
 
-                }
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.get_inbox_summary("Bob".into()), Err(Error::WrongAccount("Bob".into())));
+        }
 
+        #[ink::test]
+        fn range_queries_fetch_what_arrived_since_a_sync() {
 
-            } else {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                return Err(Error::NotContractOwner);
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
+            for (timestamp, content) in [(10, "one"), (20, "two"), (20, "three"), (30, "four")] {
+                test::set_block_timestamp::<DefaultEnvironment>(timestamp);
+                send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", content);
             }
 
+            set_caller(accounts.bob);
+            let newer: Vec<Content> = transmitter.get_messages_range("Bob".into(), 20, Timestamp::MAX).unwrap()
+                .into_iter()
+                .map(|message| message.content)
+                .collect();
+            assert_eq!(newer, vec![b"two".to_vec(), b"three".to_vec(), b"four".to_vec()]);
+
+            assert_eq!(transmitter.get_messages_range("Bob".into(), 31, Timestamp::MAX), Ok(Vec::new()));
         }
 
-        /// Sets a new value for the username registration fee. Can only be called by the contract owner.
-        #[ink(message)]
-        pub fn co_set_fee(&mut self, new_fee: Balance) -> Result<(),Error> {
+        #[ink::test]
+        fn range_queries_use_the_delivery_time_of_scheduled_messages() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            test::set_block_timestamp::<DefaultEnvironment>(10);
+            set_caller(accounts.charlie);
+            transmitter.schedule_message("Charlie".into(), "Bob".into(), MessageType::Text, b"later".to_vec(), 100).unwrap();
+            assert_eq!(
+                transmitter.schedule_message("Charlie".into(), "Bob".into(), MessageType::Text, b"too late".to_vec(), 11 + MAX_SCHEDULE_DELAY),
+                Err(Error::DeliveryTooLate)
+            );
+
+            test::set_block_timestamp::<DefaultEnvironment>(20);
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "now");
+
+            set_caller(accounts.bob);
+            let synced: Vec<Content> = transmitter.get_messages_range("Bob".into(), 0, 50).unwrap()
+                .into_iter()
+                .map(|message| message.content)
+                .collect();
+            assert_eq!(synced, vec![b"now".to_vec()]);
+
+            test::set_block_timestamp::<DefaultEnvironment>(100);
+            let newer: Vec<Content> = transmitter.get_messages_range("Bob".into(), 51, Timestamp::MAX).unwrap()
+                .into_iter()
+                .map(|message| message.content)
+                .collect();
+            assert_eq!(newer, vec![b"later".to_vec()]);
+        }
 
-            if self.env().caller() == self.owner.account_id {
+        #[ink::test]
+        fn messages_are_filtered_by_type() {
 
-                self.registration_fee = new_fee;
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                return Ok(());
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-            } else {
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "plain");
+            transmitter.send_message("Charlie".into(), "Bob".into(), MessageType::Json, b"{\"a\":1}".to_vec()).unwrap();
 
-                return Err(Error::NotContractOwner);
+            set_caller(accounts.bob);
+            let json = transmitter.get_messages_of_type("Bob".into(), 1 << MessageType::Json.index()).unwrap();
+            assert_eq!(json.len(), 1);
+            assert_eq!(json[0].mtype, MessageType::Json);
 
-            }
+            let both = 1 << MessageType::Json.index() | 1 << MessageType::Text.index();
+            assert_eq!(transmitter.get_messages_of_type("Bob".into(), both).unwrap().len(), 2);
+            assert_eq!(transmitter.get_messages_of_type("Bob".into(), 0), Ok(Vec::new()));
 
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.get_messages_of_type("Bob".into(), u32::MAX), Err(Error::WrongAccount("Bob".into())));
         }
 
-        #[ink(message)] 
-        pub fn co_get_balance(&self) -> Result<Balance,Error> {
+        #[ink::test]
+        fn messages_are_listed_by_sender() {
 
-            if self.owner.account_id != self.env().caller() {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                return Err(Error::NotContractOwner);
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            register(&mut transmitter, accounts.django, "Django");
 
-            }
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "one");
+            send_text(&mut transmitter, accounts.django, "Django", "Bob", "hi");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "two");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "three");
 
-            return Ok(self.owner.balance);
-        }
+            set_caller(accounts.bob);
+            let contents = |messages: Vec<Message>| messages.into_iter().map(|message| message.content).collect::<Vec<Content>>();
 
-        /// Attempts to withdraw a specific amount from the owner's balance. Can only be called by the contract owner.
-        #[ink(message)]
-        pub fn co_withdraw_amount(&mut self, balance: Balance) -> Result<(),Error> {
+            assert_eq!(contents(transmitter.get_messages_from("Bob".into(), "Charlie".into(), 0, 10).unwrap()), vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]);
+            assert_eq!(contents(transmitter.get_messages_from("Bob".into(), "Charlie".into(), 1, 1).unwrap()), vec![b"two".to_vec()]);
+            assert_eq!(contents(transmitter.get_messages_from("Bob".into(), "Django".into(), 0, 10).unwrap()), vec![b"hi".to_vec()]);
 
-            if self.owner.account_id == self.env().caller() {
+            // Deleted messages are left out but keep their place.
+            let first = transmitter.get_messages_from("Bob".into(), "Charlie".into(), 0, 1).unwrap()[0].hash;
+            transmitter.delete_message("Bob".into(), first).unwrap();
+            assert_eq!(contents(transmitter.get_messages_from("Bob".into(), "Charlie".into(), 0, 2).unwrap()), vec![b"two".to_vec()]);
 
-                if self.owner.balance < balance {
+            set_caller(accounts.charlie);
+            assert_eq!(transmitter.get_messages_from("Bob".into(), "Charlie".into(), 0, 10), Err(Error::WrongAccount("Bob".into())));
+        }
 
-                    return Err(Error::NotEnoughBalance);
+        #[ink::test]
+        fn messages_from_a_sender_start_over_when_a_name_is_registered_again() {
 
-                }
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                if let Err(_) = self.env().transfer(self.owner.account_id, balance) {
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-                    return Err(Error::WithdrawFailed);
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "old");
 
-                } else {
+            set_caller(accounts.bob);
+            transmitter.close_account().unwrap();
+            register(&mut transmitter, accounts.bob, "Bob");
 
-                    self.owner.balance -= balance;
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "new");
 
-                    return Ok(());
+            set_caller(accounts.bob);
+            let messages = transmitter.get_messages_from("Bob".into(), "Charlie".into(), 0, 10).unwrap();
+            assert_eq!(messages.into_iter().map(|message| message.content).collect::<Vec<Content>>(), vec![b"new".to_vec()]);
+        }
 
-                }
+        #[ink::test]
+        fn scheduled_messages_stay_out_of_reach_until_delivered() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            test::set_block_timestamp::<DefaultEnvironment>(0);
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "now");
+            let events_before = test::recorded_events().count();
+            let later = transmitter.schedule_message("Charlie".into(), "Bob".into(), MessageType::Text, b"later".to_vec(), 1_000).unwrap();
+            assert_eq!(test::recorded_events().count(), events_before);
+
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_unread_count("Bob".into()), Ok(1));
+            assert_eq!(transmitter.get_inbox_summary("Bob".into()).map(|summary| (summary.total, summary.unread)), Ok((1, 1)));
+            assert_eq!(transmitter.mark_as_read("Bob".into(), later), Err(Error::MessageNonexistent));
+            assert_eq!(transmitter.forward_message("Bob".into(), later, "Charlie".into()), Err(Error::MessageNonexistent));
+            assert_eq!(transmitter.delete_message("Bob".into(), later), Err(Error::MessageNonexistent));
+            assert_eq!(transmitter.delete_message_at("Bob".into(), 1), Err(Error::MessageNonexistent));
+            assert_eq!(transmitter.mark_all_read("Bob".into()), Ok(1));
+            assert_eq!(transmitter.announce_scheduled("Bob".into()), Ok(0));
+
+            test::set_block_timestamp::<DefaultEnvironment>(1_000);
+            assert_eq!(transmitter.get_unread_count("Bob".into()), Ok(1));
+            assert_eq!(transmitter.announce_scheduled("Bob".into()), Ok(1));
+            assert!(matches!(last_event(), Event::MessageSent(MessageSent { message_hash, .. }) if message_hash == later));
+            assert_eq!(transmitter.announce_scheduled("Bob".into()), Ok(0));
+
+            transmitter.delete_message_at("Bob".into(), 1).unwrap();
+            assert_eq!(transmitter.get_message_count("Bob".into()), Ok(1));
+        }
 
-            } else {
+        #[ink::test]
+        fn released_names_get_a_new_first_registrant() {
 
-                return Err(Error::NotContractOwner);
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-            }
+            register(&mut transmitter, accounts.bob, "Bob");
+
+            set_caller(accounts.bob);
+            transmitter.close_account().unwrap();
+            assert_eq!(transmitter.first_registrant_of("Bob".into()), None);
 
+            register(&mut transmitter, accounts.charlie, "Bob");
+            assert_eq!(transmitter.first_registrant_of("Bob".into()), Some(accounts.charlie));
         }
 
+        #[ink::test]
+        fn frozen_names_cannot_edit_react_or_post() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+            send_text(&mut transmitter, accounts.charlie, "Charlie", "Bob", "spam");
+
+            set_caller(accounts.bob);
+            let hash = transmitter.get_all_messages("Bob".into()).unwrap()[0].hash;
+            transmitter.create_channel("rust".into()).unwrap();
+            set_caller(accounts.charlie);
+            transmitter.join_channel("rust".into()).unwrap();
+
+            set_caller(accounts.alice);
+            transmitter.mod_freeze_username("Charlie".into(), true).unwrap();
+
+            set_caller(accounts.charlie);
+            assert_eq!(
+                transmitter.edit_message("Charlie".into(), "Bob".into(), hash, b"ham".to_vec()),
+                Err(Error::UsernameFrozen("Charlie".into()))
+            );
+            assert_eq!(transmitter.react_to_message("Charlie".into(), hash, "!".into()), Err(Error::UsernameFrozen("Charlie".into())));
+            assert_eq!(
+                transmitter.post_to_channel("rust".into(), "Charlie".into(), b"spam".to_vec()),
+                Err(Error::UsernameFrozen("Charlie".into()))
+            );
+            assert_eq!(transmitter.get_channel_messages("rust".into(), 0, 10).map(|posts| posts.len()), Ok(0));
+
+            // The freeze does not outlive the name.
+            transmitter.close_account().unwrap();
+            assert!(!transmitter.is_frozen("Charlie".into()));
+        }
 
-        /// Withdraw the balance stored. Can only be called by the contract owner.
-        #[ink(message)]
-        pub fn co_withdraw_all_balance(&mut self) -> Result<(),Error> {
+        #[ink::test]
+        fn edits_drop_the_signature_and_stay_with_the_sender() {
 
-            if self.owner.account_id == self.env().caller() {
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                if self.owner.balance > 0 {
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
 
-                    if let Err(_) = self.env().transfer(self.owner.account_id, self.owner.balance) {
+            test::set_block_timestamp::<DefaultEnvironment>(100);
+            set_caller(accounts.charlie);
+            transmitter.send_message_signed("Charlie".into(), "Bob".into(), MessageType::Text, b"signed".to_vec(), [7u8;64]).unwrap();
 
-                        return Err(Error::WithdrawFailed);
+            set_caller(accounts.bob);
+            let hash = transmitter.get_all_messages("Bob".into()).unwrap()[0].hash;
 
-                    } else {
+            set_caller(accounts.charlie);
+            let hash = transmitter.edit_message("Charlie".into(), "Bob".into(), hash, b"changed".to_vec()).unwrap();
 
-                        self.owner.balance = 0;
+            set_caller(accounts.bob);
+            assert_eq!(transmitter.get_all_messages("Bob".into()).unwrap()[0].signature, None);
 
-                        return Ok(());
+            test::set_block_timestamp::<DefaultEnvironment>(200);
+            set_caller(accounts.charlie);
+            transmitter.transfer_username("Charlie".into(), accounts.django).unwrap();
 
-                    }
+            set_caller(accounts.django);
+            assert_eq!(
+                transmitter.edit_message("Charlie".into(), "Bob".into(), hash, b"forged".to_vec()),
+                Err(Error::WrongAccount("Charlie".into()))
+            );
+        }
 
-                } else {
+        #[ink::test]
+        fn turning_expiry_on_spares_existing_names() {
 
-                    return Err(Error::NoBalance);
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-                }
+            test::set_block_timestamp::<DefaultEnvironment>(0);
+            register(&mut transmitter, accounts.bob, "Bob");
 
-            } else {
+            test::set_block_timestamp::<DefaultEnvironment>(10_000);
+            set_caller(accounts.alice);
+            transmitter.co_set_expiry_periods(1_000, 500).unwrap();
 
-                return Err(Error::NotContractOwner);
-            }
+            assert_eq!(transmitter.get_expiry("Bob".into()), Ok(Some((11_000, 11_500))));
+            assert!(!transmitter.is_in_grace("Bob".into()));
+            assert_eq!(transmitter.resolve("Bob".into()), Ok(accounts.bob));
 
+            test::set_block_timestamp::<DefaultEnvironment>(11_500);
+            assert!(transmitter.resolve("Bob".into()).is_err());
         }
 
-    }
+        #[ink::test]
+        fn listings_end_when_names_change_hands() {
 
+            let accounts = accounts();
+            let mut transmitter = deploy();
 
-    // WARNING: Tests are incomplete. I chose to test manually using 'contracts-ui.substrate.io'
+            register(&mut transmitter, accounts.bob, "Bob");
 
-    #[cfg(test)]
-    mod tests {
+            set_caller(accounts.bob);
+            transmitter.list_username_for_sale("Bob".into(), 100).unwrap();
+            transmitter.close_account().unwrap();
+            assert_eq!(transmitter.get_open_listings(0, 10), Vec::new());
 
-        // use super::*;
+            register(&mut transmitter, accounts.charlie, "Bob");
 
-        /// We test a simple use case of our contract.
-        #[ink::test]
-        fn it_works() {
+            set_caller(accounts.django);
+            set_value(100);
+            assert_eq!(transmitter.buy_listed_username("Bob".into()), Err(Error::UsernameNotInSale));
+            set_value(0);
+            assert_eq!(transmitter.resolve("Bob".into()), Ok(accounts.charlie));
+        }
 
-            
+        #[ink::test]
+        fn deposits_pay_for_renewals_purchases_and_bids() {
+
+            let accounts = accounts();
+            let mut transmitter = deploy();
+
+            register(&mut transmitter, accounts.bob, "Bob");
+            register(&mut transmitter, accounts.charlie, "Charlie");
+
+            set_caller(accounts.alice);
+            transmitter.co_set_fees(None, None, Some(5), None).unwrap();
+
+            set_caller(accounts.django);
+            set_value(1_000);
+            transmitter.deposit().unwrap();
+            set_value(0);
+            assert_eq!(transmitter.register_usernames(vec!["Django".into()]), Ok(()));
+            transmitter.renew_username("Django".into()).unwrap();
+
+            set_caller(accounts.bob);
+            transmitter.list_username_for_sale("Bob".into(), 100).unwrap();
+            set_caller(accounts.charlie);
+            transmitter.start_auction("Charlie".into(), 50, 1_000).unwrap();
+
+            set_caller(accounts.django);
+            transmitter.buy_listed_username("Bob".into()).unwrap();
+            transmitter.place_bid("Charlie".into()).unwrap();
+            assert_eq!(transmitter.get_balance(), Ok(1_000 - 1 - 5 - 100 - 50));
+
+            // Nothing can be deposited while the contract is paused.
+            set_caller(accounts.alice);
+            transmitter.co_pause().unwrap();
+            set_value(1);
+            assert_eq!(transmitter.deposit(), Err(Error::ContractPaused));
         }
 
     }
@@ -1672,19 +11674,23 @@ mod transmitter {
             }
 
 
-            // Alice decides she would like to sell the username to Bob.
+            // Alice decides she would like to sell the username to Bob. She looks up the account holding his name first.
+
+            let resolve = build_message::<TransmitterRef>(contract_account_id.clone())
+                .call(|transmitter| transmitter.resolve("Bob".into()));
 
-            // let make_sale_proposition = sell_username_to!("Bob_resembles_a_sponge",bob,100);
+            let bob_account_id = call_run!(alice: resolve, pay 0).expect("Error w/ 'resolve' (alice).").return_value().expect("Bob's name should resolve.");
 
-            // let make_sale_proposition_result = call_run!(alice: make_sale_proposition, pay 0);
+            let make_sale_proposition = build_message::<TransmitterRef>(contract_account_id.clone())
+                .call(|transmitter| transmitter.sell_username_to("Bob_resembles_a_sponge".into(), bob_account_id, 100, 3_600_000));
 
-            // if let Err(e) = make_sale_proposition_result.expect("Error w/ 'make_sale_proposition' (alice).").return_value() {
+            let make_sale_proposition_result = call_run!(alice: make_sale_proposition, pay 0);
 
-            //     panic!("{:?}",e);
+            if let Err(e) = make_sale_proposition_result.expect("Error w/ 'make_sale_proposition' (alice).").return_value() {
 
-            // }
+                panic!("{:?}",e);
 
-            // --> UNFORTUNATELY DON'T KNOW HOW TO FIND BOB's ACCOUNT_ID!!!!
+            }
 
 
             // Bob has heard from Alice that she has a username to sell to him.